@@ -51,6 +51,18 @@ impl ChunkerType {
         }
     }
 
+    /// Smallest and largest chunk the chunker is configured to emit. The last
+    /// chunk of a work unit may still be shorter than the lower bound.
+    pub fn getBounds(&self) -> (usize, usize) {
+        let size = self.getSize();
+        match self.getScheme() {
+            ChunkingScheme::FILE => (0, usize::MAX),
+            ChunkingScheme::STATIC => (size, size),
+            // Mirrors the limits RabinChunker derives from the average size.
+            ChunkingScheme::CONTENT => (size / 4, size * 4),
+        }
+    }
+
     pub fn getScheme(&self) -> ChunkingScheme {
         match self {
             ChunkerType::FILE => ChunkingScheme::FILE,
@@ -1,5 +1,5 @@
 use crate::chunker::chunker::Chunker;
-use fastcdc::FastCDC;
+use fastcdc::v2020::FastCDC;
 
 pub struct RabinChunker {
     min_size: usize,
@@ -78,4 +78,31 @@ mod test {
             "Chunked data doesn't match input length"
         );
     }
+
+    #[test]
+    fn testRabinChunkerBounds() {
+        // Pseudo-random data so that content-defined cut points actually occur.
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let data: Vec<u8> = (0..1 << 20)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let chunkerType = ChunkerType::CDC4K;
+        let (minSize, maxSize) = chunkerType.getBounds();
+        let chunks: Vec<&[u8]> = ChunkFactory::new(chunkerType)
+            .createChunker()
+            .chunk(&data)
+            .collect();
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= minSize && chunk.len() <= maxSize);
+        }
+        assert!(chunks.last().unwrap().len() <= maxSize);
+    }
 }
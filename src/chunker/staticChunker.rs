@@ -41,9 +41,7 @@ mod tests {
     fn createTestData(N: usize) -> Mmap {
         let mut file = tempfile().unwrap();
         file.write_all(&vec![0u8; N * 1024]).unwrap();
-        let mmap = unsafe { Mmap::map(&file).unwrap() };
-
-        mmap
+        unsafe { Mmap::map(&file).unwrap() }
     }
 
     #[test]
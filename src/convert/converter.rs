@@ -0,0 +1,714 @@
+use crate::merge::merger::dedupAgain;
+use crate::parse::popularity::hexDigest;
+use crate::trace::resemblance::{Features, FEATURE_COUNT};
+use crate::trace::sampling::MAX_SAMPLE_BITS;
+use crate::util::arguments::{ConvertArgs, ConvertFormat};
+use crate::util::csv::csvField;
+use crate::util::json::{parseObject, JsonValue};
+use crate::util::traceFile::{
+    ChunkRecord, FileRecord, Trace, TraceHeader, TraceSummary, TraceWriter, CHUNK_COMPRESSED,
+    CHUNK_DUPLICATE, CHUNK_FEATURES, CHUNK_REPEATED, CHUNK_ZERO,
+};
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Tables a trace is converted to, one file each.
+const TABLES: [&str; 3] = ["trace", "files", "chunks"];
+
+type Row = Vec<(&'static str, JsonValue)>;
+
+/// Paths are bytes. Valid UTF-8 is kept, other bytes are written as `\xNN`
+/// and backslashes as `\\`, so the exact path can be restored.
+pub fn escapePath(path: &[u8]) -> String {
+    let mut result = String::with_capacity(path.len());
+    for chunk in path.utf8_chunks() {
+        result.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for b in chunk.invalid() {
+            result.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    result
+}
+
+pub fn unescapePath(path: &str) -> Result<Vec<u8>, String> {
+    let mut result = Vec::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        result.extend_from_slice(&rest.as_bytes()[..i]);
+        rest = &rest[i + 1..];
+        if let Some(tail) = rest.strip_prefix('\\') {
+            result.push(b'\\');
+            rest = tail;
+        } else {
+            let byte = rest
+                .strip_prefix('x')
+                .and_then(|tail| tail.get(..2))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("invalid escape in path {:?}", path))?;
+            result.push(byte);
+            rest = &rest[3..];
+        }
+    }
+    result.extend_from_slice(rest.as_bytes());
+    Ok(result)
+}
+
+fn number(value: impl ToString) -> JsonValue {
+    JsonValue::NUMBER(value.to_string())
+}
+
+fn text(value: impl Into<String>) -> JsonValue {
+    JsonValue::STRING(value.into())
+}
+
+fn traceRow(trace: &Trace) -> Row {
+    let h = &trace.header;
+    let mut row = vec![
+        ("toolVersion", text(&h.toolVersion)),
+        ("chunker", text(&h.chunker)),
+        ("hasher", text(&h.hasher)),
+        ("digestLength", number(h.digestLength)),
+        ("saltVerifier", text(hexDigest(&h.saltVerifier))),
+        ("startTime", number(h.startTime)),
+        ("compression", text(&h.compression)),
+        ("compressSampleRate", number(h.compressSampleRate)),
+        ("sampleBits", number(h.sampleBits)),
+        ("sketchOnly", JsonValue::BOOL(h.sketchOnly)),
+    ];
+    let s = trace.summary.as_ref();
+    let value = |get: fn(&TraceSummary) -> u64| s.map_or(JsonValue::NULL, |s| number(get(s)));
+    row.extend([
+        ("endTime", value(|s| s.endTime)),
+        ("fileCount", value(|s| s.fileCount)),
+        ("totalSize", value(|s| s.totalSize)),
+        ("chunkCount", value(|s| s.chunkCount)),
+        ("dupCount", value(|s| s.dupCount)),
+        ("dupSize", value(|s| s.dupSize)),
+    ]);
+    row
+}
+
+fn fileRow(header: &TraceHeader, f: &FileRecord) -> Row {
+    vec![
+        ("fileIndex", number(f.fileIndex)),
+        ("path", text(escapePath(&f.path))),
+        ("size", number(f.size)),
+        ("chunkCount", number(f.chunkCount)),
+        ("dupCount", number(f.dupCount)),
+        ("dupSize", number(f.dupSize)),
+        ("internalDupSize", number(f.internalDupSize)),
+        ("fileType", text(&f.fileType)),
+        ("entropy", f.entropy.map_or(JsonValue::NULL, number)),
+        (
+            "digest",
+            f.digest.map_or(JsonValue::NULL, |d| {
+                text(hexDigest(&d[..header.digestLength]))
+            }),
+        ),
+    ]
+}
+
+fn chunkRow(header: &TraceHeader, c: &ChunkRecord) -> Row {
+    let compressed = c.flags & CHUNK_COMPRESSED != 0;
+    vec![
+        ("fileIndex", number(c.fileIndex)),
+        ("offset", number(c.offset)),
+        ("length", number(c.length)),
+        ("digest", text(hexDigest(&c.digest[..header.digestLength]))),
+        ("duplicate", JsonValue::BOOL(c.flags & CHUNK_DUPLICATE != 0)),
+        ("zero", JsonValue::BOOL(c.flags & CHUNK_ZERO != 0)),
+        ("repeated", JsonValue::BOOL(c.flags & CHUNK_REPEATED != 0)),
+        (
+            "compressedSize",
+            if compressed {
+                number(c.compressedSize)
+            } else {
+                JsonValue::NULL
+            },
+        ),
+        (
+            "features",
+            c.features.as_ref().map_or(JsonValue::NULL, |features| {
+                JsonValue::ARRAY(features.iter().map(|&f| number(f)).collect())
+            }),
+        ),
+    ]
+}
+
+/// Writes the rows of one table as JSON Lines or CSV.
+struct Table<'a> {
+    out: &'a mut dyn Write,
+    format: ConvertFormat,
+}
+
+impl<'a> Table<'a> {
+    /// Start a table; a CSV table starts with the names of the columns.
+    fn new(out: &'a mut dyn Write, format: ConvertFormat, columns: &Row) -> io::Result<Self> {
+        if format == ConvertFormat::CSV {
+            let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+            writeln!(out, "{}", names.join(","))?;
+        }
+        Ok(Self { out, format })
+    }
+
+    fn write(&mut self, row: &Row) -> io::Result<()> {
+        let fields: Vec<String> = match self.format {
+            ConvertFormat::CSV => row
+                .iter()
+                .map(|(_, value)| match value {
+                    JsonValue::NULL => String::new(),
+                    JsonValue::STRING(s) => csvField(s),
+                    // Lists are separated by spaces to stay in one column.
+                    JsonValue::ARRAY(values) => {
+                        let values: Vec<String> = values.iter().map(JsonValue::toJson).collect();
+                        values.join(" ")
+                    }
+                    value => value.toJson(),
+                })
+                .collect(),
+            _ => row
+                .iter()
+                .map(|(name, value)| format!("\"{}\": {}", name, value.toJson()))
+                .collect(),
+        };
+        match self.format {
+            ConvertFormat::CSV => writeln!(self.out, "{}", fields.join(",")),
+            _ => writeln!(self.out, "{{{}}}", fields.join(", ")),
+        }
+    }
+}
+
+/// Write the trace, file and chunk tables of a trace.
+pub fn writeTables(
+    trace: &Trace,
+    format: ConvertFormat,
+    [traceOut, filesOut, chunksOut]: [&mut dyn Write; 3],
+) -> io::Result<()> {
+    let header = &trace.header;
+    let row = traceRow(trace);
+    Table::new(traceOut, format, &row)?.write(&row)?;
+
+    let mut files = Table::new(filesOut, format, &fileRow(header, &FileRecord::default()))?;
+    for f in &trace.files {
+        files.write(&fileRow(header, f))?;
+    }
+
+    let mut chunks = Table::new(
+        chunksOut,
+        format,
+        &chunkRow(header, &ChunkRecord::default()),
+    )?;
+    for c in &trace.chunks {
+        chunks.write(&chunkRow(header, c))?;
+    }
+    Ok(())
+}
+
+/// One line of a JSON Lines table, with its position for error messages.
+struct Line {
+    table: &'static str,
+    number: usize,
+    values: HashMap<String, JsonValue>,
+}
+
+impl Line {
+    fn error(&self, message: String) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}.jsonl line {}: {}", self.table, self.number, message),
+        )
+    }
+
+    fn value(&self, key: &str) -> Option<&JsonValue> {
+        self.values.get(key).filter(|v| **v != JsonValue::NULL)
+    }
+
+    fn number<T: FromStr>(&self, key: &str) -> io::Result<Option<T>> {
+        match self.value(key) {
+            None => Ok(None),
+            Some(JsonValue::NUMBER(n)) => n
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(format!("\"{}\" is out of range: {}", key, n))),
+            Some(_) => Err(self.error(format!("\"{}\" must be a number", key))),
+        }
+    }
+
+    fn required<T: FromStr>(&self, key: &str) -> io::Result<T> {
+        self.number(key)?
+            .ok_or_else(|| self.error(format!("\"{}\" is missing", key)))
+    }
+
+    fn string(&self, key: &str) -> io::Result<Option<&str>> {
+        match self.value(key) {
+            None => Ok(None),
+            Some(JsonValue::STRING(s)) => Ok(Some(s)),
+            Some(_) => Err(self.error(format!("\"{}\" must be a string", key))),
+        }
+    }
+
+    fn flag(&self, key: &str) -> io::Result<bool> {
+        match self.value(key) {
+            None => Ok(false),
+            Some(JsonValue::BOOL(b)) => Ok(*b),
+            Some(_) => Err(self.error(format!("\"{}\" must be true or false", key))),
+        }
+    }
+
+    /// A hex digest of the trace's digest length, padded to 32 bytes.
+    fn digest(&self, key: &str, digestLength: usize) -> io::Result<Option<[u8; 32]>> {
+        let Some(hex) = self.string(key)? else {
+            return Ok(None);
+        };
+        let bytes = parseHex(hex)
+            .filter(|bytes| bytes.len() == digestLength)
+            .ok_or_else(|| {
+                self.error(format!(
+                    "\"{}\" must be {} hex digits, not {:?}",
+                    key,
+                    2 * digestLength,
+                    hex
+                ))
+            })?;
+        let mut digest = [0u8; 32];
+        digest[..digestLength].copy_from_slice(&bytes);
+        Ok(Some(digest))
+    }
+}
+
+fn parseHex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn readLines(table: &'static str, input: impl BufRead) -> io::Result<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (i, text) in input.lines().enumerate() {
+        let text = text?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let values = parseObject(&text).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}.jsonl line {}: {}", table, i + 1, e),
+            )
+        })?;
+        lines.push(Line {
+            table,
+            number: i + 1,
+            values,
+        });
+    }
+    Ok(lines)
+}
+
+fn readHeader(line: &Line) -> io::Result<TraceHeader> {
+    let header = TraceHeader {
+        toolVersion: line.string("toolVersion")?.unwrap_or("unknown").to_string(),
+        chunker: line
+            .string("chunker")?
+            .ok_or_else(|| line.error("\"chunker\" is missing".to_string()))?
+            .to_string(),
+        hasher: line
+            .string("hasher")?
+            .ok_or_else(|| line.error("\"hasher\" is missing".to_string()))?
+            .to_string(),
+        digestLength: line.required("digestLength")?,
+        startTime: line.number("startTime")?.unwrap_or(0),
+        compression: line.string("compression")?.unwrap_or("none").to_string(),
+        compressSampleRate: line.number("compressSampleRate")?.unwrap_or(1),
+        sampleBits: line.number("sampleBits")?.unwrap_or(0),
+        sketchOnly: line.flag("sketchOnly")?,
+        saltVerifier: parseHex(line.string("saltVerifier")?.unwrap_or(""))
+            .ok_or_else(|| line.error("\"saltVerifier\" must be hex digits".to_string()))?,
+    };
+    if header.digestLength == 0 || header.digestLength > 32 {
+        return Err(line.error("\"digestLength\" must be between 1 and 32".to_string()));
+    }
+    if header.sampleBits > MAX_SAMPLE_BITS {
+        return Err(line.error(format!(
+            "\"sampleBits\" must be at most {}",
+            MAX_SAMPLE_BITS
+        )));
+    }
+    if header.compressSampleRate == 0 {
+        return Err(line.error("\"compressSampleRate\" must be at least 1".to_string()));
+    }
+    Ok(header)
+}
+
+fn readFile(header: &TraceHeader, line: &Line) -> io::Result<FileRecord> {
+    let path = line
+        .string("path")?
+        .ok_or_else(|| line.error("\"path\" is missing".to_string()))?;
+    Ok(FileRecord {
+        fileIndex: line.required("fileIndex")?,
+        path: unescapePath(path).map_err(|e| line.error(e))?,
+        size: line.required("size")?,
+        chunkCount: line.number("chunkCount")?.unwrap_or(0),
+        dupCount: line.number("dupCount")?.unwrap_or(0),
+        dupSize: line.number("dupSize")?.unwrap_or(0),
+        internalDupSize: line.number("internalDupSize")?.unwrap_or(0),
+        fileType: line.string("fileType")?.unwrap_or("unknown").to_string(),
+        entropy: line.number("entropy")?,
+        digest: line.digest("digest", header.digestLength)?,
+    })
+}
+
+fn readChunk(header: &TraceHeader, line: &Line) -> io::Result<ChunkRecord> {
+    let mut c = ChunkRecord {
+        fileIndex: line.required("fileIndex")?,
+        offset: line.required("offset")?,
+        length: line.required("length")?,
+        digest: line
+            .digest("digest", header.digestLength)?
+            .ok_or_else(|| line.error("\"digest\" is missing".to_string()))?,
+        ..Default::default()
+    };
+    for (key, flag) in [("zero", CHUNK_ZERO), ("repeated", CHUNK_REPEATED)] {
+        if line.flag(key)? {
+            c.flags |= flag;
+        }
+    }
+    if let Some(size) = line.number("compressedSize")? {
+        c.flags |= CHUNK_COMPRESSED;
+        c.compressedSize = size;
+    }
+    match line.value("features") {
+        None => {}
+        Some(JsonValue::ARRAY(values)) if values.len() == FEATURE_COUNT => {
+            let mut features = Box::<Features>::default();
+            for (f, value) in features.iter_mut().zip(values) {
+                *f = match value {
+                    JsonValue::NUMBER(n) => n.parse().ok(),
+                    _ => None,
+                }
+                .ok_or_else(|| line.error("\"features\" must be 32-bit numbers".to_string()))?;
+            }
+            c.flags |= CHUNK_FEATURES;
+            c.features = Some(features);
+        }
+        Some(_) => {
+            return Err(line.error(format!(
+                "\"features\" must be a list of {} numbers",
+                FEATURE_COUNT
+            )));
+        }
+    }
+    Ok(c)
+}
+
+/// Build a trace from JSON Lines tables, e.g. written by another tool. Only
+/// the trace line and the files are required. Duplicates are found again
+/// from the chunk records in file and offset order, and totals the trace
+/// line leaves out are added up from the file records.
+pub fn readTables(
+    traceIn: impl BufRead,
+    filesIn: impl BufRead,
+    chunksIn: Option<impl BufRead>,
+) -> io::Result<Trace> {
+    let traceLines = readLines("trace", traceIn)?;
+    let [line] = &traceLines[..] else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "trace.jsonl must hold exactly one line",
+        ));
+    };
+    let header = readHeader(line)?;
+
+    let mut files = Vec::new();
+    for l in readLines("files", filesIn)? {
+        files.push(readFile(&header, &l)?);
+    }
+    files.sort_by_key(|f| f.fileIndex);
+    if let Some(w) = files.windows(2).find(|w| w[0].fileIndex == w[1].fileIndex) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("files.jsonl: file index {} is used twice", w[0].fileIndex),
+        ));
+    }
+
+    let mut chunks = Vec::new();
+    if let Some(chunksIn) = chunksIn {
+        for l in readLines("chunks", chunksIn)? {
+            let c = readChunk(&header, &l)?;
+            if files
+                .binary_search_by_key(&c.fileIndex, |f| f.fileIndex)
+                .is_err()
+            {
+                return Err(l.error(format!("no file with index {}", c.fileIndex)));
+            }
+            chunks.push(c);
+        }
+    }
+    chunks.sort_by_key(|c| (c.fileIndex, c.offset));
+
+    // File records are scaled up from the sample; the totals are not.
+    let sampleBits = header.sampleBits;
+    let sum = |get: fn(&FileRecord) -> u64| files.iter().map(get).sum::<u64>() >> sampleBits;
+    let mut summary = TraceSummary {
+        fileCount: files.len() as u64,
+        totalSize: files.iter().map(|f| f.size).sum(),
+        chunkCount: line.number("chunkCount")?.unwrap_or(sum(|f| f.chunkCount)),
+        dupCount: line.number("dupCount")?.unwrap_or(sum(|f| f.dupCount)),
+        dupSize: line.number("dupSize")?.unwrap_or(sum(|f| f.dupSize)),
+        endTime: line.number("endTime")?.unwrap_or(header.startTime),
+        ..Default::default()
+    };
+    let mut trace = Trace {
+        header,
+        files,
+        chunks,
+        ..Default::default()
+    };
+    if !trace.chunks.is_empty() && !trace.header.sketchOnly {
+        dedupAgain(&mut trace, &mut summary);
+    }
+    trace.summary = Some(summary);
+    Ok(trace)
+}
+
+fn tablePath(dir: &Path, table: &str, format: ConvertFormat) -> PathBuf {
+    let extension = match format {
+        ConvertFormat::CSV => "csv",
+        _ => "jsonl",
+    };
+    dir.join(format!("{}.{}", table, extension))
+}
+
+pub fn run(args: &ConvertArgs) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    match args.to {
+        ConvertFormat::JSONL | ConvertFormat::CSV => {
+            let trace = Trace::read(&args.input)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", args.input.display(), e)))?;
+            fs::create_dir_all(&args.output)?;
+            let mut tables = Vec::with_capacity(TABLES.len());
+            for table in TABLES {
+                let path = tablePath(&args.output, table, args.to);
+                tables.push(BufWriter::new(File::create(path)?));
+            }
+            let [traceOut, filesOut, chunksOut] = &mut tables[..] else {
+                unreachable!()
+            };
+            writeTables(&trace, args.to, [traceOut, filesOut, chunksOut])?;
+            for table in &mut tables {
+                table.flush()?;
+            }
+            writeln!(
+                out,
+                "Wrote {} files and {} chunks to {}.",
+                trace.files.len(),
+                trace.chunks.len(),
+                args.output.display()
+            )
+        }
+        ConvertFormat::TRACE => {
+            let open = |table: &str| {
+                let path = tablePath(&args.input, table, ConvertFormat::JSONL);
+                File::open(&path)
+                    .map(BufReader::new)
+                    .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+            };
+            let chunksIn = match open("chunks") {
+                Ok(input) => Some(input),
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            let trace = readTables(open("trace")?, open("files")?, chunksIn)?;
+
+            let mut writer = TraceWriter::create(&args.output, trace.header.clone())?;
+            for file in &trace.files {
+                let chunks = trace.chunksOf(file.fileIndex);
+                if !chunks.is_empty() {
+                    writer.writeChunks(file.fileIndex, chunks)?;
+                }
+                writer.writeFile(file)?;
+            }
+            writer.finish(trace.summary.as_ref().unwrap())?;
+            writeln!(
+                out,
+                "Wrote {} files and {} chunks to {}.",
+                trace.files.len(),
+                trace.chunks.len(),
+                args.output.display()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn createTrace() -> Trace {
+        let header = TraceHeader {
+            toolVersion: "0.1.3".to_string(),
+            chunker: "CDC8K".to_string(),
+            hasher: "MD5".to_string(),
+            digestLength: 16,
+            startTime: 100,
+            compression: "lz4".to_string(),
+            compressSampleRate: 1,
+            saltVerifier: vec![0xab; 8],
+            ..Default::default()
+        };
+        let digest = |id: u8| std::array::from_fn(|i| if i < 16 { id } else { 0 });
+        let files = vec![
+            FileRecord {
+                fileIndex: 0,
+                path: b"/data/a,\"b\"\\\xff".to_vec(),
+                size: 8192,
+                chunkCount: 2,
+                dupCount: 1,
+                dupSize: 4096,
+                internalDupSize: 4096,
+                fileType: "text".to_string(),
+                entropy: Some(0.1),
+                digest: Some(digest(9)),
+            },
+            FileRecord {
+                fileIndex: 1,
+                path: b"/data/empty".to_vec(),
+                fileType: "empty".to_string(),
+                ..Default::default()
+            },
+        ];
+        let chunks = vec![
+            ChunkRecord {
+                fileIndex: 0,
+                offset: 0,
+                length: 4096,
+                flags: CHUNK_ZERO | CHUNK_COMPRESSED | CHUNK_FEATURES,
+                digest: digest(1),
+                compressedSize: 12,
+                features: Some(Box::new(std::array::from_fn(|i| i as u32))),
+            },
+            ChunkRecord {
+                fileIndex: 0,
+                offset: 4096,
+                length: 4096,
+                flags: CHUNK_ZERO | CHUNK_DUPLICATE,
+                digest: digest(1),
+                ..Default::default()
+            },
+        ];
+        Trace {
+            header,
+            files,
+            chunks,
+            summary: Some(TraceSummary {
+                fileCount: 2,
+                totalSize: 8192,
+                chunkCount: 2,
+                dupCount: 1,
+                dupSize: 4096,
+                endTime: 160,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn tables(trace: &Trace, format: ConvertFormat) -> [Vec<u8>; 3] {
+        let [mut a, mut b, mut c] = [Vec::new(), Vec::new(), Vec::new()];
+        writeTables(trace, format, [&mut a, &mut b, &mut c]).unwrap();
+        [a, b, c]
+    }
+
+    #[test]
+    fn testEscapePath() {
+        let path = b"/a\\b/\xff\xfe/\xc3\xa9";
+        let escaped = escapePath(path);
+        assert_eq!(escaped, "/a\\\\b/\\xff\\xfe/é");
+        assert_eq!(unescapePath(&escaped).unwrap(), path);
+        assert!(unescapePath("/a\\q").is_err());
+        assert!(unescapePath("/a\\x4").is_err());
+    }
+
+    #[test]
+    fn testWriteCsv() {
+        let [traceCsv, filesCsv, chunksCsv] = tables(&createTrace(), ConvertFormat::CSV);
+        let traceCsv = String::from_utf8(traceCsv).unwrap();
+        assert!(traceCsv.starts_with("toolVersion,chunker,hasher,digestLength,saltVerifier,"));
+        assert!(traceCsv.ends_with(
+            "\n0.1.3,CDC8K,MD5,16,abababababababab,100,lz4,1,0,false,160,2,8192,2,1,4096\n"
+        ));
+        let filesCsv = String::from_utf8(filesCsv).unwrap();
+        assert!(filesCsv.contains(
+            "\n0,\"/data/a,\"\"b\"\"\\\\\\xff\",8192,2,1,4096,4096,text,0.1,09090909090909090909090909090909\n"
+        ));
+        assert!(filesCsv.ends_with("\n1,/data/empty,0,0,0,0,0,empty,,\n"));
+        let chunksCsv = String::from_utf8(chunksCsv).unwrap();
+        assert!(chunksCsv.starts_with(
+            "fileIndex,offset,length,digest,duplicate,zero,repeated,compressedSize,features\n"
+        ));
+        assert!(chunksCsv.contains(",false,true,false,12,0 1 2 3 4 5 6 7 8 9 10 11\n"));
+    }
+
+    #[test]
+    fn testJsonlRoundTrip() {
+        let trace = createTrace();
+        let [traceJsonl, filesJsonl, chunksJsonl] = tables(&trace, ConvertFormat::JSONL);
+        let text = String::from_utf8(filesJsonl.clone()).unwrap();
+        assert!(text.starts_with(
+            "{\"fileIndex\": 0, \"path\": \"/data/a,\\\"b\\\"\\\\\\\\\\\\xff\", \"size\": 8192,"
+        ));
+
+        let converted = readTables(
+            traceJsonl.as_slice(),
+            filesJsonl.as_slice(),
+            Some(chunksJsonl.as_slice()),
+        )
+        .unwrap();
+        assert_eq!(converted.header, trace.header);
+        assert_eq!(converted.files, trace.files);
+        assert_eq!(converted.chunks, trace.chunks);
+        let summary = converted.summary.unwrap();
+        assert_eq!(summary.dupSize, 4096);
+        assert_eq!(summary.endTime, 160);
+        assert_eq!(summary.patterns.zero.dupSize, 4096);
+
+        // Another tool only needs to give the essentials.
+        let minimal = readTables(
+            "{\"chunker\": \"CDC8K\", \"hasher\": \"SHA1\", \"digestLength\": 20}".as_bytes(),
+            "{\"fileIndex\": 3, \"path\": \"/x\", \"size\": 10}\n\n".as_bytes(),
+            None::<&[u8]>,
+        )
+        .unwrap();
+        assert_eq!(minimal.files[0].path, b"/x");
+        assert_eq!(minimal.summary.unwrap().totalSize, 10);
+
+        let err = readTables(
+            traceJsonl.as_slice(),
+            "{\"fileIndex\": 0, \"path\": \"/x\"}".as_bytes(),
+            None::<&[u8]>,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "files.jsonl line 1: \"size\" is missing");
+
+        let err = readTables(
+            "{\"chunker\": \"CDC8K\", \"hasher\": \"SHA1\", \"digestLength\": 20, \"sampleBits\": 70}"
+                .as_bytes(),
+            "".as_bytes(),
+            None::<&[u8]>,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "trace.jsonl line 1: \"sampleBits\" must be at most 24"
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::parse::churn::{compareTraces, ChurnRow, Tally};
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::{DiffArgs, ReportFormat};
+use crate::util::json::jsonString;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Error, ErrorKind, Write},
+    path::Path,
+};
+
+/// Unique chunks of two generations, scaled up from a sampled trace.
+#[derive(Debug, Default, PartialEq)]
+pub struct ChunkDiff {
+    /// Unique chunks of the new trace the old one did not have.
+    pub added: Tally,
+    /// Unique chunks of the old trace the new one no longer references.
+    pub released: Tally,
+    /// Unique chunks both traces reference.
+    pub retained: Tally,
+}
+
+impl ChunkDiff {
+    pub fn oldSize(&self) -> u64 {
+        self.retained.size + self.released.size
+    }
+
+    pub fn newSize(&self) -> u64 {
+        self.retained.size + self.added.size
+    }
+
+    /// Change of the unique data footprint from the old to the new trace.
+    pub fn netChange(&self) -> i64 {
+        self.newSize() as i64 - self.oldSize() as i64
+    }
+}
+
+fn uniqueChunks(trace: &Trace) -> HashMap<[u8; 32], u32> {
+    trace.chunks.iter().map(|c| (c.digest, c.length)).collect()
+}
+
+/// Compare the unique chunks of two traces. Returns why they cannot be
+/// compared if they lack chunk records or were traced differently.
+pub fn diffChunks(old: &Trace, new: &Trace) -> Result<ChunkDiff, &'static str> {
+    if old.chunks.is_empty() || new.chunks.is_empty() {
+        return Err("needs chunk records, trace with --log-fingerprints");
+    }
+    if old.header.chunker != new.header.chunker {
+        return Err("the traces use different chunkers");
+    }
+    if old.header.hasher != new.header.hasher {
+        return Err("the traces use different hash functions");
+    }
+    if old.header.saltVerifier != new.header.saltVerifier {
+        return Err("the traces use different --salt");
+    }
+    if old.header.sampleBits != new.header.sampleBits {
+        return Err("the traces use different --sample-bits");
+    }
+
+    let scale = 1u64 << old.header.sampleBits;
+    let oldChunks = uniqueChunks(old);
+    let newChunks = uniqueChunks(new);
+    let mut diff = ChunkDiff::default();
+    for (digest, &length) in &newChunks {
+        if oldChunks.contains_key(digest) {
+            diff.retained.add(length as u64);
+        } else {
+            diff.added.add(length as u64);
+        }
+    }
+    for (digest, &length) in &oldChunks {
+        if !newChunks.contains_key(digest) {
+            diff.released.add(length as u64);
+        }
+    }
+    for count in [&mut diff.added, &mut diff.released, &mut diff.retained] {
+        count.count *= scale;
+        count.size *= scale;
+    }
+    Ok(diff)
+}
+
+fn fmtSignedSize(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, fmtSize(bytes.unsigned_abs() as usize))
+}
+
+/// Whether modifications that keep the size and renames can be detected.
+fn hasDigests(trace: &Trace) -> bool {
+    trace
+        .files
+        .iter()
+        .all(|f| f.size == 0 || f.digest.is_some())
+}
+
+fn compareFiles(old: &Trace, new: &Trace) -> io::Result<ChurnRow> {
+    compareTraces(old, new).map_err(|reason| Error::new(ErrorKind::InvalidInput, reason))
+}
+
+fn fileCategories(files: &ChurnRow) -> [(&'static str, Tally); 6] {
+    [
+        ("unchanged", files.unchanged),
+        ("renamed", files.renamed),
+        ("moved", files.moved),
+        ("modified", files.modified),
+        ("added", files.added),
+        ("deleted", files.deleted),
+    ]
+}
+
+pub fn writeText(
+    out: &mut dyn Write,
+    (oldPath, old): (&Path, &Trace),
+    (newPath, new): (&Path, &Trace),
+) -> io::Result<()> {
+    let files = compareFiles(old, new)?;
+    writeln!(
+        out,
+        "Diff of {} -> {}:",
+        oldPath.display(),
+        newPath.display()
+    )?;
+    if !hasDigests(old) || !hasDigests(new) {
+        writeln!(
+            out,
+            "  no whole-file digests, files are compared by path and size only"
+        )?;
+    }
+    writeln!(out, "  {:<20}  {:>10}  {:>10}", "files", "count", "size")?;
+    for (label, count) in fileCategories(&files) {
+        writeln!(
+            out,
+            "  {:<20}  {:>10}  {:>10}",
+            label,
+            count.count,
+            fmtSize(count.size as usize)
+        )?;
+    }
+
+    let chunks = match diffChunks(old, new) {
+        Ok(chunks) => chunks,
+        Err(reason) => {
+            writeln!(out, "  unique chunks: {}", reason)?;
+            return writeln!(out);
+        }
+    };
+    writeln!(
+        out,
+        "  {:<20}  {:>10}  {:>10}",
+        "unique chunks", "count", "size"
+    )?;
+    for (label, count) in [
+        ("new", chunks.added),
+        ("no longer referenced", chunks.released),
+        ("still referenced", chunks.retained),
+    ] {
+        writeln!(
+            out,
+            "  {:<20}  {:>10}  {:>10}",
+            label,
+            count.count,
+            fmtSize(count.size as usize)
+        )?;
+    }
+    writeln!(
+        out,
+        "  unique data: {} -> {} ({})",
+        fmtSize(chunks.oldSize() as usize),
+        fmtSize(chunks.newSize() as usize),
+        fmtSignedSize(chunks.netChange())
+    )?;
+    writeln!(out)
+}
+
+fn jsonCount(count: Tally) -> String {
+    format!("{{\"count\": {}, \"bytes\": {}}}", count.count, count.size)
+}
+
+pub fn writeJson(
+    out: &mut dyn Write,
+    (oldPath, old): (&Path, &Trace),
+    (newPath, new): (&Path, &Trace),
+) -> io::Result<()> {
+    let files = compareFiles(old, new)?;
+    writeln!(out, "{{")?;
+    writeln!(
+        out,
+        "  \"old\": {},",
+        jsonString(&oldPath.to_string_lossy())
+    )?;
+    writeln!(
+        out,
+        "  \"new\": {},",
+        jsonString(&newPath.to_string_lossy())
+    )?;
+    writeln!(
+        out,
+        "  \"fileDigests\": {},",
+        hasDigests(old) && hasDigests(new)
+    )?;
+    let files: Vec<String> = fileCategories(&files)
+        .iter()
+        .map(|(label, count)| format!("\"{}\": {}", label, jsonCount(*count)))
+        .collect();
+    writeln!(out, "  \"files\": {{{}}},", files.join(", "))?;
+    match diffChunks(old, new) {
+        Ok(chunks) => writeln!(
+            out,
+            "  \"chunks\": {{\"new\": {}, \"released\": {}, \"retained\": {}, \"oldBytes\": {}, \"newBytes\": {}, \"netChange\": {}}}",
+            jsonCount(chunks.added),
+            jsonCount(chunks.released),
+            jsonCount(chunks.retained),
+            chunks.oldSize(),
+            chunks.newSize(),
+            chunks.netChange()
+        )?,
+        Err(reason) => writeln!(
+            out,
+            "  \"chunks\": null, \"chunksMissing\": {}",
+            jsonString(reason)
+        )?,
+    }
+    writeln!(out, "}}")
+}
+
+pub fn run(args: &DiffArgs) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let read = |path: &Path| {
+        Trace::read(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    };
+    let old = read(&args.oldTrace)?;
+    let new = read(&args.newTrace)?;
+
+    let old = (args.oldTrace.as_path(), &old);
+    let new = (args.newTrace.as_path(), &new);
+    match args.format {
+        ReportFormat::TEXT => writeText(&mut out, old, new)?,
+        ReportFormat::JSON => writeJson(&mut out, old, new)?,
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, FileRecord, TraceHeader};
+
+    /// One file per entry, of 1 KiB chunks with the given ids.
+    fn createTrace(files: &[(&str, &[u8])]) -> Trace {
+        let mut trace = Trace {
+            header: TraceHeader {
+                hasher: "BLAKE3".to_string(),
+                digestLength: 32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (fileIndex, (path, ids)) in files.iter().enumerate() {
+            trace.files.push(FileRecord {
+                fileIndex: fileIndex as u32,
+                path: path.as_bytes().to_vec(),
+                size: ids.len() as u64 * 1024,
+                digest: Some(*blake3::hash(ids).as_bytes()),
+                ..Default::default()
+            });
+            for &id in *ids {
+                trace.chunks.push(ChunkRecord {
+                    fileIndex: fileIndex as u32,
+                    length: 1024,
+                    digest: [id; 32],
+                    ..Default::default()
+                });
+            }
+        }
+        trace
+    }
+
+    fn createTraces() -> (Trace, Trace) {
+        let old = createTrace(&[("/a", &[1, 2, 3]), ("/b", &[4, 5]), ("/c", &[6])]);
+        let new = createTrace(&[("/a", &[1, 2, 3]), ("/b", &[4, 7, 7]), ("/d", &[8, 9])]);
+        (old, new)
+    }
+
+    #[test]
+    fn testDiffChunks() {
+        let (old, new) = createTraces();
+        let diff = diffChunks(&old, &new).unwrap();
+        assert_eq!(
+            diff.added,
+            Tally {
+                count: 3,
+                size: 3072
+            }
+        );
+        assert_eq!(
+            diff.released,
+            Tally {
+                count: 2,
+                size: 2048
+            }
+        );
+        assert_eq!(
+            diff.retained,
+            Tally {
+                count: 4,
+                size: 4096
+            }
+        );
+        assert_eq!(diff.netChange(), 1024);
+
+        let (_, mut sampled) = createTraces();
+        sampled.header.sampleBits = 2;
+        assert!(diffChunks(&old, &sampled).is_err());
+        let (_, mut rechunked) = createTraces();
+        rechunked.header.chunker = "CDC4K".to_string();
+        assert!(diffChunks(&old, &rechunked).is_err());
+        let (_, mut salted) = createTraces();
+        salted.header.saltVerifier = vec![1, 2, 3, 4];
+        assert_eq!(
+            diffChunks(&old, &salted),
+            Err("the traces use different --salt")
+        );
+        assert!(diffChunks(&old, &Trace::default()).is_err());
+    }
+
+    #[test]
+    fn testWriteText() {
+        let (old, new) = createTraces();
+        let mut out = Vec::new();
+        writeText(&mut out, (Path::new("old"), &old), (Path::new("new"), &new)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("Diff of old -> new:\n"));
+        assert!(text.contains("  modified                       1       3 KiB\n"));
+        assert!(text.contains("  deleted                        1       1 KiB\n"));
+        assert!(text.contains("  no longer referenced           2       2 KiB\n"));
+        assert!(text.contains("  unique data: 6 KiB -> 7 KiB (+1 KiB)\n"));
+
+        // Whole-file digests of different hash functions never match.
+        let (_, mut rehashed) = createTraces();
+        rehashed.header.hasher = "SHA256".to_string();
+        let mut out = Vec::new();
+        let result = writeText(
+            &mut out,
+            (Path::new("old"), &old),
+            (Path::new("new"), &rehashed),
+        );
+        assert!(result.is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn testWriteJson() {
+        let (old, mut new) = createTraces();
+        new.chunks.clear();
+        let mut out = Vec::new();
+        writeJson(&mut out, (Path::new("old"), &old), (Path::new("new"), &new)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\"fileDigests\": true,"));
+        assert!(text.contains("\"unchanged\": {\"count\": 1, \"bytes\": 3072}"));
+        assert!(text.contains("\"added\": {\"count\": 1, \"bytes\": 2048}"));
+        assert!(text.contains("\"chunks\": null, \"chunksMissing\": \"needs chunk records"));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::parse::popularity::hexDigest;
+use crate::parse::retention::civilDate;
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::InspectArgs;
+use crate::util::traceFile::Trace;
+
+use std::{
+    io::{self, Error, Write},
+    path::Path,
+};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// A Unix time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn fmtTime(seconds: u64) -> String {
+    let (year, month, day) = civilDate((seconds / SECONDS_PER_DAY) as i64);
+    let time = seconds % SECONDS_PER_DAY;
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Print the header of a trace and what its records contain.
+pub fn writeInspection(out: &mut dyn Write, path: &Path, trace: &Trace) -> io::Result<()> {
+    let h = &trace.header;
+    let row = |out: &mut dyn Write, label: &str, value: String| {
+        writeln!(out, "  {:<15}  {}", label, value)
+    };
+
+    writeln!(out, "Trace {}", path.display())?;
+    row(out, "format version", trace.version.to_string())?;
+    row(out, "written by", format!("chunkIQ {}", h.toolVersion))?;
+    row(out, "chunker", h.chunker.clone())?;
+    row(
+        out,
+        "hasher",
+        format!("{} ({} byte digests)", h.hasher, h.digestLength),
+    )?;
+    row(
+        out,
+        "salt verifier",
+        if h.saltVerifier.is_empty() {
+            "none, unsalted".to_string()
+        } else {
+            hexDigest(&h.saltVerifier)
+        },
+    )?;
+    row(
+        out,
+        "sampling",
+        match h.sampleBits {
+            0 => "all chunks".to_string(),
+            bits => format!("1 in {} chunks (--sample-bits {})", 1u64 << bits, bits),
+        },
+    )?;
+    row(
+        out,
+        "compression",
+        match h.compressSampleRate {
+            _ if h.compression == "none" => "none".to_string(),
+            1 => format!("{}, every unique chunk", h.compression),
+            rate => format!("{}, 1 in {} unique chunks", h.compression, rate),
+        },
+    )?;
+
+    let summary = trace.summary.as_ref();
+    let sketch = summary.and_then(|s| s.sketch.as_ref());
+    row(
+        out,
+        "sketch",
+        match sketch {
+            Some(sketch) if h.sketchOnly => format!(
+                "{} HyperLogLog, no duplicate index (--sketch-only)",
+                fmtSize(sketch.registers().len())
+            ),
+            Some(sketch) => format!("{} HyperLogLog", fmtSize(sketch.registers().len())),
+            None => "none".to_string(),
+        },
+    )?;
+    row(out, "started", fmtTime(h.startTime))?;
+    row(
+        out,
+        "finished",
+        match summary {
+            Some(s) => fmtTime(s.endTime),
+            None => "unknown, no summary record".to_string(),
+        },
+    )?;
+
+    let files = trace.files.len();
+    let chunks = trace.chunks.len();
+    match summary {
+        Some(s) => {
+            row(
+                out,
+                "files",
+                format!(
+                    "{} records, {} in the summary, {}",
+                    files,
+                    s.fileCount,
+                    fmtSize(s.totalSize as usize)
+                ),
+            )?;
+            row(
+                out,
+                "chunks",
+                format!("{} records, {} in the summary", chunks, s.chunkCount),
+            )?;
+        }
+        None => {
+            row(out, "files", format!("{} records", files))?;
+            row(out, "chunks", format!("{} records", chunks))?;
+        }
+    }
+    if chunks == 0 && files > 0 {
+        writeln!(
+            out,
+            "  (no chunk records, the trace was written without --log-fingerprints)"
+        )?;
+    }
+    writeln!(out)
+}
+
+pub fn run(args: &InspectArgs) -> io::Result<()> {
+    let trace = Trace::read(&args.traceFile)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", args.traceFile.display(), e)))?;
+    let mut out = io::stdout().lock();
+    writeInspection(&mut out, &args.traceFile, &trace)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{TraceHeader, TraceSummary};
+
+    #[test]
+    fn testFmtTime() {
+        assert_eq!(fmtTime(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(fmtTime(1_700_000_000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn testWriteInspection() {
+        let trace = Trace {
+            version: 2,
+            header: TraceHeader {
+                toolVersion: "0.1.3".to_string(),
+                chunker: "CDC8K".to_string(),
+                hasher: "SHA256".to_string(),
+                digestLength: 32,
+                compression: "zstd:3".to_string(),
+                compressSampleRate: 4,
+                sampleBits: 3,
+                saltVerifier: vec![0xab, 0xcd],
+                ..Default::default()
+            },
+            summary: Some(TraceSummary {
+                fileCount: 2,
+                chunkCount: 10,
+                endTime: 60,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        writeInspection(&mut out, Path::new("t.trace"), &trace).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("Trace t.trace\n  format version   2\n"));
+        assert!(text.contains("  hasher           SHA256 (32 byte digests)\n"));
+        assert!(text.contains("  salt verifier    abcd\n"));
+        assert!(text.contains("  sampling         1 in 8 chunks (--sample-bits 3)\n"));
+        assert!(text.contains("  compression      zstd:3, 1 in 4 unique chunks\n"));
+        assert!(text.contains("  finished         1970-01-01 00:01:00 UTC\n"));
+        assert!(text.contains("  chunks           0 records, 10 in the summary\n"));
+    }
+
+    #[test]
+    fn testWriteInspectionOfUnfinishedTrace() {
+        let trace = Trace::default();
+        let mut out = Vec::new();
+        writeInspection(&mut out, Path::new("t.trace"), &trace).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("  salt verifier    none, unsalted\n"));
+        assert!(text.contains("  finished         unknown, no summary record\n"));
+        assert!(text.contains("  files            0 records\n"));
+    }
+}
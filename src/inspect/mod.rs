@@ -0,0 +1,2 @@
+pub mod inspector;
+pub mod verifier;
@@ -0,0 +1,88 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::VerifyArgs;
+use crate::util::traceFile::{verifyTrace, Verification};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Error, ErrorKind, Write},
+    path::Path,
+};
+
+pub fn writeVerification(
+    out: &mut dyn Write,
+    path: &Path,
+    verification: &Verification,
+) -> io::Result<()> {
+    let status = if verification.isValid() {
+        "OK"
+    } else {
+        "FAILED"
+    };
+    writeln!(out, "{}: {}", path.display(), status)?;
+    writeln!(
+        out,
+        "  format version {}, {} records, {}",
+        verification.version,
+        verification.records,
+        fmtSize(verification.validBytes as usize)
+    )?;
+    if !verification.checksummed && verification.version > 0 {
+        writeln!(
+            out,
+            "  written before record checksums, only the structure was checked"
+        )?;
+    }
+    for problem in &verification.problems {
+        writeln!(out, "  {}", problem)?;
+    }
+    Ok(())
+}
+
+pub fn run(args: &VerifyArgs) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let mut failed = 0;
+    for path in &args.fileNames {
+        let file = File::open(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let verification = verifyTrace(BufReader::new(file));
+        writeVerification(&mut out, path, &verification)?;
+        failed += usize::from(!verification.isValid());
+    }
+    out.flush()?;
+
+    if failed > 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} of {} traces failed verification",
+                failed,
+                args.fileNames.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testWriteVerification() {
+        let verification = Verification {
+            version: 1,
+            records: 3,
+            validBytes: 2048,
+            complete: false,
+            problems: vec!["no summary record, the tracer did not finish".to_string()],
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        writeVerification(&mut out, Path::new("t.trace"), &verification).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "t.trace: FAILED\n  format version 1, 3 records, 2 KiB\n  written before record checksums, only the structure was checked\n  no summary record, the tracer did not finish\n"
+        );
+    }
+}
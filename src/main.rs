@@ -1,6 +1,15 @@
-#![allow(non_snake_case)]
+#![allow(
+    non_snake_case,
+    clippy::module_inception,
+    clippy::too_many_arguments,
+    clippy::upper_case_acronyms
+)]
 
 mod chunker;
+mod convert;
+mod diff;
+mod inspect;
+mod merge;
 mod parse;
 mod trace;
 mod tui;
@@ -8,6 +17,10 @@ mod util;
 
 use crate::util::arguments::{Cli, Commands};
 use clap::Parser;
+use convert::converter;
+use diff::differ;
+use inspect::{inspector, verifier};
+use merge::merger;
 use parse::parser;
 use trace::tracer;
 
@@ -21,7 +34,9 @@ fn main() {
                 return;
             }
 
-            parser::run(&args);
+            if let Err(e) = parser::run(&args) {
+                eprintln!("[Error] {}", e);
+            }
         }
 
         Commands::Trace(mut args) => {
@@ -32,10 +47,70 @@ fn main() {
 
             if let Err(e) = tracer::run(&args) {
                 eprintln!("[Error] {}", e);
-                return;
+                std::process::exit(1);
             }
             // let (numTasks, receiver, isDone, file_stats_map) = tracer::run(&args).unwrap();
             // initAndRunTrace(numTasks, receiver, isDone, file_stats_map);
         }
+
+        Commands::Diff(args) => {
+            if let Err(e) = args.validate() {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+
+            if let Err(e) = differ::run(&args) {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Merge(args) => {
+            if let Err(e) = args.validate() {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+
+            if let Err(e) = merger::run(&args) {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Inspect(args) => {
+            if let Err(e) = args.validate() {
+                eprintln!("[Error] {}", e);
+                return;
+            }
+
+            if let Err(e) = inspector::run(&args) {
+                eprintln!("[Error] {}", e);
+            }
+        }
+
+        Commands::Verify(args) => {
+            // Scripts check traces by the exit status.
+            if let Err(e) = args.validate() {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+
+            if let Err(e) = verifier::run(&args) {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Convert(args) => {
+            if let Err(e) = args.validate() {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+
+            if let Err(e) = converter::run(&args) {
+                eprintln!("[Error] {}", e);
+                std::process::exit(1);
+            }
+        }
     }
 }
@@ -0,0 +1,515 @@
+use crate::trace::pattern::ChunkPattern;
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::MergeArgs;
+use crate::util::traceFile::{
+    ChunkRecord, FileRecord, Trace, TraceHeader, TraceSummary, TraceWriter, CHUNK_COMPRESSED,
+    CHUNK_DUPLICATE, CHUNK_FEATURES, CHUNK_REPEATED, CHUNK_ZERO,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Error, ErrorKind, Write},
+    path::Path,
+};
+
+/// Why a trace cannot be merged into traces with the header `first`, if it
+/// cannot. Chunks are only comparable if they were cut, hashed and sampled
+/// the same way.
+pub fn incompatibility(first: &TraceHeader, other: &TraceHeader) -> Option<&'static str> {
+    if other.chunker != first.chunker {
+        Some("different chunker")
+    } else if other.hasher != first.hasher || other.digestLength != first.digestLength {
+        Some("different hash function")
+    } else if other.saltVerifier != first.saltVerifier {
+        Some("different --salt")
+    } else if other.sampleBits != first.sampleBits {
+        Some("different --sample-bits")
+    } else if other.compression != first.compression
+        || other.compressSampleRate != first.compressSampleRate
+    {
+        Some("different --compression")
+    } else if other.sketchOnly != first.sketchOnly {
+        Some("different --sketch-only")
+    } else {
+        None
+    }
+}
+
+/// Prefixes for the paths of the traces, like a host name in `host:path`:
+/// their file names without extension, or the paths as given if two file
+/// names are the same.
+fn pathPrefixes(traces: &[(&Path, Trace)]) -> io::Result<Vec<Vec<u8>>> {
+    let stems: Vec<&Path> = traces
+        .iter()
+        .map(|(path, _)| path.file_stem().map_or(*path, Path::new))
+        .collect();
+    let names = if isDistinct(&stems) {
+        stems
+    } else {
+        traces.iter().map(|(path, _)| *path).collect()
+    };
+    if !isDistinct(&names) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Cannot prefix paths: a trace is given twice",
+        ));
+    }
+    Ok(names
+        .iter()
+        .map(|name| [name.as_os_str().as_encoded_bytes(), b":"].concat())
+        .collect())
+}
+
+fn isDistinct(names: &[&Path]) -> bool {
+    let mut sorted = names.to_vec();
+    sorted.sort();
+    sorted.windows(2).all(|w| w[0] != w[1])
+}
+
+/// Combine traces into one. File indexes are renumbered in input order. With
+/// chunk records, duplicates are found again across all traces so the merged
+/// trace counts what a single trace of all files would have counted; without
+/// them, the per-trace counts are added up.
+pub fn merge(traces: &[(&Path, Trace)], prefixPaths: bool) -> io::Result<Trace> {
+    let Some((_, first)) = traces.first() else {
+        return Err(Error::new(ErrorKind::InvalidInput, "No traces to merge"));
+    };
+    for (path, trace) in traces {
+        // The totals of a trace that was cut off do not match its records.
+        if trace.summary.is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Cannot merge {}: no summary record, the tracer did not finish",
+                    path.display()
+                ),
+            ));
+        }
+        if let Some(reason) = incompatibility(&first.header, &trace.header) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot merge {}: {}", path.display(), reason),
+            ));
+        }
+    }
+    let withChunks = traces.iter().filter(|(_, t)| !t.chunks.is_empty()).count();
+    if withChunks != 0 && withChunks != traces.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Cannot merge traces with and without chunk records",
+        ));
+    }
+
+    let mut merged = Trace {
+        header: TraceHeader {
+            toolVersion: env!("CARGO_PKG_VERSION").to_string(),
+            startTime: traces
+                .iter()
+                .map(|(_, t)| t.header.startTime)
+                .min()
+                .unwrap(),
+            ..first.header.clone()
+        },
+        ..Default::default()
+    };
+    let mut summary = TraceSummary::default();
+    let mut sketch = first.summary.as_ref().and_then(|s| s.sketch.clone());
+    let prefixes = if prefixPaths {
+        pathPrefixes(traces)?
+    } else {
+        vec![Vec::new(); traces.len()]
+    };
+
+    for ((path, trace), prefix) in traces.iter().zip(&prefixes) {
+        // File indexes of a trace need not be dense, so they are mapped one by one.
+        let mut fileIndexes: HashMap<u32, u32> = HashMap::new();
+        for file in &trace.files {
+            let fileIndex = merged.files.len() as u32;
+            fileIndexes.insert(file.fileIndex, fileIndex);
+            merged.files.push(FileRecord {
+                fileIndex,
+                path: [&prefix[..], &file.path].concat(),
+                ..file.clone()
+            });
+        }
+        for c in &trace.chunks {
+            let Some(&fileIndex) = fileIndexes.get(&c.fileIndex) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{}: chunk records of file {} without a file record",
+                        path.display(),
+                        c.fileIndex
+                    ),
+                ));
+            };
+            merged.chunks.push(ChunkRecord {
+                fileIndex,
+                ..c.clone()
+            });
+        }
+
+        let s = trace.summary.as_ref().unwrap();
+        summary.fileCount += s.fileCount;
+        summary.totalSize += s.totalSize;
+        summary.chunkCount += s.chunkCount;
+        summary.dupCount += s.dupCount;
+        summary.dupSize += s.dupSize;
+        summary.endTime = summary.endTime.max(s.endTime);
+        summary.uniqueSizes.merge(&s.uniqueSizes);
+        summary.dupSizes.merge(&s.dupSizes);
+        summary.patterns.merge(&s.patterns);
+        summary.compressedCount += s.compressedCount;
+        summary.compressInputSize += s.compressInputSize;
+        summary.compressOutputSize += s.compressOutputSize;
+
+        // The union of the sketches is exact; it needs all of them.
+        sketch = match (sketch, s.sketch.as_ref()) {
+            (Some(mut merged), Some(other)) => merged.merge(other).then_some(merged),
+            _ => None,
+        };
+    }
+    summary.sketch = sketch;
+
+    if withChunks > 0 && !merged.header.sketchOnly {
+        dedupAgain(&mut merged, &mut summary);
+    }
+    merged.summary = Some(summary);
+    Ok(merged)
+}
+
+/// Replay the chunk records of a trace through one index, as the tracer would
+/// have, and recount duplicates per chunk, per file and in the summary. The
+/// compressed size and features of a chunk move to its first occurrence if
+/// that lacks them.
+pub fn dedupAgain(trace: &mut Trace, summary: &mut TraceSummary) {
+    summary.dupCount = 0;
+    summary.dupSize = 0;
+    summary.uniqueSizes = Default::default();
+    summary.dupSizes = Default::default();
+    summary.patterns = Default::default();
+    summary.compressedCount = 0;
+    summary.compressInputSize = 0;
+    summary.compressOutputSize = 0;
+    for file in &mut trace.files {
+        file.dupCount = 0;
+        file.dupSize = 0;
+        file.internalDupSize = 0;
+    }
+    let files: HashMap<u32, usize> = trace
+        .files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.fileIndex, i))
+        .collect();
+
+    // Per-file counts stand for the whole file, the summary for the sample.
+    let scale = 1u64 << trace.header.sampleBits;
+    // Record of the first occurrence of every chunk.
+    let mut firstChunk: HashMap<[u8; 32], usize> = HashMap::new();
+    // Fingerprints seen in every file, as the tracer remembers them.
+    let mut seenInFiles: HashSet<(u32, [u8; 32])> = HashSet::new();
+    for n in 0..trace.chunks.len() {
+        let c = &trace.chunks[n];
+        let length = c.length as u64;
+        let seenInFile = !seenInFiles.insert((c.fileIndex, c.digest));
+        let isDuplicate = match firstChunk.get(&c.digest) {
+            Some(&first) => {
+                if let Some(&i) = files.get(&c.fileIndex) {
+                    let file = &mut trace.files[i];
+                    file.dupCount += scale;
+                    file.dupSize += length * scale;
+                    if seenInFile {
+                        file.internalDupSize += length * scale;
+                    }
+                }
+                moveDetails(&mut trace.chunks, n, first, summary);
+                true
+            }
+            None => {
+                firstChunk.insert(c.digest, n);
+                false
+            }
+        };
+
+        let c = &mut trace.chunks[n];
+        if isDuplicate {
+            summary.dupCount += 1;
+            summary.dupSize += length;
+            summary.dupSizes.record(length);
+            c.flags |= CHUNK_DUPLICATE;
+        } else {
+            summary.uniqueSizes.record(length);
+            c.flags &= !CHUNK_DUPLICATE;
+            if c.flags & CHUNK_COMPRESSED != 0 {
+                summary.compressedCount += 1;
+                summary.compressInputSize += length;
+                summary.compressOutputSize += c.compressedSize as u64;
+            }
+        }
+
+        let pattern = if c.flags & CHUNK_ZERO != 0 {
+            ChunkPattern::ZERO
+        } else if c.flags & CHUNK_REPEATED != 0 {
+            ChunkPattern::REPEATED
+        } else {
+            ChunkPattern::ORDINARY
+        };
+        summary
+            .patterns
+            .record(pattern, c.length as usize, isDuplicate);
+    }
+}
+
+/// Take the compressed size and features off the duplicate chunk record
+/// `from`, since only unique chunks carry them, and give them to the first
+/// occurrence `to` if it has none.
+fn moveDetails(chunks: &mut [ChunkRecord], from: usize, to: usize, summary: &mut TraceSummary) {
+    let duplicate = &mut chunks[from];
+    let flags = duplicate.flags;
+    let compressedSize = std::mem::take(&mut duplicate.compressedSize);
+    let features = duplicate.features.take();
+    duplicate.flags &= !(CHUNK_COMPRESSED | CHUNK_FEATURES);
+
+    let first = &mut chunks[to];
+    if flags & CHUNK_COMPRESSED != 0 && first.flags & CHUNK_COMPRESSED == 0 {
+        first.flags |= CHUNK_COMPRESSED;
+        first.compressedSize = compressedSize;
+        summary.compressedCount += 1;
+        summary.compressInputSize += first.length as u64;
+        summary.compressOutputSize += compressedSize as u64;
+    }
+    if flags & CHUNK_FEATURES != 0 && first.flags & CHUNK_FEATURES == 0 {
+        first.flags |= CHUNK_FEATURES;
+        first.features = features;
+    }
+}
+
+pub fn run(args: &MergeArgs) -> io::Result<()> {
+    let mut traces = Vec::with_capacity(args.fileNames.len());
+    for path in &args.fileNames {
+        let trace = Trace::read(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        traces.push((path.as_path(), trace));
+    }
+    let merged = merge(&traces, args.prefixPaths)?;
+
+    let mut writer = TraceWriter::create(&args.output, merged.header.clone())?;
+    for file in merged.files.iter() {
+        let chunks = merged.chunksOf(file.fileIndex);
+        if !chunks.is_empty() {
+            writer.writeChunks(file.fileIndex, chunks)?;
+        }
+        writer.writeFile(file)?;
+    }
+    let summary = merged.summary.unwrap_or_default();
+    writer.finish(&summary)?;
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "Merged {} traces, {} files ({}), into {}.",
+        traces.len(),
+        merged.files.len(),
+        fmtSize(summary.totalSize as usize),
+        args.output.display()
+    )?;
+    if merged.chunks.is_empty() {
+        writeln!(
+            out,
+            "Without chunk records, duplicates across traces are not counted; trace with --log-fingerprints."
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn createTrace(path: &str, ids: &[u8]) -> Trace {
+        let mut summary = TraceSummary {
+            fileCount: 1,
+            totalSize: ids.len() as u64 * 1024,
+            chunkCount: ids.len() as u64,
+            ..Default::default()
+        };
+        let mut chunks = Vec::new();
+        let mut seen = Vec::new();
+        for (i, &id) in ids.iter().enumerate() {
+            let isDuplicate = seen.contains(&id);
+            seen.push(id);
+            if isDuplicate {
+                summary.dupCount += 1;
+                summary.dupSize += 1024;
+            }
+            chunks.push(ChunkRecord {
+                fileIndex: 0,
+                offset: i as u64 * 1024,
+                length: 1024,
+                flags: if isDuplicate { CHUNK_DUPLICATE } else { 0 },
+                digest: [id; 32],
+                ..Default::default()
+            });
+        }
+        Trace {
+            header: TraceHeader {
+                chunker: "CDC8K".to_string(),
+                hasher: "BLAKE3".to_string(),
+                digestLength: 32,
+                startTime: 1000 + ids.len() as u64,
+                ..Default::default()
+            },
+            files: vec![FileRecord {
+                fileIndex: 0,
+                path: path.as_bytes().to_vec(),
+                size: ids.len() as u64 * 1024,
+                chunkCount: ids.len() as u64,
+                dupCount: summary.dupCount,
+                dupSize: summary.dupSize,
+                internalDupSize: summary.dupSize,
+                ..Default::default()
+            }],
+            chunks,
+            summary: Some(summary),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testIncompatibility() {
+        let header = createTrace("/a", &[1]).header;
+        assert_eq!(incompatibility(&header, &header), None);
+        let salted = TraceHeader {
+            saltVerifier: vec![1; 8],
+            ..header.clone()
+        };
+        assert_eq!(incompatibility(&header, &salted), Some("different --salt"));
+        let rechunked = TraceHeader {
+            chunker: "CDC4K".to_string(),
+            ..header.clone()
+        };
+        assert_eq!(
+            incompatibility(&header, &rechunked),
+            Some("different chunker")
+        );
+
+        let traces = [
+            (Path::new("a.trace"), createTrace("/a", &[1])),
+            (
+                Path::new("b.trace"),
+                Trace {
+                    header: salted,
+                    ..createTrace("/b", &[2])
+                },
+            ),
+        ];
+        let err = merge(&traces, false).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot merge b.trace: different --salt");
+    }
+
+    #[test]
+    fn testMergeFindsDuplicatesAcrossTraces() {
+        let traces = [
+            (
+                Path::new("/x/host1.trace"),
+                createTrace("/data/f", &[1, 2, 1]),
+            ),
+            (Path::new("host2.trace"), createTrace("/data/f", &[2, 3, 2])),
+        ];
+        let merged = merge(&traces, true).unwrap();
+        assert_eq!(merged.header.startTime, 1003);
+        let paths: Vec<String> = merged.files.iter().map(|f| f.displayPath()).collect();
+        assert_eq!(paths, vec!["host1:/data/f", "host2:/data/f"]);
+        assert_eq!(merged.chunksOf(1).len(), 3);
+
+        let summary = merged.summary.as_ref().unwrap();
+        assert_eq!(summary.fileCount, 2);
+        assert_eq!(summary.chunkCount, 6);
+        assert_eq!(summary.dupCount, 3);
+        assert_eq!(summary.uniqueSizes.sum, 3 * 1024);
+        assert_eq!(merged.files[1].dupSize, 2048);
+        // Only the second copy of chunk 2 has an earlier one in the same file.
+        assert_eq!(merged.files[1].internalDupSize, 1024);
+        assert_eq!(merged.chunks[3].flags, CHUNK_DUPLICATE);
+        assert_eq!(merged.chunks[4].flags, 0);
+    }
+
+    #[test]
+    fn testMergeRemapsFilesAndKeepsDetails() {
+        let a = createTrace("/f", &[1]);
+        // Sparse file indexes, and a compressed copy of the chunk of `a`.
+        let mut b = createTrace("/f", &[1, 2]);
+        b.files[0].fileIndex = 5;
+        for c in &mut b.chunks {
+            c.fileIndex = 5;
+            c.flags |= CHUNK_COMPRESSED;
+            c.compressedSize = 100;
+        }
+        let traces = [
+            (Path::new("/x/host.trace"), a),
+            (Path::new("/y/host.trace"), b),
+        ];
+        let merged = merge(&traces, true).unwrap();
+        let paths: Vec<String> = merged.files.iter().map(|f| f.displayPath()).collect();
+        assert_eq!(paths, vec!["/x/host.trace:/f", "/y/host.trace:/f"]);
+        assert_eq!(merged.chunksOf(1).len(), 2);
+
+        assert_eq!(merged.chunks[0].flags, CHUNK_COMPRESSED);
+        assert_eq!(merged.chunks[0].compressedSize, 100);
+        assert_eq!(merged.chunks[1].flags, CHUNK_DUPLICATE);
+        assert_eq!(merged.chunks[1].compressedSize, 0);
+        let summary = merged.summary.unwrap();
+        assert_eq!(summary.compressedCount, 2);
+        assert_eq!(summary.compressOutputSize, 200);
+
+        let twice = [
+            (Path::new("a.trace"), createTrace("/a", &[1])),
+            (Path::new("a.trace"), createTrace("/a", &[1])),
+        ];
+        assert!(merge(&twice, true).is_err());
+        assert!(merge(&twice, false).is_ok());
+    }
+
+    #[test]
+    fn testMergeWithoutChunkRecords() {
+        let mut a = createTrace("/a", &[1, 1]);
+        let mut b = createTrace("/b", &[1]);
+        a.chunks.clear();
+        b.chunks.clear();
+        let merged = merge(&[(Path::new("a"), a), (Path::new("b"), b)], false).unwrap();
+        let summary = merged.summary.unwrap();
+        // The copy in the second trace is not known without chunk records.
+        assert_eq!(summary.dupCount, 1);
+        assert_eq!(summary.totalSize, 3 * 1024);
+        assert_eq!(merged.files[1].fileIndex, 1);
+        assert_eq!(merged.files[1].path, b"/b");
+
+        let mixed = [
+            (Path::new("a"), createTrace("/a", &[1])),
+            (
+                Path::new("b"),
+                Trace {
+                    chunks: Vec::new(),
+                    ..createTrace("/b", &[1])
+                },
+            ),
+        ];
+        assert!(merge(&mixed, false).is_err());
+
+        let truncated = [
+            (Path::new("a"), createTrace("/a", &[1])),
+            (
+                Path::new("b"),
+                Trace {
+                    summary: None,
+                    ..createTrace("/b", &[2])
+                },
+            ),
+        ];
+        let err = merge(&truncated, false).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Cannot merge b: no summary record"));
+    }
+}
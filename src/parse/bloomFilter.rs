@@ -0,0 +1,329 @@
+use crate::trace::chunkIndex::{ChunkIndex, ChunkLocation, Lookup};
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::parseSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// Size of a Bloom filter, either per unique fingerprint in the trace or in
+/// total, and its number of hash functions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomConfig {
+    pub bitsPerKey: Option<f64>,
+    pub size: Option<u64>,
+    /// Defaults to the optimum for the bits per key, `ln 2 * bits per key`.
+    pub hashes: Option<u32>,
+}
+
+impl BloomConfig {
+    /// Filter bits and hash functions for `keys` fingerprints.
+    pub fn dimensions(&self, keys: u64) -> (u64, u32) {
+        let bits = match (self.size, self.bitsPerKey) {
+            (Some(size), _) => size * 8,
+            (None, Some(bitsPerKey)) => (bitsPerKey * keys as f64).ceil() as u64,
+            (None, None) => unreachable!("validated when parsed"),
+        }
+        .max(64);
+        let hashes = self.hashes.unwrap_or_else(|| {
+            let bitsPerKey = bits as f64 / keys.max(1) as f64;
+            (bitsPerKey * std::f64::consts::LN_2)
+                .round()
+                .clamp(1.0, 32.0) as u32
+        });
+        (bits, hashes)
+    }
+}
+
+impl FromStr for BloomConfig {
+    type Err = String;
+
+    /// Accepts comma-separated `bits-per-key=<N>` or `size=<SIZE>`, and
+    /// optionally `hashes=<K>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = BloomConfig {
+            bitsPerKey: None,
+            size: None,
+            hashes: None,
+        };
+        for option in s.split(',') {
+            let Some((key, value)) = option.split_once('=') else {
+                return Err(format!("Expected <option>=<value>, got {:?}", option));
+            };
+            let invalid = || format!("Invalid {} {:?}", key, value);
+            match key.trim() {
+                "bits-per-key" => match value.parse::<f64>() {
+                    Ok(n) if n > 0.0 && n.is_finite() => config.bitsPerKey = Some(n),
+                    _ => return Err(invalid()),
+                },
+                "size" => match parseSize(value) {
+                    Ok(n) if n > 0 => config.size = Some(n),
+                    _ => return Err(invalid()),
+                },
+                "hashes" => match value.parse::<u32>() {
+                    Ok(n) if (1..=32).contains(&n) => config.hashes = Some(n),
+                    _ => return Err(invalid()),
+                },
+                _ => {
+                    return Err(format!(
+                        "Unknown Bloom filter option {:?}, expected bits-per-key, size or hashes",
+                        key
+                    ));
+                }
+            }
+        }
+        if config.bitsPerKey.is_some() == config.size.is_some() {
+            return Err("Give either bits-per-key or size for the Bloom filter".to_string());
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for BloomConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.bitsPerKey, self.size) {
+            (Some(bitsPerKey), _) => write!(f, "bits-per-key={}", bitsPerKey)?,
+            (None, Some(size)) => write!(f, "size={}", fmtSize(size as usize))?,
+            (None, None) => {}
+        }
+        if let Some(hashes) = self.hashes {
+            write!(f, ",hashes={}", hashes)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct BloomFilter {
+    words: Vec<u64>,
+    bits: u64,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn new(bits: u64, hashes: u32) -> Self {
+        Self {
+            words: vec![0; bits.div_ceil(64) as usize],
+            bits,
+            hashes,
+        }
+    }
+
+    /// Bit positions by double hashing. Bytes 8..16 carry the first hash
+    /// because sampling by fingerprint skews the leading bytes.
+    fn positions(&self, hash: &[u8; 32]) -> impl Iterator<Item = u64> {
+        let h1 = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash[..8].try_into().unwrap()) | 1;
+        let bits = self.bits;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.positions(hash)
+            .all(|p| self.words[(p / 64) as usize] & (1 << (p % 64)) != 0)
+    }
+
+    pub fn insert(&mut self, hash: &[u8; 32]) {
+        let positions: Vec<u64> = self.positions(hash).collect();
+        for p in positions {
+            self.words[(p / 64) as usize] |= 1 << (p % 64);
+        }
+    }
+
+    /// False-positive rate once `keys` fingerprints were inserted.
+    pub fn expectedFalsePositiveRate(&self, keys: u64) -> f64 {
+        let k = self.hashes as f64;
+        (1.0 - (-k * keys as f64 / self.bits as f64).exp()).powf(k)
+    }
+
+    /// Expected false-positive rate of a replay that tests `keys` new
+    /// fingerprints, each before inserting it, so the filter fills up as it goes.
+    pub fn expectedReplayRate(&self, keys: u64) -> f64 {
+        if keys == 0 {
+            return 0.0;
+        }
+        (0..keys)
+            .map(|i| self.expectedFalsePositiveRate(i))
+            .sum::<f64>()
+            / keys as f64
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BloomStats {
+    pub lookups: u64,
+    /// Chunks the exact index had not seen before.
+    pub uniqueCount: u64,
+    /// Unique chunks the filter claimed to have seen, each a wasted disk lookup.
+    pub falsePositives: u64,
+}
+
+impl BloomStats {
+    pub fn falsePositiveRate(&self) -> f64 {
+        if self.uniqueCount == 0 {
+            0.0
+        } else {
+            self.falsePositives as f64 / self.uniqueCount as f64
+        }
+    }
+
+    /// Lookups that reach the on-disk index: every duplicate and every false
+    /// positive.
+    pub fn diskLookups(&self) -> u64 {
+        self.lookups - self.uniqueCount + self.falsePositives
+    }
+}
+
+const NO_LOCATION: ChunkLocation = ChunkLocation {
+    fileIndex: 0,
+    offset: 0,
+    length: 0,
+};
+
+/// Replay the chunk stream through the tracer's exact index and a Bloom filter
+/// in front of it.
+pub fn simulate(trace: &Trace, filter: &mut BloomFilter) -> BloomStats {
+    let index = ChunkIndex::new(false, trace.chunks.len());
+    let mut stats = BloomStats::default();
+    for c in &trace.chunks {
+        stats.lookups += 1;
+        let maybeSeen = filter.contains(&c.digest);
+        if let Lookup::Unique = index.insert(c.digest, NO_LOCATION) {
+            stats.uniqueCount += 1;
+            if maybeSeen {
+                stats.falsePositives += 1;
+            }
+            filter.insert(&c.digest);
+        }
+    }
+    stats
+}
+
+pub fn writeBloom(out: &mut dyn Write, trace: &Trace, configs: &[BloomConfig]) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Bloom filter simulation needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+
+    // Size the filters for the fingerprints the trace will insert.
+    let index = ChunkIndex::new(false, trace.chunks.len());
+    let lookups = trace.chunks.len() as u64;
+    let uniqueCount = trace
+        .chunks
+        .iter()
+        .filter(|c| matches!(index.insert(c.digest, NO_LOCATION), Lookup::Unique))
+        .count() as u64;
+    writeln!(
+        out,
+        "Bloom filter in front of the index, {} lookups of {} unique chunks:",
+        lookups, uniqueCount
+    )?;
+    writeln!(
+        out,
+        "  {:<28}  {:>10}  {:>6}  {:>10}  {:>7}  {:>8}  {:>12}  {:>10}",
+        "filter", "size", "hashes", "false pos.", "rate", "expected", "disk lookups", "avoided"
+    )?;
+    writeln!(
+        out,
+        "  {:<28}  {:>10}  {:>6}  {:>10}  {:>7}  {:>8}  {:>12}  {:>10}",
+        "exact index",
+        fmtSize(uniqueCount as usize * trace.header.digestLength),
+        "-",
+        0,
+        "-",
+        "-",
+        lookups - uniqueCount,
+        uniqueCount
+    )?;
+    for config in configs {
+        let (bits, hashes) = config.dimensions(uniqueCount);
+        let mut filter = BloomFilter::new(bits, hashes);
+        let stats = simulate(trace, &mut filter);
+        writeln!(
+            out,
+            "  {:<28}  {:>10}  {:>6}  {:>10}  {:>6.2}%  {:>7.2}%  {:>12}  {:>10}",
+            config.to_string(),
+            fmtSize(bits.div_ceil(8) as usize),
+            hashes,
+            stats.falsePositives,
+            stats.falsePositiveRate() * 100.0,
+            filter.expectedReplayRate(stats.uniqueCount) * 100.0,
+            stats.diskLookups(),
+            stats.uniqueCount - stats.falsePositives
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::ChunkRecord;
+
+    fn fingerprint(i: u64) -> [u8; 32] {
+        *blake3::hash(&i.to_le_bytes()).as_bytes()
+    }
+
+    #[test]
+    fn testParseConfig() {
+        let config: BloomConfig = "bits-per-key=10".parse().unwrap();
+        assert_eq!(config.dimensions(1000), (10_000, 7));
+        let config: BloomConfig = "size=1K,hashes=3".parse().unwrap();
+        assert_eq!(config.dimensions(1000), (8192, 3));
+        assert_eq!(config.to_string(), "size=1 KiB,hashes=3");
+
+        assert!("bits-per-key=10,size=1M".parse::<BloomConfig>().is_err());
+        assert!("hashes=3".parse::<BloomConfig>().is_err());
+        assert!("bits-per-key=0".parse::<BloomConfig>().is_err());
+        assert!("bits=10".parse::<BloomConfig>().is_err());
+    }
+
+    #[test]
+    fn testFalsePositiveRate() {
+        let mut filter = BloomFilter::new(10 * 10_000, 7);
+        for i in 0..10_000 {
+            filter.insert(&fingerprint(i));
+        }
+        assert!((0..10_000).all(|i| filter.contains(&fingerprint(i))));
+
+        let falsePositives = (10_000..110_000)
+            .filter(|&i| filter.contains(&fingerprint(i)))
+            .count();
+        let rate = falsePositives as f64 / 100_000.0;
+        let expected = filter.expectedFalsePositiveRate(10_000);
+        assert!((expected - 0.0082).abs() < 0.001);
+        assert!((rate - expected).abs() < 0.003, "{rate} vs {expected}");
+        // Testing while filling up meets fewer false positives on average.
+        assert!(filter.expectedReplayRate(10_000) < expected / 2.0);
+    }
+
+    #[test]
+    fn testSimulate() {
+        let trace = Trace {
+            chunks: [1, 2, 1, 3, 2, 4]
+                .iter()
+                .map(|&id| ChunkRecord {
+                    digest: fingerprint(id),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let stats = simulate(&trace, &mut BloomFilter::new(1 << 16, 4));
+        assert_eq!(stats.lookups, 6);
+        assert_eq!(stats.uniqueCount, 4);
+        assert_eq!(stats.falsePositives, 0);
+        assert_eq!(stats.diskLookups(), 2);
+
+        // A saturated filter claims to know every chunk.
+        let mut full = BloomFilter::new(64, 1);
+        full.words.fill(u64::MAX);
+        let stats = simulate(&trace, &mut full);
+        assert_eq!(stats.falsePositives, 4);
+        assert_eq!(stats.diskLookups(), 6);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::util::arguments::CachePolicy;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{self, Write},
+};
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: [u8; 32],
+    prev: usize,
+    next: usize,
+}
+
+/// Fingerprints in recency order, with constant-time lookup, removal and
+/// insertion at the most recently used end.
+#[derive(Default)]
+pub struct LruList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    slots: HashMap<[u8; 32], usize>,
+    /// Most recently used node.
+    head: usize,
+    /// Least recently used node.
+    tail: usize,
+}
+
+impl LruList {
+    pub fn new() -> Self {
+        Self {
+            head: NIL,
+            tail: NIL,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn contains(&self, key: &[u8; 32]) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = (self.nodes[slot].prev, self.nodes[slot].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.nodes[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.nodes[n].prev = prev,
+        }
+    }
+
+    /// Insert a key, or move it to the most recently used end.
+    pub fn pushFront(&mut self, key: [u8; 32]) {
+        let slot = match self.slots.get(&key) {
+            Some(&slot) => {
+                self.unlink(slot);
+                slot
+            }
+            None => {
+                let node = Node {
+                    key,
+                    prev: NIL,
+                    next: NIL,
+                };
+                let slot = match self.free.pop() {
+                    Some(slot) => {
+                        self.nodes[slot] = node;
+                        slot
+                    }
+                    None => {
+                        self.nodes.push(node);
+                        self.nodes.len() - 1
+                    }
+                };
+                self.slots.insert(key, slot);
+                slot
+            }
+        };
+        self.nodes[slot].prev = NIL;
+        self.nodes[slot].next = self.head;
+        match self.head {
+            NIL => self.tail = slot,
+            h => self.nodes[h].prev = slot,
+        }
+        self.head = slot;
+    }
+
+    pub fn remove(&mut self, key: &[u8; 32]) -> bool {
+        match self.slots.remove(key) {
+            Some(slot) => {
+                self.unlink(slot);
+                self.free.push(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove and return the least recently used key.
+    pub fn popBack(&mut self) -> Option<[u8; 32]> {
+        if self.tail == NIL {
+            return None;
+        }
+        let key = self.nodes[self.tail].key;
+        self.remove(&key);
+        Some(key)
+    }
+}
+
+/// An in-memory part of the fingerprint index.
+pub trait FingerprintCache {
+    /// Look a fingerprint up and cache it. Returns true on a hit.
+    fn access(&mut self, key: &[u8; 32]) -> bool;
+}
+
+pub struct LruCache {
+    capacity: usize,
+    list: LruList,
+}
+
+impl LruCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            list: LruList::new(),
+        }
+    }
+}
+
+impl FingerprintCache for LruCache {
+    fn access(&mut self, key: &[u8; 32]) -> bool {
+        let hit = self.list.contains(key);
+        self.list.pushFront(*key);
+        if self.list.len() > self.capacity {
+            self.list.popBack();
+        }
+        hit
+    }
+}
+
+/// Evicts the least frequently used fingerprint, the least recently used one
+/// among equally frequent ones.
+pub struct LfuCache {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<[u8; 32], (u64, u64)>,
+    order: BTreeSet<(u64, u64, [u8; 32])>,
+}
+
+impl LfuCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+        }
+    }
+}
+
+impl FingerprintCache for LfuCache {
+    fn access(&mut self, key: &[u8; 32]) -> bool {
+        self.tick += 1;
+        let count = match self.entries.get(key) {
+            Some(&(count, tick)) => {
+                self.order.remove(&(count, tick, *key));
+                count + 1
+            }
+            None => {
+                if self.entries.len() >= self.capacity
+                    && let Some((_, _, victim)) = self.order.pop_first()
+                {
+                    self.entries.remove(&victim);
+                }
+                0
+            }
+        };
+        self.entries.insert(*key, (count.max(1), self.tick));
+        self.order.insert((count.max(1), self.tick, *key));
+        count > 0
+    }
+}
+
+/// Adaptive replacement cache (Megiddo and Modha, 2003). It balances a list of
+/// fingerprints seen once against a list of fingerprints seen again, steered
+/// by ghost lists of what was recently evicted from either.
+pub struct ArcCache {
+    capacity: usize,
+    /// Target size of `t1`.
+    p: usize,
+    t1: LruList,
+    t2: LruList,
+    b1: LruList,
+    b2: LruList,
+}
+
+impl ArcCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            p: 0,
+            t1: LruList::new(),
+            t2: LruList::new(),
+            b1: LruList::new(),
+            b2: LruList::new(),
+        }
+    }
+
+    fn replace(&mut self, inB2: bool) {
+        let t1 = self.t1.len();
+        if t1 > 0 && (t1 > self.p || (inB2 && t1 == self.p)) {
+            if let Some(key) = self.t1.popBack() {
+                self.b1.pushFront(key);
+            }
+        } else if let Some(key) = self.t2.popBack() {
+            self.b2.pushFront(key);
+        }
+    }
+}
+
+impl FingerprintCache for ArcCache {
+    fn access(&mut self, key: &[u8; 32]) -> bool {
+        let c = self.capacity;
+        if self.t1.remove(key) || self.t2.contains(key) {
+            self.t2.pushFront(*key);
+            return true;
+        }
+
+        if self.b1.contains(key) {
+            let delta = (self.b2.len() / self.b1.len()).max(1);
+            self.p = (self.p + delta).min(c);
+            self.replace(false);
+            self.b1.remove(key);
+            self.t2.pushFront(*key);
+        } else if self.b2.contains(key) {
+            let delta = (self.b1.len() / self.b2.len()).max(1);
+            self.p = self.p.saturating_sub(delta);
+            self.replace(true);
+            self.b2.remove(key);
+            self.t2.pushFront(*key);
+        } else {
+            let l1 = self.t1.len() + self.b1.len();
+            let total = l1 + self.t2.len() + self.b2.len();
+            if l1 >= c {
+                if self.t1.len() < c {
+                    self.b1.popBack();
+                    self.replace(false);
+                } else {
+                    self.t1.popBack();
+                }
+            } else if total >= c {
+                if total >= 2 * c {
+                    self.b2.popBack();
+                }
+                self.replace(false);
+            }
+            self.t1.pushFront(*key);
+        }
+        false
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub lookups: u64,
+    pub hits: u64,
+    /// Misses on fingerprints the store already holds, which cost a disk lookup.
+    pub duplicateMisses: u64,
+    /// Misses on new fingerprints. A store without a filter in front of the
+    /// index also looks these up on disk.
+    pub newMisses: u64,
+}
+
+impl CacheStats {
+    pub fn hitRatio(&self) -> f64 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.lookups as f64
+        }
+    }
+}
+
+pub fn createCache(policy: CachePolicy, capacity: usize) -> Box<dyn FingerprintCache> {
+    match policy {
+        CachePolicy::LRU => Box::new(LruCache::new(capacity)),
+        CachePolicy::LFU => Box::new(LfuCache::new(capacity)),
+        CachePolicy::ARC => Box::new(ArcCache::new(capacity)),
+    }
+}
+
+/// Replay the chunk records through a cache in file-then-offset order, the order
+/// of a sequential scan, which is how [`Trace`] holds them. The interleaving of
+/// the parallel tracer workers is not recorded.
+pub fn simulate(trace: &Trace, cache: &mut dyn FingerprintCache) -> CacheStats {
+    let mut stats = CacheStats::default();
+    let mut stored: HashSet<[u8; 32]> = HashSet::new();
+    for c in &trace.chunks {
+        stats.lookups += 1;
+        if cache.access(&c.digest) {
+            stats.hits += 1;
+        } else if stored.insert(c.digest) {
+            stats.newMisses += 1;
+        } else {
+            stats.duplicateMisses += 1;
+        }
+    }
+    stats
+}
+
+pub fn writeCacheSimulation(
+    out: &mut dyn Write,
+    trace: &Trace,
+    policies: &[CachePolicy],
+    cacheSize: usize,
+) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Cache simulation needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+
+    // A sampled trace only holds one in 2^bits fingerprints, and so does the cache.
+    let capacity = (cacheSize >> trace.header.sampleBits).max(1);
+    writeln!(
+        out,
+        "Fingerprint cache of {} entries, {} lookups:",
+        capacity,
+        trace.chunks.len()
+    )?;
+    writeln!(
+        out,
+        "  {:<6}  {:>10}  {:>9}  {:>12}  {:>10}  {:>10}",
+        "policy", "hits", "hit ratio", "disk lookups", "duplicate", "new"
+    )?;
+    for &policy in policies {
+        let stats = simulate(trace, createCache(policy, capacity).as_mut());
+        writeln!(
+            out,
+            "  {:<6}  {:>10}  {:>8.1}%  {:>12}  {:>10}  {:>10}",
+            format!("{:?}", policy),
+            stats.hits,
+            stats.hitRatio() * 100.0,
+            stats.duplicateMisses + stats.newMisses,
+            stats.duplicateMisses,
+            stats.newMisses
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::ChunkRecord;
+
+    fn key(id: u8) -> [u8; 32] {
+        [id; 32]
+    }
+
+    fn replay(cache: &mut dyn FingerprintCache, ids: &[u8]) -> Vec<bool> {
+        ids.iter().map(|&id| cache.access(&key(id))).collect()
+    }
+
+    #[test]
+    fn testLruAndLfu() {
+        let mut lru = LruCache::new(2);
+        assert_eq!(
+            replay(&mut lru, &[1, 2, 1, 3, 1, 2]),
+            [false, false, true, false, true, false]
+        );
+
+        // LFU keeps the frequent 1 and 2 while 3 and 4 push each other out.
+        let mut lfu = LfuCache::new(3);
+        assert_eq!(
+            replay(&mut lfu, &[1, 1, 2, 2, 3, 4, 3, 1, 2]),
+            [false, true, false, true, false, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn testArcResistsScans() {
+        // A small working set that is used again and again, interrupted by a
+        // long scan of chunks seen once. LRU loses the working set, ARC keeps it.
+        let mut ids: Vec<u8> = Vec::new();
+        for _ in 0..3 {
+            ids.extend(1..=4);
+        }
+        ids.extend(100..200);
+        ids.extend(1..=4);
+
+        let mut lru = LruCache::new(8);
+        let mut arc = ArcCache::new(8);
+        assert!(replay(&mut lru, &ids).ends_with(&[false; 4]));
+        assert!(replay(&mut arc, &ids).ends_with(&[true; 4]));
+        assert!(arc.t1.len() + arc.t2.len() <= 8);
+        assert!(arc.t1.len() + arc.t2.len() + arc.b1.len() + arc.b2.len() <= 16);
+    }
+
+    #[test]
+    fn testSimulate() {
+        let trace = Trace {
+            chunks: [1, 2, 3, 1, 2, 3, 1]
+                .iter()
+                .map(|&id| ChunkRecord {
+                    digest: key(id),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let stats = simulate(&trace, &mut LruCache::new(2));
+        assert_eq!(
+            stats,
+            CacheStats {
+                lookups: 7,
+                hits: 0,
+                duplicateMisses: 4,
+                newMisses: 3,
+            }
+        );
+        let stats = simulate(&trace, &mut LruCache::new(3));
+        assert_eq!(stats.hits, 4);
+        assert_eq!(stats.duplicateMisses, 0);
+
+        let mut out = Vec::new();
+        writeCacheSimulation(&mut out, &trace, &[CachePolicy::LRU, CachePolicy::ARC], 3).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("  LRU              4      57.1%"));
+    }
+}
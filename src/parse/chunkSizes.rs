@@ -0,0 +1,213 @@
+use crate::chunker::chunker::ChunkerType;
+use crate::trace::tracer::{fmtSize, WORK_UNIT_SIZE};
+use crate::util::histogram::SizeHistogram;
+use crate::util::traceFile::{Trace, CHUNK_DUPLICATE};
+
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+/// Unique and duplicate chunk size histograms of a trace. They are taken from the
+/// summary and rebuilt from the chunk records if the summary has none.
+pub fn sizeHistograms(trace: &Trace) -> (SizeHistogram, SizeHistogram) {
+    if let Some(ref s) = trace.summary
+        && !(s.uniqueSizes.isEmpty() && s.dupSizes.isEmpty())
+    {
+        return (s.uniqueSizes.clone(), s.dupSizes.clone());
+    }
+
+    let mut unique = SizeHistogram::new();
+    let mut dup = SizeHistogram::new();
+    for c in &trace.chunks {
+        if c.flags & CHUNK_DUPLICATE != 0 {
+            dup.record(c.length as u64);
+        } else {
+            unique.record(c.length as u64);
+        }
+    }
+    (unique, dup)
+}
+
+fn writeStatistics(out: &mut dyn Write, label: &str, h: &SizeHistogram) -> io::Result<()> {
+    if h.isEmpty() {
+        return writeln!(out, "  {:<10}  none", label);
+    }
+    writeln!(
+        out,
+        "  {:<10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+        label,
+        h.count,
+        fmtSize(h.min as usize),
+        fmtSize(h.percentile(10.0) as usize),
+        fmtSize(h.median() as usize),
+        fmtSize(h.mean() as usize),
+        fmtSize(h.percentile(90.0) as usize),
+        fmtSize(h.percentile(99.0) as usize),
+        fmtSize(h.max as usize)
+    )
+}
+
+pub fn writeChunkSizes(out: &mut dyn Write, trace: &Trace) -> io::Result<()> {
+    let (unique, dup) = sizeHistograms(trace);
+    if unique.isEmpty() && dup.isEmpty() {
+        return writeln!(
+            out,
+            "No chunk sizes recorded, trace with a newer chunkIQ or --log-fingerprints.\n"
+        );
+    }
+
+    writeln!(out, "Chunk sizes:")?;
+    writeln!(
+        out,
+        "  {:<10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+        "", "chunks", "min", "p10", "median", "mean", "p90", "p99", "max"
+    )?;
+    writeStatistics(out, "unique", &unique)?;
+    writeStatistics(out, "duplicate", &dup)?;
+    writeln!(out)?;
+
+    // One row per power of two, over the range covered by either histogram.
+    let mut all = unique.clone();
+    all.merge(&dup);
+    let uniqueBuckets = unique.log2Buckets();
+    let dupBuckets = dup.log2Buckets();
+    let countOf = |buckets: &[(u64, u64)], power: u64| {
+        buckets.iter().find(|b| b.0 == power).map_or(0, |b| b.1)
+    };
+    writeln!(
+        out,
+        "  {:>10}  {:>10}  {:>6}  {:>10}  {:>6}",
+        "size", "unique", "share", "duplicate", "share"
+    )?;
+    for (power, _) in all.log2Buckets() {
+        let u = countOf(&uniqueBuckets, power);
+        let d = countOf(&dupBuckets, power);
+        writeln!(
+            out,
+            "  {:>10}  {:>10}  {:>5}%  {:>10}  {:>5}%",
+            format!(">= {}", fmtSize(power as usize)),
+            u,
+            (u * 100).checked_div(unique.count).unwrap_or(0),
+            d,
+            (d * 100).checked_div(dup.count).unwrap_or(0)
+        )?;
+    }
+
+    // Chunks outside of the configured limits point at a chunker bug.
+    if let Ok(chunker) = ChunkerType::from_str(&trace.header.chunker, true) {
+        let (lowest, highest) = chunker.getBounds();
+        if all.max as usize > highest {
+            writeln!(
+                out,
+                "  Warning: chunks up to {} exceed the {} maximum of {}",
+                fmtSize(all.max as usize),
+                trace.header.chunker,
+                fmtSize(highest)
+            )?;
+        }
+        // The tracer chunks every work unit of a file on its own, so the last
+        // chunk of each work unit may be shorter than the minimum.
+        let workUnits = trace
+            .files
+            .iter()
+            .map(|f| f.size.div_ceil(WORK_UNIT_SIZE as u64))
+            .sum::<u64>();
+        let short = all
+            .log2Buckets()
+            .iter()
+            .filter(|b| ((b.0 * 2) as usize) <= lowest)
+            .map(|b| b.1)
+            .sum::<u64>();
+        if short > workUnits {
+            writeln!(
+                out,
+                "  Warning: {} chunks are below the {} minimum of {}",
+                short,
+                trace.header.chunker,
+                fmtSize(lowest)
+            )?;
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, FileRecord, TraceHeader, TraceSummary};
+
+    fn createChunk(offset: u64, length: u32, flags: u8) -> ChunkRecord {
+        ChunkRecord {
+            fileIndex: 0,
+            offset,
+            length,
+            flags,
+            digest: [0; 32],
+            ..Default::default()
+        }
+    }
+
+    fn createTrace(chunker: &str) -> Trace {
+        Trace {
+            header: TraceHeader {
+                chunker: chunker.to_string(),
+                ..Default::default()
+            },
+            // The 1000 byte chunk is the tail of the file.
+            files: vec![FileRecord {
+                size: 9192,
+                ..Default::default()
+            }],
+            chunks: vec![
+                createChunk(0, 4096, 0),
+                createChunk(4096, 4096, CHUNK_DUPLICATE),
+                createChunk(8192, 1000, 0),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testHistogramsFromChunks() {
+        let (unique, dup) = sizeHistograms(&createTrace("SC4K"));
+        assert_eq!(unique.count, 2);
+        assert_eq!(unique.min, 1000);
+        assert_eq!(dup.count, 1);
+        assert_eq!(dup.max, 4096);
+    }
+
+    #[test]
+    fn testHistogramsPreferSummary() {
+        let mut trace = createTrace("SC4K");
+        let mut summary = TraceSummary::default();
+        summary.uniqueSizes.record(7);
+        trace.summary = Some(summary);
+        let (unique, dup) = sizeHistograms(&trace);
+        assert_eq!(unique.count, 1);
+        assert!(dup.isEmpty());
+    }
+
+    #[test]
+    fn testWriteChunkSizesBounds() {
+        let mut out = Vec::new();
+        writeChunkSizes(&mut out, &createTrace("SC4K")).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("unique"));
+        assert!(!text.contains("Warning"));
+
+        let mut out = Vec::new();
+        writeChunkSizes(&mut out, &createTrace("SC1K")).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("exceed the SC1K maximum"));
+
+        // Every work unit may end in a short chunk, not just every file.
+        let mut trace = createTrace("SC4K");
+        trace.chunks.push(createChunk(9192, 500, 0));
+        let mut out = Vec::new();
+        writeChunkSizes(&mut out, &trace).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("2 chunks are below"));
+        trace.files[0].size = WORK_UNIT_SIZE as u64 + 9692;
+        let mut out = Vec::new();
+        writeChunkSizes(&mut out, &trace).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("Warning"));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::{FileRecord, Trace};
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Number and bytes of the files or chunks in one category.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tally {
+    pub count: u64,
+    pub size: u64,
+}
+
+impl Tally {
+    pub fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.size += size;
+    }
+}
+
+/// How the files of a generation relate to those of the one before.
+#[derive(Debug, Default, PartialEq)]
+pub struct ChurnRow {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub unchanged: Tally,
+    /// Same content under a new name in the same directory.
+    pub renamed: Tally,
+    /// Same content in a different directory.
+    pub moved: Tally,
+    /// Same path, different content.
+    pub modified: Tally,
+    pub added: Tally,
+    pub deleted: Tally,
+    /// Old and new path and size of every rename or move.
+    pub renames: Vec<(String, String, u64)>,
+}
+
+/// Path, size and whole-file digest of a file.
+struct FileState {
+    path: Vec<u8>,
+    size: u64,
+    digest: Option<[u8; 32]>,
+}
+
+fn parent(path: &[u8]) -> &[u8] {
+    path.iter()
+        .rposition(|&b| b == b'/')
+        .map_or(&[][..], |i| &path[..i])
+}
+
+/// Compare two generations by path and whole-file digest. A new path with the
+/// content of a path that is gone is a rename or move; empty files are never
+/// matched that way, since any two of them are identical. A file without a
+/// digest on either side is unchanged if its size is.
+fn compare(previous: &[FileState], current: &[FileState]) -> ChurnRow {
+    let mut row = ChurnRow::default();
+    let old: HashMap<&[u8], &FileState> = previous.iter().map(|f| (f.path.as_slice(), f)).collect();
+    let new: HashMap<&[u8], &FileState> = current.iter().map(|f| (f.path.as_slice(), f)).collect();
+
+    // Files that are gone, by content, in trace order.
+    let mut gone: HashMap<[u8; 32], Vec<&FileState>> = HashMap::new();
+    for f in previous.iter().rev() {
+        if new.contains_key(f.path.as_slice()) {
+            continue;
+        }
+        match f.digest {
+            Some(digest) if f.size > 0 => gone.entry(digest).or_default().push(f),
+            _ => row.deleted.add(f.size),
+        }
+    }
+
+    for f in current {
+        if let Some(before) = old.get(f.path.as_slice()) {
+            let sameDigest = match (before.digest, f.digest) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            };
+            if before.size == f.size && sameDigest {
+                row.unchanged.add(f.size);
+            } else {
+                row.modified.add(f.size);
+            }
+            continue;
+        }
+        let source = f
+            .digest
+            .filter(|_| f.size > 0)
+            .and_then(|digest| gone.get_mut(&digest))
+            .and_then(|candidates| candidates.pop());
+        match source {
+            Some(source) => {
+                if parent(&source.path) == parent(&f.path) {
+                    row.renamed.add(f.size);
+                } else {
+                    row.moved.add(f.size);
+                }
+                row.renames.push((
+                    String::from_utf8_lossy(&source.path).into_owned(),
+                    String::from_utf8_lossy(&f.path).into_owned(),
+                    f.size,
+                ));
+            }
+            None => row.added.add(f.size),
+        }
+    }
+
+    for f in gone.values().flatten() {
+        row.deleted.add(f.size);
+    }
+    row
+}
+
+fn fileStates(files: &[FileRecord]) -> Vec<FileState> {
+    files
+        .iter()
+        .map(|f| FileState {
+            path: f.path.clone(),
+            size: f.size,
+            digest: f.digest,
+        })
+        .collect()
+}
+
+/// Compare the files of two traces of the same file system. Returns why they
+/// cannot be compared if their digests were taken differently.
+pub fn compareTraces(old: &Trace, new: &Trace) -> Result<ChurnRow, &'static str> {
+    if old.header.hasher != new.header.hasher {
+        return Err("the traces use different hash functions");
+    }
+    if old.header.saltVerifier != new.header.saltVerifier {
+        return Err("the traces use different --salt");
+    }
+    Ok(compare(&fileStates(&old.files), &fileStates(&new.files)))
+}
+
+/// Compares every trace with the one before it as consecutive generations of
+/// the same file system.
+pub struct Churn {
+    /// Renames and moves to list per generation.
+    top: usize,
+    hasher: String,
+    saltVerifier: Vec<u8>,
+    previous: Option<(PathBuf, Vec<FileState>)>,
+    pub rows: Vec<ChurnRow>,
+    skipped: Vec<(PathBuf, &'static str)>,
+}
+
+impl Churn {
+    pub fn new(top: usize) -> Self {
+        Self {
+            top,
+            hasher: String::new(),
+            saltVerifier: Vec::new(),
+            previous: None,
+            rows: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, path: &Path, trace: &Trace) {
+        if trace.files.iter().any(|f| f.size > 0 && f.digest.is_none()) {
+            self.skipped.push((
+                path.to_path_buf(),
+                "no whole-file digests, trace again with --file-digests",
+            ));
+            return;
+        }
+        if self.previous.is_none() {
+            self.hasher = trace.header.hasher.clone();
+            self.saltVerifier = trace.header.saltVerifier.clone();
+        } else if trace.header.hasher != self.hasher {
+            self.skipped
+                .push((path.to_path_buf(), "different hash function"));
+            return;
+        } else if trace.header.saltVerifier != self.saltVerifier {
+            self.skipped.push((path.to_path_buf(), "different --salt"));
+            return;
+        }
+
+        let current = fileStates(&trace.files);
+        if let Some((from, previous)) = self.previous.take() {
+            let mut row = compare(&previous, &current);
+            row.from = from;
+            row.to = path.to_path_buf();
+            self.rows.push(row);
+        }
+        self.previous = Some((path.to_path_buf(), current));
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "File churn between {} generations:",
+            self.rows.len() + usize::from(self.previous.is_some())
+        )?;
+        for (path, reason) in &self.skipped {
+            writeln!(out, "  skipped {}: {}", path.display(), reason)?;
+        }
+        for row in &self.rows {
+            writeln!(out, "  {} -> {}:", row.from.display(), row.to.display())?;
+            writeln!(out, "    {:<10}  {:>8}  {:>10}", "", "files", "size")?;
+            for (label, count) in [
+                ("unchanged", row.unchanged),
+                ("renamed", row.renamed),
+                ("moved", row.moved),
+                ("modified", row.modified),
+                ("added", row.added),
+                ("deleted", row.deleted),
+            ] {
+                writeln!(
+                    out,
+                    "    {:<10}  {:>8}  {:>10}",
+                    label,
+                    count.count,
+                    fmtSize(count.size as usize)
+                )?;
+            }
+
+            let mut renames: Vec<&(String, String, u64)> = row.renames.iter().collect();
+            renames.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(&b.1)));
+            for (from, to, size) in renames.into_iter().take(self.top) {
+                writeln!(
+                    out,
+                    "    {:>10}  {} -> {}",
+                    fmtSize(*size as usize),
+                    from,
+                    to
+                )?;
+            }
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn createFile(path: &str, size: u64, id: u8) -> FileState {
+        FileState {
+            path: path.as_bytes().to_vec(),
+            size,
+            digest: Some([id; 32]),
+        }
+    }
+
+    #[test]
+    fn testCompare() {
+        let previous = [
+            createFile("/d/same", 100, 1),
+            createFile("/d/old", 200, 2),
+            createFile("/d/edited", 300, 3),
+            createFile("/d/gone", 400, 4),
+            createFile("/d/tomove", 500, 5),
+            createFile("/d/empty", 0, 0),
+        ];
+        let current = [
+            createFile("/d/same", 100, 1),
+            createFile("/d/new", 200, 2),
+            createFile("/d/edited", 310, 6),
+            createFile("/e/moved", 500, 5),
+            createFile("/d/fresh", 600, 7),
+            createFile("/d/empty2", 0, 0),
+        ];
+        let row = compare(&previous, &current);
+        assert_eq!(
+            row.unchanged,
+            Tally {
+                count: 1,
+                size: 100
+            }
+        );
+        assert_eq!(
+            row.renamed,
+            Tally {
+                count: 1,
+                size: 200
+            }
+        );
+        assert_eq!(
+            row.moved,
+            Tally {
+                count: 1,
+                size: 500
+            }
+        );
+        assert_eq!(
+            row.modified,
+            Tally {
+                count: 1,
+                size: 310
+            }
+        );
+        // Empty files are not renamed.
+        assert_eq!(
+            row.added,
+            Tally {
+                count: 2,
+                size: 600
+            }
+        );
+        assert_eq!(
+            row.deleted,
+            Tally {
+                count: 2,
+                size: 400
+            }
+        );
+        assert_eq!(
+            row.renames,
+            vec![
+                ("/d/old".to_string(), "/d/new".to_string(), 200),
+                ("/d/tomove".to_string(), "/e/moved".to_string(), 500),
+            ]
+        );
+    }
+
+    #[test]
+    fn testCopiesAreMatchedOnce() {
+        // Two identical files are gone and three copies appear: two of them
+        // are renames in trace order, the third is new.
+        let previous = [createFile("/a", 10, 1), createFile("/b", 10, 1)];
+        let current = [
+            createFile("/x", 10, 1),
+            createFile("/y", 10, 1),
+            createFile("/z", 10, 1),
+        ];
+        let row = compare(&previous, &current);
+        assert_eq!(row.renamed.count, 2);
+        assert_eq!(row.added.count, 1);
+        assert_eq!(row.deleted.count, 0);
+        assert_eq!(row.renames[0].0, "/a");
+        assert_eq!(row.renames[1].0, "/b");
+    }
+
+    #[test]
+    fn testCompareWithoutDigests() {
+        // A path without a digest on one side can only be compared by size.
+        let mut previous = [createFile("/a", 10, 1), createFile("/b", 10, 2)];
+        previous[0].digest = None;
+        let mut current = [createFile("/a", 10, 3), createFile("/b", 20, 2)];
+        current[1].digest = None;
+        let row = compare(&previous, &current);
+        assert_eq!(row.unchanged.count, 1);
+        assert_eq!(row.modified.count, 1);
+    }
+
+    #[test]
+    fn testChurnAcrossTraces() {
+        let trace = |files: Vec<(&str, u64, u8)>| Trace {
+            files: files
+                .into_iter()
+                .enumerate()
+                .map(|(i, (path, size, id))| FileRecord {
+                    fileIndex: i as u32,
+                    path: path.as_bytes().to_vec(),
+                    size,
+                    digest: Some([id; 32]),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut churn = Churn::new(5);
+        churn.add(Path::new("g1"), &trace(vec![("/a", 2048, 1)]));
+        let mut old = trace(vec![("/b", 2048, 1)]);
+        old.files[0].digest = None;
+        churn.add(Path::new("old"), &old);
+        let mut salted = trace(vec![("/b", 2048, 1)]);
+        salted.header.saltVerifier = vec![1, 2, 3, 4];
+        churn.add(Path::new("salted"), &salted);
+        churn.add(Path::new("g2"), &trace(vec![("/b", 2048, 1)]));
+        assert_eq!(churn.rows.len(), 1);
+
+        let mut out = Vec::new();
+        churn.write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("skipped old: no whole-file digests"));
+        assert!(text.contains("skipped salted: different --salt"));
+        assert!(text.contains("  g1 -> g2:\n"));
+        assert!(text.contains("    renamed            1       2 KiB\n"));
+        assert!(text.contains("         2 KiB  /a -> /b\n"));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::{ChunkRecord, Trace};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// A rewriting decision covers this many containers worth of logical data.
+const SEGMENT_CONTAINERS: u64 = 32;
+const DEFAULT_CAP: usize = 16;
+const DEFAULT_THRESHOLD: u8 = 10;
+
+/// How a store rewrites duplicate chunks to limit fragmentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RewriteStrategy {
+    #[default]
+    NONE,
+    /// Reference at most this many old containers per segment and rewrite the
+    /// duplicates in all others (Lillibridge et al., 2013).
+    CAPPING(usize),
+    /// Rewrite the duplicates in old containers of which a segment uses less
+    /// than this percentage.
+    THRESHOLD(u8),
+}
+
+impl FromStr for RewriteStrategy {
+    type Err = String;
+
+    /// Accepts `none`, `capping[:<containers>]` and `threshold[:<percent>]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let invalid = |value: &str| format!("Invalid {} value {:?}", name, value);
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("none", None) => Ok(RewriteStrategy::NONE),
+            ("capping", None) => Ok(RewriteStrategy::CAPPING(DEFAULT_CAP)),
+            ("capping", Some(value)) => match value.parse() {
+                Ok(cap) if cap > 0 => Ok(RewriteStrategy::CAPPING(cap)),
+                _ => Err(invalid(value)),
+            },
+            ("threshold", None) => Ok(RewriteStrategy::THRESHOLD(DEFAULT_THRESHOLD)),
+            ("threshold", Some(value)) => match value.parse() {
+                Ok(percent) if percent <= 100 => Ok(RewriteStrategy::THRESHOLD(percent)),
+                _ => Err(invalid(value)),
+            },
+            _ => Err(format!(
+                "Unknown rewrite strategy {:?}, expected none, capping[:containers] or threshold[:percent]",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RewriteStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewriteStrategy::NONE => write!(f, "none"),
+            RewriteStrategy::CAPPING(cap) => write!(f, "capping:{}", cap),
+            RewriteStrategy::THRESHOLD(percent) => write!(f, "threshold:{}", percent),
+        }
+    }
+}
+
+/// Restore cost of a file or a whole generation. The restore keeps every
+/// container it has read, so each container is read at most once.
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreStats {
+    pub logicalSize: u64,
+    pub containerReads: u64,
+}
+
+impl RestoreStats {
+    /// Logical bytes restored per container byte read: 1.0 for data stored
+    /// sequentially, less for fragmented data.
+    pub fn speedFactor(&self, containerSize: u64) -> f64 {
+        if self.containerReads == 0 {
+            return 1.0;
+        }
+        self.logicalSize as f64 / (self.containerReads * containerSize) as f64
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GenerationStats {
+    /// Bytes appended to containers, new chunks and rewritten duplicates.
+    pub storedSize: u64,
+    pub rewrittenSize: u64,
+    pub containersWritten: u64,
+    pub restore: RestoreStats,
+    /// Restore cost of each file, by file index.
+    pub files: Vec<(u32, RestoreStats)>,
+}
+
+/// A dedup store that appends unique chunks to fixed-size containers in
+/// stream order. Every trace added is one backup generation.
+pub struct ContainerStore {
+    pub containerSize: u64,
+    pub strategy: RewriteStrategy,
+    /// The container that holds the latest copy of every chunk.
+    index: HashMap<[u8; 32], u64>,
+    /// The open container and the bytes in it.
+    current: u64,
+    currentSize: u64,
+}
+
+impl ContainerStore {
+    pub fn new(containerSize: u64, strategy: RewriteStrategy) -> Self {
+        Self {
+            containerSize,
+            strategy,
+            index: HashMap::new(),
+            current: 0,
+            currentSize: 0,
+        }
+    }
+
+    fn append(&mut self, c: &ChunkRecord, stats: &mut GenerationStats) -> u64 {
+        if self.currentSize > 0 && self.currentSize + c.length as u64 > self.containerSize {
+            self.current += 1;
+            self.currentSize = 0;
+        }
+        if self.currentSize == 0 {
+            stats.containersWritten += 1;
+        }
+        self.currentSize += c.length as u64;
+        stats.storedSize += c.length as u64;
+        self.index.insert(c.digest, self.current);
+        self.current
+    }
+
+    /// Old containers whose duplicates in this segment are written again.
+    fn rewriteSet(&self, segment: &[ChunkRecord]) -> HashSet<u64> {
+        let mut used: HashMap<u64, u64> = HashMap::new();
+        let mut seen = HashSet::new();
+        for c in segment {
+            if let Some(&container) = self.index.get(&c.digest)
+                && seen.insert(c.digest)
+            {
+                *used.entry(container).or_default() += c.length as u64;
+            }
+        }
+        match self.strategy {
+            RewriteStrategy::NONE => HashSet::new(),
+            RewriteStrategy::CAPPING(cap) => {
+                let mut ranked: Vec<(u64, u64)> = used.into_iter().collect();
+                ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                ranked.into_iter().skip(cap).map(|(id, _)| id).collect()
+            }
+            RewriteStrategy::THRESHOLD(percent) => used
+                .into_iter()
+                .filter(|&(_, size)| size * 100 < self.containerSize * percent as u64)
+                .map(|(id, _)| id)
+                .collect(),
+        }
+    }
+
+    /// Store the chunks of one trace and measure the cost of restoring it.
+    pub fn addGeneration(&mut self, trace: &Trace) -> GenerationStats {
+        let mut stats = GenerationStats::default();
+        let mut generationReads: HashSet<u64> = HashSet::new();
+        let mut fileReads: HashSet<u64> = HashSet::new();
+        let mut file = RestoreStats::default();
+
+        let segmentSize = SEGMENT_CONTAINERS * self.containerSize;
+        let mut start = 0;
+        while start < trace.chunks.len() {
+            let mut end = start;
+            let mut size = 0;
+            while end < trace.chunks.len() && size < segmentSize {
+                size += trace.chunks[end].length as u64;
+                end += 1;
+            }
+            let rewrite = self.rewriteSet(&trace.chunks[start..end]);
+
+            for (i, c) in trace.chunks[start..end].iter().enumerate() {
+                let container = match self.index.get(&c.digest) {
+                    Some(&container) if !rewrite.contains(&container) => container,
+                    Some(_) => {
+                        stats.rewrittenSize += c.length as u64;
+                        self.append(c, &mut stats)
+                    }
+                    None => self.append(c, &mut stats),
+                };
+
+                generationReads.insert(container);
+                fileReads.insert(container);
+                file.logicalSize += c.length as u64;
+                stats.restore.logicalSize += c.length as u64;
+
+                let next = trace.chunks.get(start + i + 1);
+                if next.is_none_or(|n| n.fileIndex != c.fileIndex) {
+                    file.containerReads = fileReads.len() as u64;
+                    stats.files.push((c.fileIndex, file));
+                    file = RestoreStats::default();
+                    fileReads.clear();
+                }
+            }
+            start = end;
+        }
+        stats.restore.containerReads = generationReads.len() as u64;
+        stats
+    }
+}
+
+/// Add a trace as the next generation to every store and compare them.
+pub fn writeGeneration(
+    out: &mut dyn Write,
+    path: &Path,
+    trace: &Trace,
+    stores: &mut [ContainerStore],
+    n: usize,
+) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Container simulation needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+    if trace.header.sampleBits > 0 {
+        return writeln!(
+            out,
+            "Container simulation needs all chunks, trace without --sample-bits.\n"
+        );
+    }
+    let Some(containerSize) = stores.first().map(|s| s.containerSize) else {
+        return Ok(());
+    };
+
+    writeln!(
+        out,
+        "Containers of {}, generation {}:",
+        fmtSize(containerSize as usize),
+        path.display()
+    )?;
+    writeln!(
+        out,
+        "  {:<14}  {:>10}  {:>10}  {:>10}  {:>10}  {:>5}",
+        "rewriting", "stored", "rewritten", "opened", "reads", "speed"
+    )?;
+    let mut first = None;
+    for store in stores.iter_mut() {
+        let stats = store.addGeneration(trace);
+        writeln!(
+            out,
+            "  {:<14}  {:>10}  {:>10}  {:>10}  {:>10}  {:>5.2}",
+            store.strategy.to_string(),
+            fmtSize(stats.storedSize as usize),
+            fmtSize(stats.rewrittenSize as usize),
+            stats.containersWritten,
+            stats.restore.containerReads,
+            stats.restore.speedFactor(containerSize)
+        )?;
+        first.get_or_insert(stats);
+    }
+    writeln!(out)?;
+
+    // The most fragmented files with the first strategy, by container reads
+    // beyond what the file would need if it were stored sequentially.
+    let Some(mut stats) = first else {
+        return Ok(());
+    };
+    let excess = |r: &RestoreStats| {
+        r.containerReads
+            .saturating_sub(r.logicalSize.div_ceil(containerSize))
+    };
+    stats.files.retain(|(_, r)| excess(r) > 0);
+    if stats.files.is_empty() || n == 0 {
+        return Ok(());
+    }
+    stats
+        .files
+        .sort_by(|a, b| excess(&b.1).cmp(&excess(&a.1)).then(a.0.cmp(&b.0)));
+    stats.files.truncate(n);
+
+    let paths: HashMap<u32, String> = trace
+        .files
+        .iter()
+        .map(|f| (f.fileIndex, f.displayPath()))
+        .collect();
+    writeln!(out, "Most fragmented files ({}):", stores[0].strategy)?;
+    writeln!(
+        out,
+        "  {:>4}  {:>10}  {:>8}  {:>5}  path",
+        "#", "logical", "reads", "speed"
+    )?;
+    for (i, (fileIndex, r)) in stats.files.iter().enumerate() {
+        writeln!(
+            out,
+            "  {:>4}  {:>10}  {:>8}  {:>5.2}  {}",
+            i + 1,
+            fmtSize(r.logicalSize as usize),
+            r.containerReads,
+            r.speedFactor(containerSize),
+            paths.get(fileIndex).map_or("?", |p| p.as_str())
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// One file of 1 KiB chunks with the given ids.
+    fn createTrace(ids: &[u32]) -> Trace {
+        Trace {
+            chunks: ids
+                .iter()
+                .enumerate()
+                .map(|(i, &id)| {
+                    let mut digest = [0u8; 32];
+                    digest[..4].copy_from_slice(&id.to_le_bytes());
+                    ChunkRecord {
+                        offset: i as u64 * 1024,
+                        length: 1024,
+                        digest,
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testParseStrategy() {
+        assert_eq!("none".parse(), Ok(RewriteStrategy::NONE));
+        assert_eq!("capping".parse(), Ok(RewriteStrategy::CAPPING(DEFAULT_CAP)));
+        assert_eq!("Capping:4".parse(), Ok(RewriteStrategy::CAPPING(4)));
+        assert_eq!("threshold:25".parse(), Ok(RewriteStrategy::THRESHOLD(25)));
+        assert!("capping:0".parse::<RewriteStrategy>().is_err());
+        assert!("threshold:101".parse::<RewriteStrategy>().is_err());
+        assert!("lru".parse::<RewriteStrategy>().is_err());
+        assert_eq!(RewriteStrategy::CAPPING(4).to_string(), "capping:4");
+    }
+
+    #[test]
+    fn testFragmentation() {
+        let mut store = ContainerStore::new(4096, RewriteStrategy::NONE);
+        let first = store.addGeneration(&createTrace(&(0..16).collect::<Vec<_>>()));
+        assert_eq!(first.containersWritten, 4);
+        assert_eq!(first.restore.containerReads, 4);
+        assert_eq!(first.restore.speedFactor(4096), 1.0);
+
+        // The second generation changes every fourth chunk and then reads one
+        // old chunk from each of the four containers.
+        let ids: Vec<u32> = (0..16)
+            .map(|i| if i % 4 == 0 { 100 + i } else { i })
+            .collect();
+        let second = store.addGeneration(&createTrace(&ids));
+        assert_eq!(second.storedSize, 4 * 1024);
+        assert_eq!(second.containersWritten, 1);
+        assert_eq!(second.restore.containerReads, 5);
+        assert_eq!(second.restore.speedFactor(4096), 16.0 / 20.0);
+        assert_eq!(second.files.len(), 1);
+    }
+
+    #[test]
+    fn testRewritingReducesReads() {
+        let base: Vec<u32> = (0..64).collect();
+        // Take one chunk from each of the 16 old containers, plus new data.
+        let next: Vec<u32> = (0..16).map(|i| i * 4).chain(100..148).collect();
+
+        let mut reads = Vec::new();
+        for strategy in [
+            RewriteStrategy::NONE,
+            RewriteStrategy::CAPPING(4),
+            RewriteStrategy::THRESHOLD(50),
+        ] {
+            let mut store = ContainerStore::new(4096, strategy);
+            store.addGeneration(&createTrace(&base));
+            let stats = store.addGeneration(&createTrace(&next));
+            reads.push((stats.restore.containerReads, stats.rewrittenSize));
+        }
+        assert_eq!(reads[0], (16 + 12, 0));
+        // Capping keeps four old containers and rewrites the other twelve chunks.
+        assert_eq!(reads[1], (4 + 15, 12 * 1024));
+        // Every old container is used by one chunk, a quarter of it.
+        assert_eq!(reads[2], (16, 16 * 1024));
+    }
+}
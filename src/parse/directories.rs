@@ -0,0 +1,330 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::DirectorySort;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+/// Redundancy of everything below one directory.
+#[derive(Debug, Default, PartialEq)]
+pub struct DirectoryStats {
+    pub fileCount: u64,
+    /// Size of all files, as seen by a user.
+    pub logicalSize: u64,
+    /// Size after deduplicating the subtree on its own.
+    pub uniqueSize: u64,
+    /// Part of `uniqueSize` that also occurs outside of the subtree.
+    pub sharedSize: u64,
+}
+
+impl DirectoryStats {
+    pub fn dedupRatio(&self) -> f64 {
+        if self.uniqueSize == 0 {
+            1.0
+        } else {
+            self.logicalSize as f64 / self.uniqueSize as f64
+        }
+    }
+}
+
+/// Path components of a directory. Absolute paths start with an empty component.
+type DirKey = Vec<Vec<u8>>;
+
+fn parentComponents(path: &[u8]) -> DirKey {
+    let mut components: DirKey = path.split(|&b| b == b'/').map(|c| c.to_vec()).collect();
+    components.pop();
+    components
+}
+
+fn displayKey(key: &[Vec<u8>]) -> String {
+    match key {
+        [] => ".".to_string(),
+        [root] if root.is_empty() => "/".to_string(),
+        _ => String::from_utf8_lossy(&key.join(&b'/')).into_owned(),
+    }
+}
+
+/// Roll file and chunk records up the directory tree. Directories deeper than
+/// `maxDepth` below the common ancestor of all files are folded into their
+/// ancestor at `maxDepth`. The result is keyed by path components.
+pub fn rollup(trace: &Trace, maxDepth: usize) -> BTreeMap<DirKey, DirectoryStats> {
+    let dirs: Vec<DirKey> = trace
+        .files
+        .iter()
+        .map(|f| parentComponents(&f.path))
+        .collect();
+
+    // The report starts at the deepest directory that contains all files.
+    let mut rootLen = dirs.first().map_or(0, |d| d.len());
+    for d in &dirs {
+        rootLen = dirs[0][..rootLen]
+            .iter()
+            .zip(d)
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+
+    let mut occurrences: HashMap<[u8; 32], u64> = HashMap::new();
+    for c in &trace.chunks {
+        *occurrences.entry(c.digest).or_insert(0) += 1;
+    }
+
+    // Per directory: chunk size and number of occurrences inside the subtree.
+    let mut digests: BTreeMap<DirKey, HashMap<[u8; 32], (u32, u64)>> = BTreeMap::new();
+    let mut result: BTreeMap<DirKey, DirectoryStats> = BTreeMap::new();
+
+    for (file, dir) in trace.files.iter().zip(&dirs) {
+        let deepest = dir.len().min(rootLen.saturating_add(maxDepth));
+        for len in rootLen..=deepest {
+            let key = dir[..len].to_vec();
+            let stats = result.entry(key.clone()).or_default();
+            stats.fileCount += 1;
+            stats.logicalSize += file.size;
+
+            let seen = digests.entry(key).or_default();
+            for c in trace.chunksOf(file.fileIndex) {
+                seen.entry(c.digest).or_insert((c.length, 0)).1 += 1;
+            }
+        }
+    }
+
+    // The logical size is that of the whole files, the chunks are a sample.
+    let scale = 1u64 << trace.header.sampleBits;
+    for (key, seen) in digests {
+        let stats = result.get_mut(&key).unwrap();
+        for (digest, (length, count)) in seen {
+            stats.uniqueSize += length as u64 * scale;
+            if occurrences[&digest] > count {
+                stats.sharedSize += length as u64 * scale;
+            }
+        }
+    }
+
+    result
+}
+
+fn sortKey(sort: DirectorySort, a: &DirectoryStats, b: &DirectoryStats) -> std::cmp::Ordering {
+    match sort {
+        DirectorySort::LOGICAL => b.logicalSize.cmp(&a.logicalSize),
+        DirectorySort::UNIQUE => b.uniqueSize.cmp(&a.uniqueSize),
+        DirectorySort::RATIO => b.dedupRatio().total_cmp(&a.dedupRatio()),
+        DirectorySort::SHARED => b.sharedSize.cmp(&a.sharedSize),
+        DirectorySort::NAME => std::cmp::Ordering::Equal,
+    }
+}
+
+fn writeNode(
+    out: &mut dyn Write,
+    tree: &BTreeMap<DirKey, DirectoryStats>,
+    key: &DirKey,
+    depth: usize,
+    sort: DirectorySort,
+) -> io::Result<()> {
+    let stats = &tree[key];
+    let name = if depth == 0 {
+        displayKey(key)
+    } else {
+        format!(
+            "{}/",
+            String::from_utf8_lossy(key.last().map_or(&[][..], |c| c))
+        )
+    };
+    writeln!(
+        out,
+        "  {:>10}  {:>10}  {:>6.2}  {:>10}  {}{}",
+        fmtSize(stats.logicalSize as usize),
+        fmtSize(stats.uniqueSize as usize),
+        stats.dedupRatio(),
+        fmtSize(stats.sharedSize as usize),
+        "  ".repeat(depth),
+        name
+    )?;
+
+    // BTreeMap order keeps children sorted by name, which breaks ties.
+    let mut children: Vec<&DirKey> = tree
+        .range(key.clone()..)
+        .skip(1)
+        .take_while(|(k, _)| k.starts_with(key))
+        .filter(|(k, _)| k.len() == key.len() + 1)
+        .map(|(k, _)| k)
+        .collect();
+    children.sort_by(|a, b| sortKey(sort, &tree[*a], &tree[*b]));
+
+    for child in children {
+        writeNode(out, tree, child, depth + 1, sort)?;
+    }
+    Ok(())
+}
+
+pub fn writeDirectories(
+    out: &mut dyn Write,
+    trace: &Trace,
+    maxDepth: usize,
+    sort: DirectorySort,
+) -> io::Result<()> {
+    if trace.chunks.is_empty() && trace.files.iter().any(|f| f.size > 0) {
+        return writeln!(
+            out,
+            "Directory rollup needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+
+    let tree = rollup(trace, maxDepth);
+    let Some(root) = tree.keys().next().cloned() else {
+        return Ok(());
+    };
+
+    writeln!(out, "Directories:")?;
+    writeln!(
+        out,
+        "  {:>10}  {:>10}  {:>6}  {:>10}  path",
+        "logical", "unique", "ratio", "shared"
+    )?;
+    writeNode(out, &tree, &root, 0, sort)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::trace::sampling::isSampled;
+    use crate::util::traceFile::{ChunkRecord, FileRecord};
+
+    fn createFile(fileIndex: u32, path: &str, size: u64) -> FileRecord {
+        FileRecord {
+            fileIndex,
+            path: path.as_bytes().to_vec(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    fn createChunk(fileIndex: u32, offset: u64, fill: u8) -> ChunkRecord {
+        ChunkRecord {
+            fileIndex,
+            offset,
+            length: 100,
+            flags: 0,
+            digest: [fill; 32],
+            ..Default::default()
+        }
+    }
+
+    // /data/a/x: chunks 1 1
+    // /data/a/y: chunks 2
+    // /data/b/z: chunks 2 3
+    fn createTrace() -> Trace {
+        Trace {
+            files: vec![
+                createFile(0, "/data/a/x", 200),
+                createFile(1, "/data/a/y", 100),
+                createFile(2, "/data/b/z", 200),
+            ],
+            chunks: vec![
+                createChunk(0, 0, 1),
+                createChunk(0, 100, 1),
+                createChunk(1, 0, 2),
+                createChunk(2, 0, 2),
+                createChunk(2, 100, 3),
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// Two files of 4000 chunks sharing 2000, sampled by fingerprint.
+    fn createSampledTrace(sampleBits: u8) -> Trace {
+        let mut trace = Trace::default();
+        trace.header.sampleBits = sampleBits;
+        for fileIndex in 0..2u32 {
+            let path = format!("/data/{}/f", fileIndex);
+            trace.files.push(createFile(fileIndex, &path, 4000 * 100));
+            for i in 0..4000u32 {
+                let digest = *blake3::hash(&(i + fileIndex * 2000).to_le_bytes()).as_bytes();
+                if isSampled(&digest, sampleBits) {
+                    trace.chunks.push(ChunkRecord {
+                        fileIndex,
+                        offset: i as u64 * 100,
+                        length: 100,
+                        digest,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        trace
+    }
+
+    fn key(path: &str) -> DirKey {
+        path.split('/').map(|c| c.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn testRollup() {
+        let tree = rollup(&createTrace(), usize::MAX);
+        assert_eq!(tree.len(), 3);
+
+        let root = &tree[&key("/data")];
+        assert_eq!(root.fileCount, 3);
+        assert_eq!(root.logicalSize, 500);
+        assert_eq!(root.uniqueSize, 300);
+        assert_eq!(root.sharedSize, 0);
+
+        let a = &tree[&key("/data/a")];
+        assert_eq!(a.logicalSize, 300);
+        assert_eq!(a.uniqueSize, 200);
+        // Chunk 2 also occurs in /data/b.
+        assert_eq!(a.sharedSize, 100);
+        assert_eq!(a.dedupRatio(), 1.5);
+
+        let b = &tree[&key("/data/b")];
+        assert_eq!(b.uniqueSize, 200);
+        assert_eq!(b.sharedSize, 100);
+    }
+
+    #[test]
+    fn testRollupSampled() {
+        // A sampled trace scales its chunks up to the ratio of the exact one.
+        let exact = rollup(&createSampledTrace(0), usize::MAX);
+        let sampled = rollup(&createSampledTrace(3), usize::MAX);
+        let root = key("/data");
+        assert_eq!(exact[&root].dedupRatio(), 8000.0 / 6000.0);
+        let error = sampled[&root].dedupRatio() / exact[&root].dedupRatio() - 1.0;
+        assert!(error.abs() < 0.1, "{}", sampled[&root].dedupRatio());
+
+        let dir = key("/data/0");
+        let error = sampled[&dir].sharedSize as f64 / exact[&dir].sharedSize as f64 - 1.0;
+        assert!(error.abs() < 0.2, "{}", sampled[&dir].sharedSize);
+    }
+
+    #[test]
+    fn testRollupDepthLimit() {
+        let tree = rollup(&createTrace(), 0);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[&key("/data")].logicalSize, 500);
+    }
+
+    #[test]
+    fn testWriteDirectoriesSorted() {
+        let mut out = Vec::new();
+        writeDirectories(&mut out, &createTrace(), 4, DirectorySort::LOGICAL).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        assert!(lines[2].ends_with("/data"));
+        assert!(lines[3].ends_with("  a/"));
+        assert!(lines[4].ends_with("  b/"));
+
+        let mut out = Vec::new();
+        writeDirectories(&mut out, &createTrace(), 4, DirectorySort::RATIO).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.find("a/").unwrap() < text.find("b/").unwrap());
+    }
+
+    #[test]
+    fn testDisplayKey() {
+        assert_eq!(displayKey(&key("/")[..1]), "/");
+        assert_eq!(displayKey(&[]), ".");
+        assert_eq!(displayKey(&key("rel/dir")), "rel/dir");
+    }
+}
@@ -0,0 +1,246 @@
+use crate::parse::popularity::hexDigest;
+use crate::trace::tracer::fmtSize;
+use crate::util::arguments::ReportFormat;
+use crate::util::json::jsonString;
+use crate::util::traceFile::{FileRecord, Trace};
+
+use std::{
+    collections::HashMap,
+    io::{self, Error, ErrorKind, Write},
+    path::Path,
+};
+
+/// Files with byte-identical content.
+#[derive(Debug, PartialEq)]
+pub struct DuplicateGroup<'a> {
+    pub digest: [u8; 32],
+    pub size: u64,
+    /// In trace order; the first file is the one a dedup would keep.
+    pub files: Vec<&'a FileRecord>,
+}
+
+impl DuplicateGroup<'_> {
+    /// Bytes taken by all copies but one.
+    pub fn wastedSize(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// Groups of identical non-empty files by whole-file digest, most wasted bytes
+/// first. Files without a digest are left out.
+pub fn findDuplicates(trace: &Trace) -> Vec<DuplicateGroup<'_>> {
+    let mut groups: HashMap<([u8; 32], u64), Vec<&FileRecord>> = HashMap::new();
+    for file in &trace.files {
+        if let Some(digest) = file.digest
+            && file.size > 0
+        {
+            groups.entry((digest, file.size)).or_default().push(file);
+        }
+    }
+
+    let mut result: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|((digest, size), mut files)| {
+            files.sort_by_key(|f| f.fileIndex);
+            DuplicateGroup {
+                digest,
+                size,
+                files,
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        b.wastedSize()
+            .cmp(&a.wastedSize())
+            .then(a.files[0].fileIndex.cmp(&b.files[0].fileIndex))
+    });
+    result
+}
+
+/// One JSON object per trace, with one line per group.
+fn writeJson(
+    out: &mut dyn Write,
+    path: &Path,
+    trace: &Trace,
+    groups: &[DuplicateGroup],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "{{\"trace\": {}, \"groups\": [",
+        jsonString(&path.to_string_lossy())
+    )?;
+    for (i, group) in groups.iter().enumerate() {
+        let paths: Vec<String> = group
+            .files
+            .iter()
+            .map(|f| jsonString(&f.displayPath()))
+            .collect();
+        writeln!(
+            out,
+            "  {{\"digest\": \"{}\", \"size\": {}, \"copies\": {}, \"wasted\": {}, \"paths\": [{}]}}{}",
+            hexDigest(&group.digest[..trace.header.digestLength]),
+            group.size,
+            group.files.len(),
+            group.wastedSize(),
+            paths.join(", "),
+            if i + 1 < groups.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "]}}")
+}
+
+pub fn writeDuplicateFiles(
+    out: &mut dyn Write,
+    path: &Path,
+    trace: &Trace,
+    format: ReportFormat,
+    n: usize,
+) -> io::Result<()> {
+    if trace.files.iter().any(|f| f.size > 0) && trace.files.iter().all(|f| f.digest.is_none()) {
+        if let ReportFormat::JSON = format {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: no whole-file digests", path.display()),
+            ));
+        }
+        return writeln!(
+            out,
+            "Duplicate files need whole-file digests, trace again with --file-digests.\n"
+        );
+    }
+
+    let groups = findDuplicates(trace);
+    if let ReportFormat::JSON = format {
+        return writeJson(out, path, trace, &groups);
+    }
+
+    let fileCount: usize = groups.iter().map(|g| g.files.len()).sum();
+    let wasted: u64 = groups.iter().map(|g| g.wastedSize()).sum();
+    writeln!(out, "Identical files:")?;
+    writeln!(
+        out,
+        "  {} groups of {} files, {} wasted by copies",
+        groups.len(),
+        fileCount,
+        fmtSize(wasted as usize)
+    )?;
+    for (i, group) in groups.iter().take(n).enumerate() {
+        writeln!(
+            out,
+            "  #{}: {} copies of {}, {} wasted",
+            i + 1,
+            group.files.len(),
+            fmtSize(group.size as usize),
+            fmtSize(group.wastedSize() as usize)
+        )?;
+        for file in &group.files {
+            writeln!(out, "    {}", file.displayPath())?;
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::TraceHeader;
+
+    fn createFile(fileIndex: u32, path: &str, size: u64, id: u8) -> FileRecord {
+        FileRecord {
+            fileIndex,
+            path: path.as_bytes().to_vec(),
+            size,
+            digest: Some([id; 32]),
+            ..Default::default()
+        }
+    }
+
+    fn createTrace() -> Trace {
+        Trace {
+            header: TraceHeader {
+                digestLength: 4,
+                ..Default::default()
+            },
+            files: vec![
+                createFile(0, "/a", 100, 1),
+                createFile(1, "/b", 5000, 2),
+                createFile(2, "/c", 100, 1),
+                createFile(3, "/d", 5000, 2),
+                createFile(4, "/e", 5000, 3),
+                createFile(5, "/f", 0, 4),
+                createFile(6, "/g", 0, 4),
+                createFile(7, "/h\"quoted\"", 100, 1),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testFindDuplicates() {
+        let trace = createTrace();
+        let groups = findDuplicates(&trace);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].size, 5000);
+        assert_eq!(groups[0].wastedSize(), 5000);
+        let paths: Vec<String> = groups[1].files.iter().map(|f| f.displayPath()).collect();
+        assert_eq!(paths, vec!["/a", "/c", "/h\"quoted\""]);
+        assert_eq!(groups[1].wastedSize(), 200);
+    }
+
+    #[test]
+    fn testWriteText() {
+        let mut out = Vec::new();
+        writeDuplicateFiles(
+            &mut out,
+            Path::new("t"),
+            &createTrace(),
+            ReportFormat::TEXT,
+            1,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("2 groups of 5 files, 5 KiB wasted by copies"));
+        assert!(text.contains("#1: 2 copies of 5 KiB, 5 KiB wasted\n    /b\n    /d\n"));
+        assert!(!text.contains("#2"));
+
+        // Traces without whole-file digests.
+        let mut trace = createTrace();
+        trace.files.iter_mut().for_each(|f| f.digest = None);
+        let mut out = Vec::new();
+        writeDuplicateFiles(&mut out, Path::new("t"), &trace, ReportFormat::TEXT, 1).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("whole-file digests"));
+    }
+
+    #[test]
+    fn testWriteJson() {
+        let mut out = Vec::new();
+        writeDuplicateFiles(
+            &mut out,
+            Path::new("t"),
+            &createTrace(),
+            ReportFormat::JSON,
+            1,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "{\"trace\": \"t\", \"groups\": [\n\
+             \x20 {\"digest\": \"02020202\", \"size\": 5000, \"copies\": 2, \"wasted\": 5000, \"paths\": [\"/b\", \"/d\"]},\n\
+             \x20 {\"digest\": \"01010101\", \"size\": 100, \"copies\": 3, \"wasted\": 200, \"paths\": [\"/a\", \"/c\", \"/h\\\"quoted\\\"\"]}\n\
+             ]}\n"
+        );
+
+        // No text may end up in the JSON.
+        let mut trace = createTrace();
+        trace.files.iter_mut().for_each(|f| f.digest = None);
+        let mut out = Vec::new();
+        assert!(
+            writeDuplicateFiles(&mut out, Path::new("t"), &trace, ReportFormat::JSON, 1).is_err()
+        );
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::io::{self, Write};
+
+/// Upper entropy limits in bits per byte of the low and medium class. Text and
+/// sparse data are low, executables and databases medium, and compressed,
+/// encrypted or media files high.
+const LOW_ENTROPY: f32 = 5.0;
+const HIGH_ENTROPY: f32 = 7.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EntropyClass {
+    LOW,
+    MEDIUM,
+    HIGH,
+}
+
+impl EntropyClass {
+    pub fn of(entropy: f32) -> EntropyClass {
+        if entropy < LOW_ENTROPY {
+            EntropyClass::LOW
+        } else if entropy < HIGH_ENTROPY {
+            EntropyClass::MEDIUM
+        } else {
+            EntropyClass::HIGH
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            EntropyClass::LOW => format!("low (< {})", LOW_ENTROPY),
+            EntropyClass::MEDIUM => format!("medium (< {})", HIGH_ENTROPY),
+            EntropyClass::HIGH => "high".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ClassStats {
+    pub fileCount: u64,
+    pub logicalSize: u64,
+    pub savedSize: u64,
+    /// Sum of file entropy times file size, for the average of the class.
+    entropySum: f64,
+}
+
+impl ClassStats {
+    pub fn averageEntropy(&self) -> f64 {
+        if self.logicalSize == 0 {
+            0.0
+        } else {
+            self.entropySum / self.logicalSize as f64
+        }
+    }
+}
+
+/// Group the files of a trace by entropy class, indexed by `EntropyClass`.
+/// Files without a measured entropy are left out.
+pub fn groupByEntropy(trace: &Trace) -> [ClassStats; 3] {
+    let mut result: [ClassStats; 3] = Default::default();
+    for f in &trace.files {
+        let Some(entropy) = f.entropy else {
+            continue;
+        };
+        let stats = &mut result[EntropyClass::of(entropy) as usize];
+        stats.fileCount += 1;
+        stats.logicalSize += f.size;
+        stats.savedSize += f.dupSize;
+        stats.entropySum += entropy as f64 * f.size as f64;
+    }
+    result
+}
+
+pub fn writeEntropy(out: &mut dyn Write, trace: &Trace) -> io::Result<()> {
+    if trace.files.iter().all(|f| f.entropy.is_none()) {
+        return writeln!(
+            out,
+            "Entropy classes need a trace written with --entropy.\n"
+        );
+    }
+
+    let classes = groupByEntropy(trace);
+    let totalSaved: u64 = classes.iter().map(|c| c.savedSize).sum();
+
+    writeln!(out, "Entropy classes:")?;
+    writeln!(
+        out,
+        "  {:<14}  {:>6}  {:>7}  {:>10}  {:>10}  {:>9}  {:>5}",
+        "class", "files", "bits/B", "logical", "saved", "redundant", "share"
+    )?;
+    for (class, c) in [EntropyClass::LOW, EntropyClass::MEDIUM, EntropyClass::HIGH]
+        .iter()
+        .zip(&classes)
+    {
+        writeln!(
+            out,
+            "  {:<14}  {:>6}  {:>7.2}  {:>10}  {:>10}  {:>8}%  {:>4}%",
+            class.label(),
+            c.fileCount,
+            c.averageEntropy(),
+            fmtSize(c.logicalSize as usize),
+            fmtSize(c.savedSize as usize),
+            (c.savedSize * 100).checked_div(c.logicalSize).unwrap_or(0),
+            (c.savedSize * 100).checked_div(totalSaved).unwrap_or(0)
+        )?;
+    }
+
+    // High-entropy data that is not duplicated will not shrink with any engine.
+    let high = &classes[EntropyClass::HIGH as usize];
+    let totalSize: u64 = classes.iter().map(|c| c.logicalSize).sum();
+    if high.logicalSize > 0 {
+        writeln!(
+            out,
+            "  {} ({}%) is encrypted or already compressed, {} of it is unique",
+            fmtSize(high.logicalSize as usize),
+            (high.logicalSize * 100).checked_div(totalSize).unwrap_or(0),
+            fmtSize((high.logicalSize - high.savedSize) as usize)
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::FileRecord;
+
+    fn createFile(fileIndex: u32, entropy: Option<f32>, size: u64, dupSize: u64) -> FileRecord {
+        FileRecord {
+            fileIndex,
+            path: format!("/f{}", fileIndex).into_bytes(),
+            size,
+            dupSize,
+            entropy,
+            ..Default::default()
+        }
+    }
+
+    fn createTrace() -> Trace {
+        Trace {
+            files: vec![
+                createFile(0, Some(4.0), 100, 50),
+                createFile(1, Some(4.5), 300, 250),
+                createFile(2, Some(6.0), 100, 0),
+                createFile(3, Some(7.99), 400, 100),
+                createFile(4, None, 1000, 1000),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testEntropyClass() {
+        assert_eq!(EntropyClass::of(0.0), EntropyClass::LOW);
+        assert_eq!(EntropyClass::of(5.0), EntropyClass::MEDIUM);
+        assert_eq!(EntropyClass::of(7.5), EntropyClass::HIGH);
+        assert_eq!(EntropyClass::of(8.0), EntropyClass::HIGH);
+    }
+
+    #[test]
+    fn testGroupByEntropy() {
+        let classes = groupByEntropy(&createTrace());
+        let low = &classes[EntropyClass::LOW as usize];
+        assert_eq!(low.fileCount, 2);
+        assert_eq!(low.logicalSize, 400);
+        assert_eq!(low.savedSize, 300);
+        assert_eq!(low.averageEntropy(), 4.375);
+        assert_eq!(classes[EntropyClass::MEDIUM as usize].fileCount, 1);
+        // The unmeasured file is not counted.
+        assert_eq!(classes[EntropyClass::HIGH as usize].savedSize, 100);
+    }
+
+    #[test]
+    fn testWriteEntropy() {
+        let mut out = Vec::new();
+        writeEntropy(&mut out, &createTrace()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        assert!(lines[2].starts_with("  low") && lines[2].ends_with("75%"));
+        assert!(lines[4].starts_with("  high") && lines[4].ends_with("25%"));
+        assert!(lines[5].contains("(44%) is encrypted"));
+
+        let mut trace = createTrace();
+        trace.files.iter_mut().for_each(|f| f.entropy = None);
+        let mut out = Vec::new();
+        writeEntropy(&mut out, &trace).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("--entropy"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct TypeStats {
+    pub fileCount: u64,
+    pub logicalSize: u64,
+    /// Size after deduplicating the files of this type among themselves.
+    pub uniqueSize: u64,
+    /// Duplicate bytes the tracer found in files of this type. Unlike the
+    /// difference between logical and unique size this includes data shared
+    /// with other types, so the savings of all types add up to the total.
+    pub savedSize: u64,
+}
+
+impl TypeStats {
+    pub fn dedupRatio(&self) -> f64 {
+        if self.uniqueSize == 0 {
+            1.0
+        } else {
+            self.logicalSize as f64 / self.uniqueSize as f64
+        }
+    }
+}
+
+/// Group files by type. Without chunk records the unique size falls back to the
+/// bytes the tracer saw first in files of that type.
+pub fn groupByType(trace: &Trace) -> BTreeMap<String, TypeStats> {
+    let mut result: BTreeMap<String, TypeStats> = BTreeMap::new();
+    let mut digests: HashMap<&str, HashMap<[u8; 32], u32>> = HashMap::new();
+    let hasChunks = !trace.chunks.is_empty();
+
+    for f in &trace.files {
+        let stats = result.entry(f.fileType.clone()).or_default();
+        stats.fileCount += 1;
+        stats.logicalSize += f.size;
+        stats.savedSize += f.dupSize;
+
+        if hasChunks {
+            let seen = digests.entry(f.fileType.as_str()).or_default();
+            for c in trace.chunksOf(f.fileIndex) {
+                seen.insert(c.digest, c.length);
+            }
+        } else {
+            stats.uniqueSize += f.size - f.dupSize;
+        }
+    }
+
+    // The logical size is that of the whole files, the chunks are a sample.
+    let scale = 1u64 << trace.header.sampleBits;
+    for (fileType, seen) in digests {
+        result.get_mut(fileType).unwrap().uniqueSize =
+            seen.values().map(|&l| l as u64 * scale).sum();
+    }
+
+    result
+}
+
+pub fn writeFileTypes(out: &mut dyn Write, trace: &Trace) -> io::Result<()> {
+    let types = groupByType(trace);
+    if types.is_empty() {
+        return Ok(());
+    }
+    let totalSaved: u64 = types.values().map(|t| t.savedSize).sum();
+
+    let mut rows: Vec<(&String, &TypeStats)> = types.iter().collect();
+    rows.sort_by(|a, b| b.1.savedSize.cmp(&a.1.savedSize).then(a.0.cmp(b.0)));
+
+    writeln!(out, "File types:")?;
+    writeln!(
+        out,
+        "  {:<12}  {:>6}  {:>10}  {:>10}  {:>6}  {:>10}  {:>5}",
+        "type", "files", "logical", "unique", "ratio", "saved", "share"
+    )?;
+    for (fileType, t) in rows {
+        writeln!(
+            out,
+            "  {:<12}  {:>6}  {:>10}  {:>10}  {:>6.2}  {:>10}  {:>4}%",
+            fileType,
+            t.fileCount,
+            fmtSize(t.logicalSize as usize),
+            fmtSize(t.uniqueSize as usize),
+            t.dedupRatio(),
+            fmtSize(t.savedSize as usize),
+            (t.savedSize * 100).checked_div(totalSaved).unwrap_or(0)
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::trace::sampling::isSampled;
+    use crate::util::traceFile::{ChunkRecord, FileRecord};
+
+    fn createFile(fileIndex: u32, fileType: &str, size: u64, dupSize: u64) -> FileRecord {
+        FileRecord {
+            fileIndex,
+            path: format!("/f{}", fileIndex).into_bytes(),
+            size,
+            dupSize,
+            fileType: fileType.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn createChunk(fileIndex: u32, offset: u64, fill: u8) -> ChunkRecord {
+        ChunkRecord {
+            fileIndex,
+            offset,
+            length: 100,
+            flags: 0,
+            digest: [fill; 32],
+            ..Default::default()
+        }
+    }
+
+    fn createTrace() -> Trace {
+        Trace {
+            files: vec![
+                createFile(0, "qcow2", 300, 0),
+                createFile(1, "qcow2", 300, 200),
+                createFile(2, "jpeg", 100, 100),
+            ],
+            chunks: vec![
+                createChunk(0, 0, 1),
+                createChunk(0, 100, 2),
+                createChunk(0, 200, 3),
+                createChunk(1, 0, 1),
+                createChunk(1, 100, 2),
+                createChunk(1, 200, 4),
+                createChunk(2, 0, 3),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testGroupByType() {
+        let types = groupByType(&createTrace());
+        let qcow2 = &types["qcow2"];
+        assert_eq!(qcow2.fileCount, 2);
+        assert_eq!(qcow2.logicalSize, 600);
+        assert_eq!(qcow2.uniqueSize, 400);
+        assert_eq!(qcow2.savedSize, 200);
+        assert_eq!(qcow2.dedupRatio(), 1.5);
+
+        // The JPEG only duplicates data from another type.
+        let jpeg = &types["jpeg"];
+        assert_eq!(jpeg.uniqueSize, 100);
+        assert_eq!(jpeg.savedSize, 100);
+    }
+
+    #[test]
+    fn testGroupByTypeSampled() {
+        // Two files of 4000 chunks sharing 2000, sampled by fingerprint.
+        let createTrace = |sampleBits: u8| {
+            let mut trace = Trace::default();
+            trace.header.sampleBits = sampleBits;
+            for fileIndex in 0..2u32 {
+                trace
+                    .files
+                    .push(createFile(fileIndex, "qcow2", 4000 * 100, 0));
+                for i in 0..4000u32 {
+                    let digest = *blake3::hash(&(i + fileIndex * 2000).to_le_bytes()).as_bytes();
+                    if isSampled(&digest, sampleBits) {
+                        trace.chunks.push(ChunkRecord {
+                            fileIndex,
+                            offset: i as u64 * 100,
+                            length: 100,
+                            digest,
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+            trace
+        };
+        let exact = groupByType(&createTrace(0))["qcow2"].dedupRatio();
+        let sampled = groupByType(&createTrace(3))["qcow2"].dedupRatio();
+        assert_eq!(exact, 8000.0 / 6000.0);
+        assert!((sampled / exact - 1.0).abs() < 0.1, "{sampled}");
+    }
+
+    #[test]
+    fn testGroupByTypeWithoutChunks() {
+        let mut trace = createTrace();
+        trace.chunks.clear();
+        let types = groupByType(&trace);
+        assert_eq!(types["qcow2"].uniqueSize, 400);
+        assert_eq!(types["jpeg"].uniqueSize, 0);
+    }
+
+    #[test]
+    fn testWriteFileTypes() {
+        let mut out = Vec::new();
+        writeFileTypes(&mut out, &createTrace()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        assert!(lines[2].trim_start().starts_with("qcow2"));
+        assert!(lines[2].ends_with("66%"));
+        assert!(lines[3].trim_start().starts_with("jpeg"));
+    }
+}
@@ -1 +1,19 @@
+pub mod bloomFilter;
+pub mod cacheSimulation;
+pub mod chunkSizes;
+pub mod churn;
+pub mod containers;
+pub mod directories;
+pub mod duplicateFiles;
+pub mod entropy;
+pub mod fileTypes;
+pub mod overlap;
 pub mod parser;
+pub mod popularity;
+pub mod report;
+pub mod resemblance;
+pub mod retention;
+pub mod sampling;
+pub mod similarFiles;
+pub mod sketches;
+pub mod sparseIndex;
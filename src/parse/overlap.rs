@@ -0,0 +1,290 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::csv::csvField;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Unique chunks shared between traces, for deciding which datasets to put
+/// behind a common dedup target. Traces are compared by their chunk records,
+/// so they need the same chunker, hasher, salt and sampling.
+#[derive(Default)]
+pub struct Overlap {
+    chunker: String,
+    hasher: String,
+    saltVerifier: Vec<u8>,
+    sampleBits: u8,
+    paths: Vec<PathBuf>,
+    /// Length of every distinct chunk and the traces that contain it.
+    chunks: HashMap<[u8; 32], (u32, Vec<usize>)>,
+    skipped: Vec<(PathBuf, &'static str)>,
+}
+
+/// Shared unique bytes of every pair of traces, scaled up for sampled traces.
+#[derive(Debug, Default, PartialEq)]
+pub struct OverlapMatrix {
+    pub uniqueSizes: Vec<u64>,
+    /// `shared[i][j]` is the size of the chunks in both trace i and j.
+    pub shared: Vec<Vec<u64>>,
+    /// Unique bytes a trace adds to a pool that already holds all the others.
+    pub marginalSizes: Vec<u64>,
+    pub poolSize: u64,
+}
+
+impl OverlapMatrix {
+    /// Shared unique bytes relative to the unique bytes of both traces together.
+    pub fn jaccard(&self, i: usize, j: usize) -> f64 {
+        let union = self.uniqueSizes[i] + self.uniqueSizes[j] - self.shared[i][j];
+        if union == 0 {
+            0.0
+        } else {
+            self.shared[i][j] as f64 / union as f64
+        }
+    }
+}
+
+impl Overlap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, path: &Path, trace: &Trace) {
+        if trace.chunks.is_empty() {
+            self.skipped.push((
+                path.to_path_buf(),
+                "no chunk records, trace with --log-fingerprints",
+            ));
+            return;
+        }
+        if self.paths.is_empty() {
+            self.chunker = trace.header.chunker.clone();
+            self.hasher = trace.header.hasher.clone();
+            self.saltVerifier = trace.header.saltVerifier.clone();
+            self.sampleBits = trace.header.sampleBits;
+        } else if trace.header.chunker != self.chunker {
+            self.skipped.push((path.to_path_buf(), "different chunker"));
+            return;
+        } else if trace.header.hasher != self.hasher {
+            self.skipped
+                .push((path.to_path_buf(), "different hash function"));
+            return;
+        } else if trace.header.saltVerifier != self.saltVerifier {
+            self.skipped.push((path.to_path_buf(), "different --salt"));
+            return;
+        } else if trace.header.sampleBits != self.sampleBits {
+            self.skipped
+                .push((path.to_path_buf(), "different --sample-bits"));
+            return;
+        }
+
+        let index = self.paths.len();
+        self.paths.push(path.to_path_buf());
+        for c in &trace.chunks {
+            let (_, traces) = self
+                .chunks
+                .entry(c.digest)
+                .or_insert_with(|| (c.length, Vec::new()));
+            if traces.last() != Some(&index) {
+                traces.push(index);
+            }
+        }
+    }
+
+    pub fn matrix(&self) -> OverlapMatrix {
+        let n = self.paths.len();
+        let scale = 1u64 << self.sampleBits;
+        let mut m = OverlapMatrix {
+            uniqueSizes: vec![0; n],
+            shared: vec![vec![0; n]; n],
+            marginalSizes: vec![0; n],
+            poolSize: 0,
+        };
+        for (length, traces) in self.chunks.values() {
+            let length = *length as u64 * scale;
+            m.poolSize += length;
+            if let [only] = traces[..] {
+                m.marginalSizes[only] += length;
+            }
+            for &i in traces {
+                m.uniqueSizes[i] += length;
+                for &j in traces {
+                    m.shared[i][j] += length;
+                }
+            }
+        }
+        m
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Overlap of {} traces:", self.paths.len())?;
+        for (path, reason) in &self.skipped {
+            writeln!(out, "  skipped {}: {}", path.display(), reason)?;
+        }
+        if self.paths.is_empty() {
+            return writeln!(out);
+        }
+        let m = self.matrix();
+
+        // Each cell holds the shared unique bytes and their Jaccard similarity.
+        write!(out, "  {:>3}  {:>10}", "#", "unique")?;
+        for j in 0..self.paths.len() {
+            write!(out, "  {:>15}", j + 1)?;
+        }
+        writeln!(out)?;
+        for i in 0..self.paths.len() {
+            write!(
+                out,
+                "  {:>3}  {:>10}",
+                i + 1,
+                fmtSize(m.uniqueSizes[i] as usize)
+            )?;
+            for j in 0..self.paths.len() {
+                if i == j {
+                    write!(out, "  {:>15}", "-")?;
+                } else {
+                    write!(
+                        out,
+                        "  {:>10} {:.2}",
+                        fmtSize(m.shared[i][j] as usize),
+                        m.jaccard(i, j)
+                    )?;
+                }
+            }
+            writeln!(out)?;
+        }
+        writeln!(out)?;
+
+        writeln!(
+            out,
+            "  a pool of all traces holds {} unique",
+            fmtSize(m.poolSize as usize)
+        )?;
+        writeln!(
+            out,
+            "  {:>3}  {:>10}  {:>10}  {:>5}  trace",
+            "#", "unique", "marginal", "share"
+        )?;
+        for (i, path) in self.paths.iter().enumerate() {
+            writeln!(
+                out,
+                "  {:>3}  {:>10}  {:>10}  {:>4}%  {}",
+                i + 1,
+                fmtSize(m.uniqueSizes[i] as usize),
+                fmtSize(m.marginalSizes[i] as usize),
+                (m.marginalSizes[i] * 100)
+                    .checked_div(m.uniqueSizes[i])
+                    .unwrap_or(0),
+                path.display()
+            )?;
+        }
+        writeln!(out)
+    }
+
+    /// One row for every ordered pair of traces, including each trace with itself.
+    pub fn writeCsv(&self, out: &mut dyn Write) -> io::Result<()> {
+        let m = self.matrix();
+        writeln!(
+            out,
+            "trace,other,unique_bytes,marginal_bytes,shared_bytes,jaccard"
+        )?;
+        for (i, a) in self.paths.iter().enumerate() {
+            for (j, b) in self.paths.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{:.6}",
+                    csvField(&a.to_string_lossy()),
+                    csvField(&b.to_string_lossy()),
+                    m.uniqueSizes[i],
+                    m.marginalSizes[i],
+                    m.shared[i][j],
+                    m.jaccard(i, j)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, TraceHeader};
+
+    /// A trace of 1 KiB chunks with the given ids.
+    fn createTrace(ids: &[u8]) -> Trace {
+        Trace {
+            header: TraceHeader {
+                hasher: "BLAKE3".to_string(),
+                ..Default::default()
+            },
+            chunks: ids
+                .iter()
+                .map(|&id| ChunkRecord {
+                    length: 1024,
+                    digest: [id; 32],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn createOverlap() -> Overlap {
+        let mut overlap = Overlap::new();
+        overlap.add(Path::new("a"), &createTrace(&[1, 2, 3, 4, 4]));
+        overlap.add(Path::new("b"), &createTrace(&[3, 4, 5, 6]));
+        overlap.add(Path::new("c, d"), &createTrace(&[7]));
+        overlap
+    }
+
+    #[test]
+    fn testMatrix() {
+        let m = createOverlap().matrix();
+        assert_eq!(m.uniqueSizes, vec![4096, 4096, 1024]);
+        assert_eq!(m.shared[0][1], 2048);
+        assert_eq!(m.shared[1][0], 2048);
+        assert_eq!(m.shared[0][2], 0);
+        assert_eq!(m.jaccard(0, 1), 2048.0 / 6144.0);
+        assert_eq!(m.marginalSizes, vec![2048, 2048, 1024]);
+        assert_eq!(m.poolSize, 7 * 1024);
+    }
+
+    #[test]
+    fn testSkipsIncompatibleTraces() {
+        let mut overlap = createOverlap();
+        let mut sampled = createTrace(&[1]);
+        sampled.header.sampleBits = 3;
+        overlap.add(Path::new("e"), &sampled);
+        overlap.add(Path::new("f"), &Trace::default());
+        let mut otherChunker = createTrace(&[1]);
+        otherChunker.header.chunker = "FastCDC-4K".to_string();
+        overlap.add(Path::new("g"), &otherChunker);
+        let mut salted = createTrace(&[1]);
+        salted.header.saltVerifier = vec![1, 2, 3, 4];
+        overlap.add(Path::new("h"), &salted);
+        assert_eq!(overlap.paths.len(), 3);
+
+        let mut out = Vec::new();
+        overlap.write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("skipped e: different --sample-bits"));
+        assert!(text.contains("skipped f: no chunk records"));
+        assert!(text.contains("skipped g: different chunker"));
+        assert!(text.contains("skipped h: different --salt"));
+        assert!(text.contains("a pool of all traces holds 7 KiB unique"));
+    }
+
+    #[test]
+    fn testWriteCsv() {
+        let mut out = Vec::new();
+        createOverlap().writeCsv(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + 9);
+        assert_eq!(lines[2], "a,b,4096,2048,2048,0.333333");
+        assert_eq!(lines[9], "\"c, d\",\"c, d\",1024,1024,1024,1.000000");
+    }
+}
@@ -1,5 +1,159 @@
-use crate::util::arguments::ParseArgs;
+use crate::parse::churn::Churn;
+use crate::parse::containers::ContainerStore;
+use crate::parse::overlap::Overlap;
+use crate::parse::retention::RetentionSimulation;
+use crate::parse::sketches::SketchUnion;
+use crate::parse::{
+    bloomFilter, cacheSimulation, chunkSizes, containers, directories, duplicateFiles, entropy,
+    fileTypes, popularity, report, resemblance, similarFiles, sparseIndex,
+};
+use crate::util::arguments::{ParseArgs, ReportFormat};
+use crate::util::traceFile::Trace;
 
-pub fn run(_args: &ParseArgs) {
-    println!("Hello from the parser.\n");
+use std::{
+    fs::File,
+    io::{self, BufWriter, Error, Write},
+};
+
+pub fn run(args: &ParseArgs) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match args.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut union = SketchUnion::new();
+    let mut overlap = Overlap::new();
+    let mut churn = Churn::new(args.top);
+    // Every trace is the next backup generation in the simulated stores.
+    let mut stores: Vec<ContainerStore> = args
+        .rewrite
+        .iter()
+        .map(|&strategy| ContainerStore::new(args.containerSize, strategy))
+        .collect();
+    let mut retention = args
+        .retention
+        .map(|policy| RetentionSimulation::new(policy, args.daily));
+
+    // JSON goes to other tools, so it is written without the text reports.
+    if args.duplicateFiles == Some(ReportFormat::JSON) {
+        for path in &args.fileNames {
+            let trace = Trace::read(path)
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            duplicateFiles::writeDuplicateFiles(
+                &mut out,
+                path,
+                &trace,
+                ReportFormat::JSON,
+                args.top,
+            )?;
+        }
+        return out.flush();
+    }
+
+    for path in &args.fileNames {
+        let trace = Trace::read(path)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+        report::writeSummary(&mut out, path, &trace)?;
+        report::writeTopFiles(&mut out, &trace, args.top)?;
+
+        if args.directories {
+            directories::writeDirectories(&mut out, &trace, args.depth, args.sort)?;
+        }
+
+        if args.types {
+            fileTypes::writeFileTypes(&mut out, &trace)?;
+        }
+
+        if args.chunkSizes {
+            chunkSizes::writeChunkSizes(&mut out, &trace)?;
+        }
+
+        if args.popularity {
+            popularity::writePopularity(&mut out, &trace, args.top)?;
+        }
+
+        if args.entropy {
+            entropy::writeEntropy(&mut out, &trace)?;
+        }
+
+        if args.resemblance {
+            resemblance::writeResemblance(&mut out, &trace)?;
+        }
+
+        if let Some(format) = args.duplicateFiles {
+            duplicateFiles::writeDuplicateFiles(&mut out, path, &trace, format, args.top)?;
+        }
+
+        if args.similarFiles {
+            similarFiles::writeSimilarFiles(&mut out, &trace, args.similarity, args.top)?;
+        }
+
+        if !args.simulateCache.is_empty() {
+            cacheSimulation::writeCacheSimulation(
+                &mut out,
+                &trace,
+                &args.simulateCache,
+                args.cacheSize,
+            )?;
+        }
+
+        if args.simulateContainers {
+            containers::writeGeneration(&mut out, path, &trace, &mut stores, args.top)?;
+        }
+
+        if !args.sparseIndex.is_empty() {
+            sparseIndex::writeSparseIndex(
+                &mut out,
+                &trace,
+                &args.sparseIndex,
+                args.segmentSize,
+                args.champions,
+            )?;
+        }
+
+        if !args.simulateBloom.is_empty() {
+            bloomFilter::writeBloom(&mut out, &trace, &args.simulateBloom)?;
+        }
+
+        if args.union {
+            union.add(path, &trace);
+        }
+
+        if args.overlap {
+            overlap.add(path, &trace);
+        }
+
+        if args.churn {
+            churn.add(path, &trace);
+        }
+
+        if let Some(ref mut retention) = retention {
+            retention.add(path, &trace);
+        }
+    }
+
+    if args.union {
+        union.write(&mut out)?;
+    }
+
+    if args.overlap {
+        overlap.write(&mut out)?;
+    }
+
+    if args.churn {
+        churn.write(&mut out)?;
+    }
+
+    if let Some(ref retention) = retention {
+        retention.write(&mut out)?;
+    }
+
+    if let Some(ref path) = args.overlapCsv {
+        let mut csv = BufWriter::new(File::create(path)?);
+        overlap.writeCsv(&mut csv)?;
+        csv.flush()?;
+    }
+
+    out.flush()
 }
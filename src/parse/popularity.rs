@@ -0,0 +1,260 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+/// Number of files listed for each of the most referenced chunks.
+const SAMPLE_FILES: usize = 3;
+
+/// How often one fingerprint occurs in a trace.
+#[derive(Debug, Default, PartialEq)]
+pub struct ChunkPopularity {
+    pub digest: [u8; 32],
+    pub length: u32,
+    pub refCount: u64,
+    /// The first few distinct files that contain the chunk, by file index.
+    pub sampleFiles: Vec<u32>,
+}
+
+impl ChunkPopularity {
+    /// Bytes deduplication saves on this chunk.
+    pub fn savedSize(&self) -> u64 {
+        (self.refCount - 1) * self.length as u64
+    }
+}
+
+/// Range of reference counts `(lowest, highest)` a count is reported in:
+/// 1, 2, 3–10, 11–100, 101–1000 and so on.
+pub fn refCountBucket(refCount: u64) -> (u64, u64) {
+    if refCount <= 2 {
+        return (refCount, refCount);
+    }
+    let mut highest = 10;
+    while highest < refCount {
+        highest = highest.saturating_mul(10);
+    }
+    let lowest = if highest == 10 { 3 } else { highest / 10 + 1 };
+    (lowest, highest)
+}
+
+/// Count the references of every fingerprint in the chunk records.
+pub fn countReferences(trace: &Trace) -> Vec<ChunkPopularity> {
+    let mut chunks: HashMap<[u8; 32], ChunkPopularity> = HashMap::new();
+    for c in &trace.chunks {
+        let entry = chunks.entry(c.digest).or_insert_with(|| ChunkPopularity {
+            digest: c.digest,
+            length: c.length,
+            ..Default::default()
+        });
+        entry.refCount += 1;
+        // Chunks are sorted by file, so a repeated file is always the last one.
+        if entry.sampleFiles.len() < SAMPLE_FILES && entry.sampleFiles.last() != Some(&c.fileIndex)
+        {
+            entry.sampleFiles.push(c.fileIndex);
+        }
+    }
+    chunks.into_values().collect()
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BucketStats {
+    /// Distinct chunks in the bucket.
+    pub chunkCount: u64,
+    pub refCount: u64,
+    pub uniqueSize: u64,
+    pub savedSize: u64,
+}
+
+pub fn groupByRefCount(chunks: &[ChunkPopularity]) -> BTreeMap<(u64, u64), BucketStats> {
+    let mut result: BTreeMap<(u64, u64), BucketStats> = BTreeMap::new();
+    for c in chunks {
+        let stats = result.entry(refCountBucket(c.refCount)).or_default();
+        stats.chunkCount += 1;
+        stats.refCount += c.refCount;
+        stats.uniqueSize += c.length as u64;
+        stats.savedSize += c.savedSize();
+    }
+    result
+}
+
+pub fn hexDigest(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn writePopularity(out: &mut dyn Write, trace: &Trace, n: usize) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Chunk popularity needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+
+    let mut chunks = countReferences(trace);
+    let buckets = groupByRefCount(&chunks);
+    let totalSaved: u64 = buckets.values().map(|b| b.savedSize).sum();
+
+    writeln!(out, "Chunk popularity:")?;
+    writeln!(
+        out,
+        "  {:>11}  {:>10}  {:>10}  {:>10}  {:>10}  {:>5}",
+        "references", "chunks", "refs", "unique", "saved", "share"
+    )?;
+    for ((lowest, highest), b) in &buckets {
+        let range = if lowest == highest {
+            lowest.to_string()
+        } else {
+            format!("{}-{}", lowest, highest)
+        };
+        writeln!(
+            out,
+            "  {:>11}  {:>10}  {:>10}  {:>10}  {:>10}  {:>4}%",
+            range,
+            b.chunkCount,
+            b.refCount,
+            fmtSize(b.uniqueSize as usize),
+            fmtSize(b.savedSize as usize),
+            (b.savedSize * 100).checked_div(totalSaved).unwrap_or(0)
+        )?;
+    }
+    writeln!(out)?;
+
+    chunks.retain(|c| c.refCount > 1);
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    chunks.sort_by(|a, b| {
+        b.refCount
+            .cmp(&a.refCount)
+            .then(b.length.cmp(&a.length))
+            .then(a.digest.cmp(&b.digest))
+    });
+    chunks.truncate(n);
+
+    // A prefix of the digest is enough to tell the chunks apart.
+    let digestLength = trace.header.digestLength.clamp(1, 8);
+    let paths: HashMap<u32, String> = trace
+        .files
+        .iter()
+        .map(|f| (f.fileIndex, f.displayPath()))
+        .collect();
+
+    writeln!(out, "Most referenced chunks:")?;
+    writeln!(
+        out,
+        "  {:>4}  {:>8}  {:>10}  {:>10}  {:<16}  files",
+        "#", "refs", "size", "saved", "digest"
+    )?;
+    for (rank, c) in chunks.iter().enumerate() {
+        let files: Vec<&str> = c
+            .sampleFiles
+            .iter()
+            .map(|i| paths.get(i).map_or("?", |p| p.as_str()))
+            .collect();
+        writeln!(
+            out,
+            "  {:>4}  {:>8}  {:>10}  {:>10}  {:<16}  {}",
+            rank + 1,
+            c.refCount,
+            fmtSize(c.length as usize),
+            fmtSize(c.savedSize() as usize),
+            hexDigest(&c.digest[..digestLength]),
+            files.join(", ")
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, FileRecord};
+
+    fn createChunk(fileIndex: u32, offset: u64, length: u32, fill: u8) -> ChunkRecord {
+        ChunkRecord {
+            fileIndex,
+            offset,
+            length,
+            flags: 0,
+            digest: [fill; 32],
+            ..Default::default()
+        }
+    }
+
+    // Chunk 1 is a hot block repeated in every file, chunk 2 is shared once.
+    fn createTrace() -> Trace {
+        let mut chunks = Vec::new();
+        for fileIndex in 0..4 {
+            for i in 0..3 {
+                chunks.push(createChunk(fileIndex, i * 10, 10, 1));
+            }
+            chunks.push(createChunk(fileIndex, 30, 100, 10 + fileIndex as u8));
+        }
+        chunks.push(createChunk(4, 0, 50, 2));
+        chunks.push(createChunk(5, 0, 50, 2));
+        Trace {
+            files: (0..6)
+                .map(|i| FileRecord {
+                    fileIndex: i,
+                    path: format!("/f{}", i).into_bytes(),
+                    ..Default::default()
+                })
+                .collect(),
+            chunks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testRefCountBucket() {
+        assert_eq!(refCountBucket(1), (1, 1));
+        assert_eq!(refCountBucket(2), (2, 2));
+        assert_eq!(refCountBucket(3), (3, 10));
+        assert_eq!(refCountBucket(10), (3, 10));
+        assert_eq!(refCountBucket(11), (11, 100));
+        assert_eq!(refCountBucket(1000), (101, 1000));
+    }
+
+    #[test]
+    fn testCountReferences() {
+        let mut chunks = countReferences(&createTrace());
+        chunks.sort_by_key(|c| c.digest);
+        assert_eq!(chunks.len(), 6);
+
+        let hot = &chunks[0];
+        assert_eq!(hot.refCount, 12);
+        assert_eq!(hot.savedSize(), 110);
+        // Each file is sampled once, and only the first few.
+        assert_eq!(hot.sampleFiles, vec![0, 1, 2]);
+
+        let shared = &chunks[1];
+        assert_eq!(shared.refCount, 2);
+        assert_eq!(shared.sampleFiles, vec![4, 5]);
+    }
+
+    #[test]
+    fn testGroupByRefCount() {
+        let buckets = groupByRefCount(&countReferences(&createTrace()));
+        assert_eq!(buckets[&(1, 1)].chunkCount, 4);
+        assert_eq!(buckets[&(1, 1)].savedSize, 0);
+        assert_eq!(buckets[&(2, 2)].savedSize, 50);
+        assert_eq!(buckets[&(11, 100)].refCount, 12);
+        assert_eq!(buckets[&(11, 100)].savedSize, 110);
+    }
+
+    #[test]
+    fn testWritePopularity() {
+        let mut out = Vec::new();
+        writePopularity(&mut out, &createTrace(), 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("68%"));
+        let top: Vec<&str> = text
+            .lines()
+            .skip_while(|l| !l.starts_with("Most referenced"))
+            .collect();
+        assert_eq!(top.len(), 4);
+        assert!(top[2].ends_with("/f0, /f1, /f2"));
+    }
+}
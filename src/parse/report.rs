@@ -0,0 +1,388 @@
+use crate::parse::{sampling, sketches};
+use crate::trace::compression::compressionRatio;
+use crate::trace::pattern::{ChunkPattern, PatternStats};
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::{
+    FileRecord, Trace, CHUNK_COMPRESSED, CHUNK_DUPLICATE, CHUNK_REPEATED, CHUNK_ZERO,
+};
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+fn percent(part: u64, whole: u64) -> u64 {
+    (part * 100).checked_div(whole).unwrap_or(0)
+}
+
+pub fn writeSummary(out: &mut dyn Write, path: &Path, trace: &Trace) -> io::Result<()> {
+    let h = &trace.header;
+    writeln!(out, "Trace {}", path.display())?;
+    writeln!(
+        out,
+        "  {} · {} · chunkIQ {}",
+        h.chunker, h.hasher, h.toolVersion
+    )?;
+
+    // The summary is written last, so it is missing if the tracer was interrupted.
+    let (fileCount, totalSize, chunkCount, dupCount, dupSize) = match trace.summary {
+        Some(ref s) => (
+            s.fileCount,
+            s.totalSize,
+            s.chunkCount,
+            s.dupCount,
+            s.dupSize,
+        ),
+        None => {
+            writeln!(
+                out,
+                "  (no summary record, totals are taken from the file records)"
+            )?;
+            // File records are scaled up from the sample; the totals are not.
+            let (files, size, chunks, dups, dupSize) =
+                trace.files.iter().fold((0, 0, 0, 0, 0), |acc, f| {
+                    (
+                        acc.0 + 1,
+                        acc.1 + f.size,
+                        acc.2 + f.chunkCount,
+                        acc.3 + f.dupCount,
+                        acc.4 + f.dupSize,
+                    )
+                });
+            let bits = h.sampleBits;
+            (files, size, chunks >> bits, dups >> bits, dupSize >> bits)
+        }
+    };
+
+    writeln!(
+        out,
+        "  {} files, {} in {} chunks",
+        fileCount,
+        fmtSize(totalSize as usize),
+        chunkCount
+    )?;
+
+    // A sampled trace only counts the sampled chunks; its savings are scaled up.
+    let sample = if h.sampleBits > 0 {
+        sampling::estimate(trace)
+    } else {
+        None
+    };
+    let (sampledSize, savedSize) = match sample {
+        Some(ref e) => (e.sampledSize, e.savedSize(totalSize)),
+        None => (totalSize, dupSize),
+    };
+    if h.sketchOnly {
+        writeln!(out, "  duplicates were not indexed (--sketch-only)")?;
+    } else {
+        writeln!(
+            out,
+            "  {} duplicate chunks ({}%), {} saved ({}%)",
+            dupCount,
+            percent(dupCount, chunkCount),
+            fmtSize(dupSize as usize),
+            percent(dupSize, sampledSize)
+        )?;
+    }
+    if let Some(ref e) = sample {
+        writeln!(
+            out,
+            "  sampled one in {} chunks by fingerprint ({}), an estimated {} saved ({:.1}%)",
+            1u64 << h.sampleBits,
+            fmtSize(e.sampledSize as usize),
+            fmtSize(savedSize as usize),
+            e.savedFraction * 100.0
+        )?;
+        if let Some((low, high)) = e.interval {
+            writeln!(
+                out,
+                "  95% confidence interval {:.1}% to {:.1}%",
+                low * 100.0,
+                high * 100.0
+            )?;
+        }
+    }
+
+    sketches::writeSketch(out, trace)?;
+
+    // Sampled chunks stand in for all unique data.
+    let (compressedCount, inputSize, outputSize) = compressionTotals(trace);
+    if compressedCount > 0 {
+        let uniqueSize = totalSize.saturating_sub(savedSize);
+        let ratio = compressionRatio(inputSize, outputSize);
+        let compressedSize = (uniqueSize as f64 * ratio) as u64;
+        writeln!(
+            out,
+            "  {}: logical {}, deduplicated {}, deduplicated and compressed {} ({}%)",
+            h.compression,
+            fmtSize(totalSize as usize),
+            fmtSize(uniqueSize as usize),
+            fmtSize(compressedSize as usize),
+            percent(compressedSize, totalSize)
+        )?;
+        if h.compressSampleRate > 1 {
+            writeln!(
+                out,
+                "  (estimated from {} of the unique chunks, one in {})",
+                compressedCount, h.compressSampleRate
+            )?;
+        }
+    }
+
+    // Zero-filled and single-byte chunks dedup trivially and overstate the savings.
+    let patterns = patternStats(trace);
+    let trivial = patterns.trivial();
+    if trivial.chunkCount > 0 {
+        writeln!(
+            out,
+            "  {} zero-filled chunks ({}), {} single-byte chunks ({})",
+            patterns.zero.chunkCount,
+            fmtSize(patterns.zero.size as usize),
+            patterns.repeated.chunkCount,
+            fmtSize(patterns.repeated.size as usize)
+        )?;
+        let chunkCount = chunkCount.saturating_sub(trivial.chunkCount);
+        let dupCount = dupCount.saturating_sub(trivial.dupCount);
+        let sampledSize = sampledSize.saturating_sub(trivial.size);
+        let dupSize = dupSize.saturating_sub(trivial.dupSize);
+        writeln!(
+            out,
+            "  without them {} duplicate chunks ({}%), {} saved ({}%)",
+            dupCount,
+            percent(dupCount, chunkCount),
+            fmtSize(dupSize as usize),
+            percent(dupSize, sampledSize)
+        )?;
+    }
+    writeln!(out)
+}
+
+/// Number of compressed chunks and their total size before and after compression,
+/// from the summary or the chunk records.
+pub fn compressionTotals(trace: &Trace) -> (u64, u64, u64) {
+    if let Some(ref s) = trace.summary {
+        return (s.compressedCount, s.compressInputSize, s.compressOutputSize);
+    }
+    trace
+        .chunks
+        .iter()
+        .filter(|c| c.flags & CHUNK_COMPRESSED != 0)
+        .fold((0, 0, 0), |acc, c| {
+            (
+                acc.0 + 1,
+                acc.1 + c.length as u64,
+                acc.2 + c.compressedSize as u64,
+            )
+        })
+}
+
+/// Trivial chunk counters from the summary, or from the chunk flags if the
+/// summary is missing.
+pub fn patternStats(trace: &Trace) -> PatternStats {
+    if let Some(ref s) = trace.summary {
+        return s.patterns;
+    }
+    let mut stats = PatternStats::default();
+    for c in &trace.chunks {
+        let chunkPattern = if c.flags & CHUNK_ZERO != 0 {
+            ChunkPattern::ZERO
+        } else if c.flags & CHUNK_REPEATED != 0 {
+            ChunkPattern::REPEATED
+        } else {
+            continue;
+        };
+        stats.record(chunkPattern, c.length as usize, c.flags & CHUNK_DUPLICATE != 0);
+    }
+    stats
+}
+
+/// Files ordered by the number of duplicate bytes they contain, most first.
+pub fn rankFiles(trace: &Trace, n: usize) -> Vec<&FileRecord> {
+    let mut files: Vec<&FileRecord> = trace.files.iter().filter(|f| f.dupSize > 0).collect();
+    files.sort_by(|a, b| b.dupSize.cmp(&a.dupSize).then(a.path.cmp(&b.path)));
+    files.truncate(n);
+    files
+}
+
+pub fn writeTopFiles(out: &mut dyn Write, trace: &Trace, n: usize) -> io::Result<()> {
+    let ranked = rankFiles(trace, n);
+    if ranked.is_empty() {
+        return writeln!(out, "No redundant files.\n");
+    }
+
+    writeln!(out, "Most redundant files:")?;
+    writeln!(
+        out,
+        "  {:>4}  {:>10}  {:>5}  {:>10}  path",
+        "#", "duplicate", "share", "internal"
+    )?;
+    for (rank, f) in ranked.iter().enumerate() {
+        writeln!(
+            out,
+            "  {:>4}  {:>10}  {:>4}%  {:>10}  {}",
+            rank + 1,
+            fmtSize(f.dupSize as usize),
+            percent(f.dupSize, f.size),
+            fmtSize(f.internalDupSize as usize),
+            f.displayPath()
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, TraceSummary};
+
+    fn createFile(fileIndex: u32, path: &str, size: u64, dupSize: u64) -> FileRecord {
+        FileRecord {
+            fileIndex,
+            path: path.as_bytes().to_vec(),
+            size,
+            chunkCount: size / 4096,
+            dupCount: dupSize / 4096,
+            dupSize,
+            internalDupSize: dupSize / 2,
+            ..Default::default()
+        }
+    }
+
+    fn createTrace() -> Trace {
+        Trace {
+            files: vec![
+                createFile(0, "/a", 8192, 4096),
+                createFile(1, "/b", 8192, 0),
+                createFile(2, "/c", 16384, 12288),
+                createFile(3, "/d", 4096, 4096),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testRankFiles() {
+        let trace = createTrace();
+        let ranked: Vec<&str> = rankFiles(&trace, 10)
+            .iter()
+            .map(|f| std::str::from_utf8(&f.path).unwrap())
+            .collect();
+        // Ties are broken by path; files without duplicates are left out.
+        assert_eq!(ranked, vec!["/c", "/a", "/d"]);
+        assert_eq!(rankFiles(&trace, 1).len(), 1);
+    }
+
+    #[test]
+    fn testWriteTopFiles() {
+        let mut out = Vec::new();
+        writeTopFiles(&mut out, &createTrace(), 2).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.trim_end().lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].ends_with("/c"));
+        assert!(lines[2].contains("75%"));
+        assert!(lines[3].ends_with("/a"));
+    }
+
+    #[test]
+    fn testWriteSummaryWithoutSummaryRecord() {
+        let mut out = Vec::new();
+        writeSummary(&mut out, Path::new("x.trace"), &createTrace()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("no summary record"));
+        assert!(text.contains("4 files, 36 KiB in 9 chunks"));
+        assert!(!text.contains("zero-filled"));
+    }
+
+    #[test]
+    fn testWriteSummaryWithoutTrivialChunks() {
+        let mut trace = createTrace();
+        let mut summary = TraceSummary {
+            fileCount: 4,
+            totalSize: 36864,
+            chunkCount: 9,
+            dupCount: 5,
+            dupSize: 20480,
+            ..Default::default()
+        };
+        summary.patterns.zero.record(4096, false);
+        summary.patterns.zero.record(4096, true);
+        summary.patterns.zero.record(4096, true);
+        trace.summary = Some(summary);
+
+        let mut out = Vec::new();
+        writeSummary(&mut out, Path::new("x.trace"), &trace).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("3 zero-filled chunks (12 KiB), 0 single-byte chunks"));
+        assert!(text.contains("without them 3 duplicate chunks (50%), 12 KiB saved (50%)"));
+    }
+
+    #[test]
+    fn testWriteSummaryCompressed() {
+        let mut trace = createTrace();
+        trace.header.compression = "zstd:3".to_string();
+        trace.header.compressSampleRate = 4;
+        // Half of the 16 KiB of unique data, compressed to a quarter.
+        for (offset, compressedSize) in [(0, 1024), (4096, 1024)] {
+            trace.chunks.push(ChunkRecord {
+                offset,
+                length: 4096,
+                flags: CHUNK_COMPRESSED,
+                compressedSize,
+                ..Default::default()
+            });
+        }
+        assert_eq!(compressionTotals(&trace), (2, 8192, 2048));
+
+        let mut out = Vec::new();
+        writeSummary(&mut out, Path::new("x.trace"), &trace).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains(
+            "zstd:3: logical 36 KiB, deduplicated 16 KiB, deduplicated and compressed 4 KiB (11%)"
+        ));
+        assert!(text.contains("one in 4"));
+    }
+
+    #[test]
+    fn testWriteSummarySampled() {
+        let mut trace = createTrace();
+        trace.header.sampleBits = 4;
+        // One chunk kept twice, one once: a third of the sampled bytes is saved.
+        for (fileIndex, fill) in [(0, 1), (1, 1), (2, 2)] {
+            trace.chunks.push(ChunkRecord {
+                fileIndex,
+                length: 4096,
+                digest: [fill; 32],
+                ..Default::default()
+            });
+        }
+
+        let mut out = Vec::new();
+        writeSummary(&mut out, Path::new("x.trace"), &trace).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("one in 16 chunks by fingerprint (12 KiB), an estimated 12 KiB saved (33.3%)"));
+        assert!(text.contains("95% confidence interval"));
+    }
+
+    #[test]
+    fn testPatternStatsFromChunkFlags() {
+        let mut trace = createTrace();
+        for (flags, length) in [
+            (CHUNK_ZERO, 10),
+            (CHUNK_ZERO | CHUNK_DUPLICATE, 10),
+            (CHUNK_REPEATED, 5),
+            (CHUNK_DUPLICATE, 100),
+        ] {
+            trace.chunks.push(ChunkRecord {
+                flags,
+                length,
+                ..Default::default()
+            });
+        }
+        let stats = patternStats(&trace);
+        assert_eq!(stats.zero.chunkCount, 2);
+        assert_eq!(stats.zero.dupSize, 10);
+        assert_eq!(stats.repeated.size, 5);
+        assert_eq!(stats.trivial().dupCount, 1);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::trace::resemblance::{superFeatures, Features, FEATURE_COUNT, SUPER_FEATURE_COUNT};
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ResemblanceStats {
+    /// Unique chunks with features, and their bytes.
+    pub chunkCount: u64,
+    pub size: u64,
+    /// Unique chunks that share a super-feature with an earlier unique chunk.
+    pub similarCount: u64,
+    pub similarSize: u64,
+    /// Similar chunks by the number of super-features shared with their base.
+    pub bySharedSuperFeatures: [u64; SUPER_FEATURE_COUNT],
+    /// Bytes a delta against the base would save.
+    pub deltaSavedSize: u64,
+}
+
+fn sharedFeatures(a: &Features, b: &Features) -> usize {
+    a.iter().zip(b).filter(|(x, y)| x == y).count()
+}
+
+/// Look for a delta base for every unique chunk among the unique chunks before
+/// it. A base shares at least one super-feature; among several candidates the
+/// one sharing the most features wins. The share of shared features estimates
+/// the share of content in common, which is what a delta saves.
+pub fn findSimilar(trace: &Trace) -> ResemblanceStats {
+    let mut stats = ResemblanceStats::default();
+    // The latest chunk with each super-feature, by position of the super-feature.
+    let mut bases: HashMap<(usize, u64), usize> = HashMap::new();
+
+    for (i, c) in trace.chunks.iter().enumerate() {
+        let Some(ref features) = c.features else {
+            continue;
+        };
+        // Chunks shorter than a window have no features.
+        if features.iter().all(|&f| f == 0) {
+            continue;
+        }
+        stats.chunkCount += 1;
+        stats.size += c.length as u64;
+
+        let sfs = superFeatures(features);
+        let best = sfs
+            .iter()
+            .enumerate()
+            .filter_map(|(j, sf)| bases.get(&(j, *sf)))
+            .filter_map(|&base| trace.chunks[base].features.as_ref().map(|f| (base, f)))
+            .max_by_key(|(base, f)| (sharedFeatures(features, f), *base));
+        if let Some((_, base)) = best {
+            let shared = sharedFeatures(features, base);
+            let sharedSfs = superFeatures(base)
+                .iter()
+                .zip(&sfs)
+                .filter(|(a, b)| a == b)
+                .count();
+            stats.similarCount += 1;
+            stats.similarSize += c.length as u64;
+            stats.bySharedSuperFeatures[sharedSfs - 1] += 1;
+            stats.deltaSavedSize += c.length as u64 * shared as u64 / FEATURE_COUNT as u64;
+        }
+
+        for (j, sf) in sfs.into_iter().enumerate() {
+            bases.insert((j, sf), i);
+        }
+    }
+    stats
+}
+
+pub fn writeResemblance(out: &mut dyn Write, trace: &Trace) -> io::Result<()> {
+    let stats = findSimilar(trace);
+    if stats.chunkCount == 0 {
+        return writeln!(
+            out,
+            "Resemblance needs chunk features, trace with --super-features.\n"
+        );
+    }
+
+    writeln!(out, "Resemblance of unique chunks:")?;
+    writeln!(
+        out,
+        "  {} unique chunks ({}) with features",
+        stats.chunkCount,
+        fmtSize(stats.size as usize)
+    )?;
+    writeln!(
+        out,
+        "  {} ({}%) resemble an earlier chunk, {} ({}%)",
+        stats.similarCount,
+        (stats.similarCount * 100) / stats.chunkCount,
+        fmtSize(stats.similarSize as usize),
+        (stats.similarSize * 100)
+            .checked_div(stats.size)
+            .unwrap_or(0)
+    )?;
+    let shared: Vec<String> = stats
+        .bySharedSuperFeatures
+        .iter()
+        .enumerate()
+        .map(|(n, count)| format!("{} share {}", count, n + 1))
+        .collect();
+    writeln!(
+        out,
+        "  super-features shared with the base: {}",
+        shared.join(", ")
+    )?;
+    writeln!(
+        out,
+        "  delta compression could save an estimated {} ({}%) on top of dedup",
+        fmtSize(stats.deltaSavedSize as usize),
+        (stats.deltaSavedSize * 100)
+            .checked_div(stats.size)
+            .unwrap_or(0)
+    )?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::trace::resemblance::features;
+    use crate::util::traceFile::{ChunkRecord, CHUNK_FEATURES};
+
+    fn randomData(seed: u64, length: usize) -> Vec<u8> {
+        let mut x = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+        (0..length)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn createChunk(data: &[u8]) -> ChunkRecord {
+        ChunkRecord {
+            length: data.len() as u32,
+            flags: CHUNK_FEATURES,
+            digest: *blake3::hash(data).as_bytes(),
+            features: Some(Box::new(features(data))),
+            ..Default::default()
+        }
+    }
+
+    fn createTrace() -> Trace {
+        let base = randomData(1, 8192);
+        let mut edited = base.clone();
+        edited[100..200].fill(0);
+        Trace {
+            chunks: vec![
+                createChunk(&base),
+                createChunk(&randomData(2, 8192)),
+                createChunk(&edited),
+                createChunk(&[5; 32]),
+                // A duplicate carries no features.
+                ChunkRecord {
+                    length: 8192,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testFindSimilar() {
+        let stats = findSimilar(&createTrace());
+        assert_eq!(stats.chunkCount, 3);
+        assert_eq!(stats.size, 3 * 8192);
+        assert_eq!(stats.similarCount, 1);
+        assert_eq!(stats.similarSize, 8192);
+        assert_eq!(stats.bySharedSuperFeatures.iter().sum::<u64>(), 1);
+        // A small edit keeps most features.
+        assert!(stats.deltaSavedSize >= 8192 * 9 / 12);
+    }
+
+    #[test]
+    fn testWriteResemblance() {
+        let mut out = Vec::new();
+        writeResemblance(&mut out, &createTrace()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("3 unique chunks (24 KiB) with features"));
+        assert!(text.contains("1 (33%) resemble an earlier chunk, 8 KiB (33%)"));
+
+        let mut out = Vec::new();
+        writeResemblance(&mut out, &Trace::default()).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("--super-features"));
+    }
+}
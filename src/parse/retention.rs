@@ -0,0 +1,428 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// How many daily, weekly, monthly and yearly generations to keep. In every
+/// period the newest generation is kept, and the newest generation overall is
+/// always kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    pub daily: usize,
+    pub weekly: usize,
+    pub monthly: usize,
+    pub yearly: usize,
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
+
+    /// Accepts a comma-separated list like `7d,4w,12m,2y`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = RetentionPolicy::default();
+        for rule in s.split(',') {
+            let rule = rule.trim();
+            let Some(unit) = rule.chars().last() else {
+                continue;
+            };
+            let count: usize = rule[..rule.len() - unit.len_utf8()]
+                .parse()
+                .map_err(|_| format!("Invalid retention rule {:?}", rule))?;
+            match unit.to_ascii_lowercase() {
+                'd' => policy.daily = count,
+                'w' => policy.weekly = count,
+                'm' => policy.monthly = count,
+                'y' => policy.yearly = count,
+                _ => {
+                    return Err(format!(
+                        "Unknown retention period in {:?}, expected d, w, m or y",
+                        rule
+                    ));
+                }
+            }
+        }
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rules: Vec<String> = [
+            (self.daily, 'd'),
+            (self.weekly, 'w'),
+            (self.monthly, 'm'),
+            (self.yearly, 'y'),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect();
+        if rules.is_empty() {
+            write!(f, "latest only")
+        } else {
+            write!(f, "{}", rules.join(","))
+        }
+    }
+}
+
+/// Year, month and day of a day number counted from 1970-01-01.
+pub fn civilDate(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let dayOfEra = z.rem_euclid(146097);
+    let yearOfEra = (dayOfEra - dayOfEra / 1460 + dayOfEra / 36524 - dayOfEra / 146096) / 365;
+    let dayOfYear = dayOfEra - (365 * yearOfEra + yearOfEra / 4 - yearOfEra / 100);
+    let mp = (5 * dayOfYear + 2) / 153;
+    let day = (dayOfYear - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yearOfEra + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Maps a day number to the period it falls in.
+type Period = fn(i64) -> i64;
+
+/// Which generations a policy keeps, given their days in the order they were
+/// taken. The last one is the newest.
+pub fn retained(policy: &RetentionPolicy, days: &[i64]) -> Vec<bool> {
+    let mut keep = vec![false; days.len()];
+    let periods: [(usize, Period); 4] = [
+        (policy.daily, |d| d),
+        // Weeks start on Monday; 1970-01-01 was a Thursday.
+        (policy.weekly, |d| (d + 3).div_euclid(7)),
+        (policy.monthly, |d| {
+            let (year, month, _) = civilDate(d);
+            year * 12 + month as i64
+        }),
+        (policy.yearly, |d| civilDate(d).0),
+    ];
+    for (count, period) in periods {
+        let mut last = None;
+        let mut kept = 0;
+        for i in (0..days.len()).rev() {
+            let key = period(days[i]);
+            if last == Some(key) {
+                continue;
+            }
+            if kept == count {
+                break;
+            }
+            keep[i] = true;
+            kept += 1;
+            last = Some(key);
+        }
+    }
+    if let Some(newest) = keep.last_mut() {
+        *newest = true;
+    }
+    keep
+}
+
+struct Generation {
+    day: i64,
+    /// Distinct fingerprints of the generation.
+    digests: Vec<[u8; 32]>,
+}
+
+/// State of the store after one generation was added and the policy applied.
+#[derive(Debug, Default, PartialEq)]
+pub struct GenerationRow {
+    pub path: PathBuf,
+    pub day: i64,
+    pub logicalSize: u64,
+    pub storedSize: u64,
+    pub chunkCount: u64,
+    pub freedSize: u64,
+    pub expiredCount: usize,
+    pub liveCount: usize,
+}
+
+/// A reference-counted chunk store that receives every trace as the next
+/// backup generation and expires old ones according to a retention policy.
+pub struct RetentionSimulation {
+    policy: RetentionPolicy,
+    /// Take the traces as consecutive days instead of using their start times.
+    daily: bool,
+    chunker: String,
+    hasher: String,
+    saltVerifier: Vec<u8>,
+    sampleBits: u8,
+    live: Vec<Generation>,
+    /// Length and number of live generations of every stored chunk.
+    refCounts: HashMap<[u8; 32], (u32, u32)>,
+    storedSize: u64,
+    peakSize: u64,
+    peakChunks: u64,
+    pub rows: Vec<GenerationRow>,
+    skipped: Vec<(PathBuf, &'static str)>,
+}
+
+impl RetentionSimulation {
+    pub fn new(policy: RetentionPolicy, daily: bool) -> Self {
+        Self {
+            policy,
+            daily,
+            chunker: String::new(),
+            hasher: String::new(),
+            saltVerifier: Vec::new(),
+            sampleBits: 0,
+            live: Vec::new(),
+            refCounts: HashMap::new(),
+            storedSize: 0,
+            peakSize: 0,
+            peakChunks: 0,
+            rows: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, path: &Path, trace: &Trace) {
+        if trace.chunks.is_empty() {
+            self.skipped.push((
+                path.to_path_buf(),
+                "no chunk records, trace with --log-fingerprints",
+            ));
+            return;
+        }
+        if self.rows.is_empty() {
+            self.chunker = trace.header.chunker.clone();
+            self.hasher = trace.header.hasher.clone();
+            self.saltVerifier = trace.header.saltVerifier.clone();
+            self.sampleBits = trace.header.sampleBits;
+        } else if trace.header.chunker != self.chunker {
+            self.skipped.push((path.to_path_buf(), "different chunker"));
+            return;
+        } else if trace.header.hasher != self.hasher {
+            self.skipped
+                .push((path.to_path_buf(), "different hash function"));
+            return;
+        } else if trace.header.saltVerifier != self.saltVerifier {
+            self.skipped.push((path.to_path_buf(), "different --salt"));
+            return;
+        } else if trace.header.sampleBits != self.sampleBits {
+            self.skipped
+                .push((path.to_path_buf(), "different --sample-bits"));
+            return;
+        }
+
+        let day = match self.rows.first() {
+            Some(first) if self.daily => first.day + self.rows.len() as i64,
+            _ => (trace.header.startTime / SECONDS_PER_DAY) as i64,
+        };
+        let scale = 1u64 << self.sampleBits;
+        let mut seen: HashSet<[u8; 32]> = HashSet::new();
+        let mut digests = Vec::new();
+        let mut logicalSize = 0;
+        for c in &trace.chunks {
+            logicalSize += c.length as u64 * scale;
+            // A generation holds one reference to each of its chunks.
+            if !seen.insert(c.digest) {
+                continue;
+            }
+            let entry = self.refCounts.entry(c.digest).or_insert((c.length, 0));
+            if entry.1 == 0 {
+                self.storedSize += c.length as u64 * scale;
+            }
+            entry.1 += 1;
+            digests.push(c.digest);
+        }
+        self.live.push(Generation { day, digests });
+
+        // The new generation is stored before the old ones expire.
+        self.peakSize = self.peakSize.max(self.storedSize);
+        self.peakChunks = self.peakChunks.max(self.refCounts.len() as u64 * scale);
+
+        let days: Vec<i64> = self.live.iter().map(|g| g.day).collect();
+        let keep = retained(&self.policy, &days);
+        let mut freedSize = 0;
+        let mut expiredCount = 0;
+        let mut live = Vec::new();
+        for (generation, keep) in std::mem::take(&mut self.live).into_iter().zip(keep) {
+            if keep {
+                live.push(generation);
+                continue;
+            }
+            expiredCount += 1;
+            for digest in generation.digests {
+                if let Some(entry) = self.refCounts.get_mut(&digest) {
+                    entry.1 -= 1;
+                    if entry.1 == 0 {
+                        freedSize += entry.0 as u64 * scale;
+                        self.refCounts.remove(&digest);
+                    }
+                }
+            }
+        }
+        self.live = live;
+        self.storedSize -= freedSize;
+
+        self.rows.push(GenerationRow {
+            path: path.to_path_buf(),
+            day,
+            logicalSize,
+            storedSize: self.storedSize,
+            chunkCount: self.refCounts.len() as u64 * scale,
+            freedSize,
+            expiredCount,
+            liveCount: self.live.len(),
+        });
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Retention {} over {} generations:",
+            self.policy,
+            self.rows.len()
+        )?;
+        for (path, reason) in &self.skipped {
+            writeln!(out, "  skipped {}: {}", path.display(), reason)?;
+        }
+        if self.rows.is_empty() {
+            return writeln!(out);
+        }
+        writeln!(
+            out,
+            "  {:>4}  {:<10}  {:>10}  {:>10}  {:>10}  {:>7}  {:>4}  {:>10}  trace",
+            "#", "date", "logical", "stored", "freed", "expired", "live", "chunks"
+        )?;
+        for (i, r) in self.rows.iter().enumerate() {
+            let (year, month, day) = civilDate(r.day);
+            writeln!(
+                out,
+                "  {:>4}  {:04}-{:02}-{:02}  {:>10}  {:>10}  {:>10}  {:>7}  {:>4}  {:>10}  {}",
+                i + 1,
+                year,
+                month,
+                day,
+                fmtSize(r.logicalSize as usize),
+                fmtSize(r.storedSize as usize),
+                fmtSize(r.freedSize as usize),
+                r.expiredCount,
+                r.liveCount,
+                r.chunkCount,
+                r.path.display()
+            )?;
+        }
+
+        let freed: u64 = self.rows.iter().map(|r| r.freedSize).sum();
+        writeln!(
+            out,
+            "  peak {} in {} chunks, {} freed in total, {} stored at the end",
+            fmtSize(self.peakSize as usize),
+            self.peakChunks,
+            fmtSize(freed as usize),
+            fmtSize(self.storedSize as usize)
+        )?;
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, TraceHeader};
+
+    #[test]
+    fn testParsePolicy() {
+        let policy: RetentionPolicy = "7d,4w,12m".parse().unwrap();
+        assert_eq!(
+            policy,
+            RetentionPolicy {
+                daily: 7,
+                weekly: 4,
+                monthly: 12,
+                yearly: 0
+            }
+        );
+        assert_eq!(policy.to_string(), "7d,4w,12m");
+        assert!("7x".parse::<RetentionPolicy>().is_err());
+        assert!("d".parse::<RetentionPolicy>().is_err());
+        assert_eq!(civilDate(0), (1970, 1, 1));
+        assert_eq!(civilDate(20_743), (2026, 10, 17));
+        assert_eq!(civilDate(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn testRetained() {
+        // Forty consecutive days starting on Thursday 1970-01-01.
+        let days: Vec<i64> = (0..40).collect();
+        let keep = retained(&"3d,2w,2m".parse().unwrap(), &days);
+        let kept: Vec<i64> = days
+            .iter()
+            .filter(|&&d| keep[d as usize])
+            .copied()
+            .collect();
+        // Days 37-39, which include the newest of this and the previous week
+        // (day 39 is a Monday), and the last day of January (day 30).
+        assert_eq!(kept, vec![30, 37, 38, 39]);
+
+        assert_eq!(
+            retained(&RetentionPolicy::default(), &days[..3]),
+            [false, false, true]
+        );
+    }
+
+    fn createTrace(ids: &[u8]) -> Trace {
+        Trace {
+            header: TraceHeader {
+                hasher: "BLAKE3".to_string(),
+                ..Default::default()
+            },
+            chunks: ids
+                .iter()
+                .map(|&id| ChunkRecord {
+                    length: 1024,
+                    digest: [id; 32],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testSimulation() {
+        let mut simulation = RetentionSimulation::new("2d".parse().unwrap(), true);
+        simulation.add(Path::new("1"), &createTrace(&[1, 2, 3, 3]));
+        simulation.add(Path::new("2"), &createTrace(&[1, 2, 4]));
+        simulation.add(Path::new("3"), &createTrace(&[1, 5, 5]));
+        simulation.add(Path::new("4"), &createTrace(&[6]));
+        let mut salted = createTrace(&[7]);
+        salted.header.saltVerifier = vec![1, 2, 3, 4];
+        simulation.add(Path::new("5"), &salted);
+        let mut otherChunker = createTrace(&[7]);
+        otherChunker.header.chunker = "FastCDC-4K".to_string();
+        simulation.add(Path::new("6"), &otherChunker);
+        assert_eq!(simulation.rows.len(), 4);
+
+        let stored: Vec<u64> = simulation
+            .rows
+            .iter()
+            .map(|r| r.storedSize / 1024)
+            .collect();
+        assert_eq!(stored, [3, 4, 4, 3]);
+        // Generation 1 expires with the third and frees chunk 3, generation 2
+        // with the fourth and frees chunks 2 and 4.
+        let freed: Vec<u64> = simulation.rows.iter().map(|r| r.freedSize / 1024).collect();
+        assert_eq!(freed, [0, 0, 1, 2]);
+        assert_eq!(simulation.rows[3].chunkCount, 3);
+        assert_eq!(simulation.rows[3].liveCount, 2);
+        assert_eq!(simulation.rows[1].day, 1);
+
+        let mut out = Vec::new();
+        simulation.write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("peak 5 KiB in 5 chunks, 3 KiB freed in total, 3 KiB stored"));
+        assert!(text.contains("skipped 5: different --salt"));
+        assert!(text.contains("skipped 6: different chunker"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::util::traceFile::Trace;
+
+use std::collections::HashMap;
+
+/// Normal quantile for a two-sided 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// Dedup rate of a trace that only kept one in 2^`sampleBits` distinct chunks.
+#[derive(Debug, PartialEq)]
+pub struct SampleEstimate {
+    /// Bytes of the sampled chunks, including their duplicates.
+    pub sampledSize: u64,
+    /// Share of the logical bytes that dedup removes.
+    pub savedFraction: f64,
+    /// 95% confidence interval of `savedFraction`. Needs chunk records.
+    pub interval: Option<(f64, f64)>,
+}
+
+impl SampleEstimate {
+    /// Estimated bytes saved in a dataset of `totalSize` bytes.
+    pub fn savedSize(&self, totalSize: u64) -> u64 {
+        (self.savedFraction * totalSize as f64) as u64
+    }
+}
+
+/// Extrapolate the dedup rate of a sampled trace.
+///
+/// Sampling by fingerprint keeps every copy of a kept chunk, so each distinct
+/// chunk is a cluster with `refs * length` logical and `length` unique bytes.
+/// The unique fraction is a ratio estimator over those clusters; its variance
+/// comes from the usual linearization for Poisson sampling with rate p:
+/// `(1 - p) * sum((y - R x)^2) / sum(x)^2`.
+pub fn estimate(trace: &Trace) -> Option<SampleEstimate> {
+    let p = 0.5f64.powi(trace.header.sampleBits as i32);
+
+    if trace.chunks.is_empty() {
+        let s = trace.summary.as_ref()?;
+        let sampledSize = s.uniqueSizes.sum + s.dupSizes.sum;
+        if sampledSize == 0 {
+            return None;
+        }
+        return Some(SampleEstimate {
+            sampledSize,
+            savedFraction: s.dupSizes.sum as f64 / sampledSize as f64,
+            interval: None,
+        });
+    }
+
+    let mut clusters: HashMap<[u8; 32], (u64, u64)> = HashMap::new();
+    for c in &trace.chunks {
+        let cluster = clusters.entry(c.digest).or_insert((0, c.length as u64));
+        cluster.0 += c.length as u64;
+    }
+
+    let logical: u64 = clusters.values().map(|c| c.0).sum();
+    let unique: u64 = clusters.values().map(|c| c.1).sum();
+    if logical == 0 {
+        return None;
+    }
+    let ratio = unique as f64 / logical as f64;
+    let residuals: f64 = clusters
+        .values()
+        .map(|&(x, y)| (y as f64 - ratio * x as f64).powi(2))
+        .sum();
+    let deviation = ((1.0 - p) * residuals).sqrt() / logical as f64;
+
+    let savedFraction = 1.0 - ratio;
+    Some(SampleEstimate {
+        sampledSize: logical,
+        savedFraction,
+        interval: Some((
+            (savedFraction - Z_95 * deviation).max(0.0),
+            (savedFraction + Z_95 * deviation).min(1.0),
+        )),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::trace::sampling::isSampled;
+    use crate::util::traceFile::{ChunkRecord, TraceHeader};
+
+    /// A dataset of `distinct` chunks with a skewed number of copies each:
+    /// most chunks are unique and a few are copied many times.
+    fn createChunks(distinct: u32) -> Vec<ChunkRecord> {
+        let mut chunks = Vec::new();
+        let mut x: u64 = 0x9e3779b97f4a7c15;
+        for id in 0..distinct {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let refs = match x % 100 {
+                0..60 => 1,
+                60..90 => 2 + x % 3,
+                _ => 5 + x % 40,
+            };
+            let length = 2048 + (x >> 40) as u32 % 8192;
+            let digest = *blake3::hash(&id.to_le_bytes()).as_bytes();
+            for copy in 0..refs {
+                chunks.push(ChunkRecord {
+                    fileIndex: copy as u32,
+                    offset: id as u64 * 16384,
+                    length,
+                    digest,
+                    ..Default::default()
+                });
+            }
+        }
+        chunks
+    }
+
+    fn createTrace(chunks: &[ChunkRecord], sampleBits: u8) -> Trace {
+        Trace {
+            header: TraceHeader {
+                sampleBits,
+                ..Default::default()
+            },
+            chunks: chunks
+                .iter()
+                .filter(|c| isSampled(&c.digest, sampleBits))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testExactWithoutSampling() {
+        let chunks = createChunks(1000);
+        let exact = estimate(&createTrace(&chunks, 0)).unwrap();
+        let (low, high) = exact.interval.unwrap();
+        // Nothing to extrapolate, so the interval collapses.
+        assert!((high - low) < 1e-12);
+        assert_eq!(
+            exact.sampledSize,
+            chunks.iter().map(|c| c.length as u64).sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn testSampledEstimatesConverge() {
+        let mut previousWidth = f64::MAX;
+        for distinct in [10_000, 40_000, 160_000] {
+            let chunks = createChunks(distinct);
+            let exact = estimate(&createTrace(&chunks, 0)).unwrap().savedFraction;
+            let sampled = estimate(&createTrace(&chunks, 5)).unwrap();
+            let (low, high) = sampled.interval.unwrap();
+
+            // A 95% interval misses one in twenty datasets; allow for three
+            // standard deviations instead of two.
+            let width = high - low;
+            let error = (sampled.savedFraction - exact).abs();
+            assert!(error <= 0.75 * width, "{exact} vs {low}..{high}");
+            assert!(error < 0.05);
+            // Four times the data halves the interval.
+            assert!(width < previousWidth * 0.7, "{width} vs {previousWidth}");
+            previousWidth = width;
+        }
+    }
+
+    #[test]
+    fn testEstimateFromSummary() {
+        let mut trace = createTrace(&[], 3);
+        assert!(estimate(&trace).is_none());
+
+        let mut summary = crate::util::traceFile::TraceSummary::default();
+        summary.uniqueSizes.record(3000);
+        summary.dupSizes.record(1000);
+        trace.summary = Some(summary);
+        let e = estimate(&trace).unwrap();
+        assert_eq!(e.savedFraction, 0.25);
+        assert_eq!(e.interval, None);
+        assert_eq!(e.savedSize(1 << 20), 1 << 18);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::trace::resemblance::splitmix64;
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::Trace;
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+/// Minimum hashes per MinHash signature.
+const HASH_COUNT: usize = 128;
+
+pub type Signature = [u64; HASH_COUNT];
+
+/// MinHash signature of a set of chunk fingerprints. Two signatures agree in
+/// each position with a probability equal to the Jaccard similarity of the sets.
+pub fn signature<'a>(digests: impl IntoIterator<Item = &'a [u8; 32]>) -> Signature {
+    let mut result = [u64::MAX; HASH_COUNT];
+    for digest in digests {
+        // Bytes 8..16 because sampling by fingerprint skews the leading bytes.
+        let key = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        for (i, min) in result.iter_mut().enumerate() {
+            *min = (*min).min(splitmix64(key ^ splitmix64(i as u64)));
+        }
+    }
+    result
+}
+
+/// Splits the signature into bands of rows for LSH: files that agree in all
+/// rows of any band become candidates. Picks the most rows per band whose
+/// threshold, about `(1 / bands) ^ (1 / rows)`, is still below `threshold`, so
+/// few pairs above it are missed.
+pub fn bandRows(threshold: f64) -> usize {
+    let mut rows = 1;
+    while rows < HASH_COUNT {
+        let next = rows * 2;
+        let bands = (HASH_COUNT / next) as f64;
+        if (1.0 / bands).powf(1.0 / next as f64) > threshold {
+            break;
+        }
+        rows = next;
+    }
+    rows
+}
+
+/// Jaccard similarity of two sorted, deduplicated fingerprint lists.
+fn jaccard(a: &[[u8; 32]], b: &[[u8; 32]]) -> f64 {
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        0.0
+    } else {
+        shared as f64 / union as f64
+    }
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// A group of files linked by pairs at or above the threshold.
+#[derive(Debug, PartialEq)]
+pub struct Cluster {
+    /// File indexes, largest file first.
+    pub files: Vec<u32>,
+    pub size: u64,
+    /// Lowest Jaccard similarity of the pairs that link the cluster.
+    pub minSimilarity: f64,
+}
+
+/// Clusters of near-duplicate files in a trace, largest first. Candidate pairs
+/// from LSH are confirmed by the exact Jaccard similarity of their chunks.
+pub fn findClusters(trace: &Trace, threshold: f64) -> Vec<Cluster> {
+    // Sizes of the whole files, as the chunk records may only be a sample.
+    let sizes: HashMap<u32, u64> = trace.files.iter().map(|f| (f.fileIndex, f.size)).collect();
+    let scale = 1u64 << trace.header.sampleBits;
+    let mut files: Vec<(u32, u64, Vec<[u8; 32]>)> = Vec::new();
+    for file in trace.chunks.chunk_by(|a, b| a.fileIndex == b.fileIndex) {
+        let mut digests: Vec<[u8; 32]> = file.iter().map(|c| c.digest).collect();
+        digests.sort_unstable();
+        digests.dedup();
+        let fileIndex = file[0].fileIndex;
+        let size = sizes
+            .get(&fileIndex)
+            .copied()
+            .unwrap_or_else(|| file.iter().map(|c| c.length as u64).sum::<u64>() * scale);
+        files.push((fileIndex, size, digests));
+    }
+
+    let rows = bandRows(threshold);
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (i, (_, _, digests)) in files.iter().enumerate() {
+        let sig = signature(digests);
+        for (band, values) in sig.chunks(rows).enumerate() {
+            let key = values.iter().fold(0, |acc, &v| splitmix64(acc ^ v));
+            buckets.entry((band, key)).or_default().push(i);
+        }
+    }
+
+    // Link each file to the first earlier file of its bucket that is similar
+    // enough; that is enough for the clusters and keeps large buckets of
+    // identical files from turning quadratic.
+    let mut parents: Vec<usize> = (0..files.len()).collect();
+    let mut edges: Vec<(usize, f64)> = Vec::new();
+    for members in buckets.values() {
+        for (k, &i) in members.iter().enumerate() {
+            for &j in &members[..k] {
+                if find(&mut parents, i) == find(&mut parents, j) {
+                    break;
+                }
+                let similarity = jaccard(&files[i].2, &files[j].2);
+                if similarity >= threshold {
+                    let root = find(&mut parents, i);
+                    parents[root] = find(&mut parents, j);
+                    edges.push((i, similarity));
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Cluster> = HashMap::new();
+    for (i, (_, size, _)) in files.iter().enumerate() {
+        let root = find(&mut parents, i);
+        let cluster = groups.entry(root).or_insert(Cluster {
+            files: Vec::new(),
+            size: 0,
+            minSimilarity: 1.0,
+        });
+        cluster.files.push(i as u32);
+        cluster.size += size;
+    }
+    for (i, similarity) in edges {
+        let cluster = groups.get_mut(&find(&mut parents, i)).unwrap();
+        cluster.minSimilarity = cluster.minSimilarity.min(similarity);
+    }
+
+    let mut clusters: Vec<Cluster> = groups
+        .into_values()
+        .filter(|c| c.files.len() > 1)
+        .map(|mut c| {
+            c.files.sort_by(|&a, &b| {
+                files[b as usize]
+                    .1
+                    .cmp(&files[a as usize].1)
+                    .then(a.cmp(&b))
+            });
+            c.files = c.files.iter().map(|&i| files[i as usize].0).collect();
+            c
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.size.cmp(&a.size).then(a.files.cmp(&b.files)));
+    clusters
+}
+
+pub fn writeSimilarFiles(
+    out: &mut dyn Write,
+    trace: &Trace,
+    threshold: f64,
+    n: usize,
+) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Similar files need chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+
+    let clusters = findClusters(trace, threshold);
+    let fileCount: usize = clusters.iter().map(|c| c.files.len()).sum();
+    writeln!(
+        out,
+        "Near-duplicate files (Jaccard similarity of chunks at least {}):",
+        threshold
+    )?;
+    writeln!(
+        out,
+        "  {} clusters of {} files, {}",
+        clusters.len(),
+        fileCount,
+        fmtSize(clusters.iter().map(|c| c.size).sum::<u64>() as usize)
+    )?;
+
+    let files: HashMap<u32, (String, u64)> = trace
+        .files
+        .iter()
+        .map(|f| (f.fileIndex, (f.displayPath(), f.size)))
+        .collect();
+    for (i, cluster) in clusters.iter().take(n).enumerate() {
+        writeln!(
+            out,
+            "  #{}: {} files, {}, similarity at least {:.2}",
+            i + 1,
+            cluster.files.len(),
+            fmtSize(cluster.size as usize),
+            cluster.minSimilarity
+        )?;
+        for fileIndex in &cluster.files {
+            let (path, size) = files.get(fileIndex).map_or(("?", 0), |(p, s)| (p, *s));
+            writeln!(out, "    {:>10}  {}", fmtSize(size as usize), path)?;
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{ChunkRecord, FileRecord};
+
+    fn digest(id: u32) -> [u8; 32] {
+        *blake3::hash(&id.to_le_bytes()).as_bytes()
+    }
+
+    /// Files of 1 KiB chunks with the given ids.
+    fn createTrace(files: &[Vec<u32>]) -> Trace {
+        let mut trace = Trace::default();
+        for (fileIndex, ids) in files.iter().enumerate() {
+            trace.files.push(FileRecord {
+                fileIndex: fileIndex as u32,
+                path: format!("/f{}", fileIndex).into_bytes(),
+                size: ids.len() as u64 * 1024,
+                ..Default::default()
+            });
+            for &id in ids {
+                trace.chunks.push(ChunkRecord {
+                    fileIndex: fileIndex as u32,
+                    length: 1024,
+                    digest: digest(id),
+                    ..Default::default()
+                });
+            }
+        }
+        trace
+    }
+
+    #[test]
+    fn testSignature() {
+        let a: Vec<[u8; 32]> = (0..1000).map(digest).collect();
+        let b: Vec<[u8; 32]> = (200..1200).map(digest).collect();
+        let (sa, sb) = (signature(&a), signature(&b));
+        let agree = sa.iter().zip(&sb).filter(|(x, y)| x == y).count();
+        // The Jaccard similarity is 800 / 1200.
+        let estimate = agree as f64 / HASH_COUNT as f64;
+        assert!((estimate - 0.667).abs() < 0.15, "{estimate}");
+        assert_eq!(signature(&a), signature(a.iter().rev()));
+    }
+
+    #[test]
+    fn testBandRows() {
+        assert_eq!(bandRows(0.8), 8);
+        assert_eq!(bandRows(0.5), 4);
+        assert_eq!(bandRows(0.01), 1);
+        assert_eq!(bandRows(1.0), HASH_COUNT);
+    }
+
+    #[test]
+    fn testFindClusters() {
+        let trace = createTrace(&[
+            (0..100).collect(),
+            (1000..1050).collect(),
+            // A fork of file 0 with a few changed chunks.
+            (0..95).chain(500..505).collect(),
+            (2000..2100).collect(),
+            (1000..1050).collect(),
+        ]);
+        let clusters = findClusters(&trace, 0.8);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].files, vec![0, 2]);
+        assert_eq!(clusters[0].size, 200 * 1024);
+        assert!((clusters[0].minSimilarity - 95.0 / 105.0).abs() < 1e-9);
+        assert_eq!(clusters[1].files, vec![1, 4]);
+        assert_eq!(clusters[1].minSimilarity, 1.0);
+
+        let mut out = Vec::new();
+        writeSimilarFiles(&mut out, &trace, 0.8, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("2 clusters of 4 files, 300 KiB"));
+        assert!(text.contains("#1: 2 files, 200 KiB, similarity at least 0.90"));
+        assert!(!text.contains("#2"));
+    }
+
+    #[test]
+    fn testClusterSizeOfSampledTrace() {
+        // The chunk records of a sampled trace cover only part of the files.
+        let mut trace = createTrace(&[(0..100).collect(), (0..100).collect()]);
+        trace.header.sampleBits = 3;
+        for file in &mut trace.files {
+            file.size *= 8;
+        }
+        let clusters = findClusters(&trace, 0.8);
+        assert_eq!(clusters[0].size, 2 * 800 * 1024);
+
+        let mut out = Vec::new();
+        writeSimilarFiles(&mut out, &trace, 0.8, 1).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("#1: 2 files, 1.6 MiB"));
+        assert!(text.contains("       800 KiB  /f0\n"));
+    }
+}
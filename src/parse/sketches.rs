@@ -0,0 +1,216 @@
+use crate::trace::tracer::fmtSize;
+use crate::util::histogram::SizeHistogram;
+use crate::util::hyperLogLog::HyperLogLog;
+use crate::util::traceFile::Trace;
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Unique data estimated from a sketch. Unique bytes assume that distinct chunks
+/// have the mean length of the chunks the tracer saw first.
+#[derive(Debug, PartialEq)]
+pub struct SketchEstimate {
+    pub uniqueChunks: f64,
+    pub uniqueSize: f64,
+    /// Relative standard error.
+    pub error: f64,
+}
+
+fn estimateFrom(sketch: &HyperLogLog, sampleBits: u8, lengths: &SizeHistogram) -> SketchEstimate {
+    let uniqueChunks = sketch.estimate() * (1u64 << sampleBits) as f64;
+    SketchEstimate {
+        uniqueChunks,
+        uniqueSize: uniqueChunks * lengths.mean(),
+        error: sketch.standardError(),
+    }
+}
+
+pub fn estimateTrace(trace: &Trace) -> Option<SketchEstimate> {
+    let summary = trace.summary.as_ref()?;
+    let sketch = summary.sketch.as_ref()?;
+    Some(estimateFrom(
+        sketch,
+        trace.header.sampleBits,
+        &summary.uniqueSizes,
+    ))
+}
+
+pub fn writeSketch(out: &mut dyn Write, trace: &Trace) -> io::Result<()> {
+    let Some(e) = estimateTrace(trace) else {
+        return Ok(());
+    };
+    writeln!(
+        out,
+        "  the sketch estimates {:.0} unique chunks, {} unique (±{:.1}%)",
+        e.uniqueChunks,
+        fmtSize(e.uniqueSize as usize),
+        e.error * 100.0
+    )
+}
+
+/// Union of the sketches of several traces. Only traces with the same chunker,
+/// hasher, salt, sampling and sketch size can be merged.
+#[derive(Default)]
+pub struct SketchUnion {
+    sketch: Option<HyperLogLog>,
+    chunker: String,
+    hasher: String,
+    saltVerifier: Vec<u8>,
+    sampleBits: u8,
+    lengths: SizeHistogram,
+    totalSize: u64,
+    merged: Vec<PathBuf>,
+    skipped: Vec<(PathBuf, &'static str)>,
+}
+
+impl SketchUnion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, path: &Path, trace: &Trace) {
+        let Some(summary) = trace.summary.as_ref() else {
+            self.skipped.push((path.to_path_buf(), "no summary record"));
+            return;
+        };
+        let Some(ref sketch) = summary.sketch else {
+            self.skipped
+                .push((path.to_path_buf(), "no sketch, trace with --sketch"));
+            return;
+        };
+
+        match self.sketch {
+            None => {
+                self.sketch = Some(sketch.clone());
+                self.chunker = trace.header.chunker.clone();
+                self.hasher = trace.header.hasher.clone();
+                self.saltVerifier = trace.header.saltVerifier.clone();
+                self.sampleBits = trace.header.sampleBits;
+            }
+            Some(ref mut union) => {
+                if trace.header.chunker != self.chunker {
+                    self.skipped.push((path.to_path_buf(), "different chunker"));
+                    return;
+                }
+                if trace.header.hasher != self.hasher {
+                    self.skipped
+                        .push((path.to_path_buf(), "different hash function"));
+                    return;
+                }
+                if trace.header.saltVerifier != self.saltVerifier {
+                    self.skipped.push((path.to_path_buf(), "different --salt"));
+                    return;
+                }
+                if trace.header.sampleBits != self.sampleBits {
+                    self.skipped
+                        .push((path.to_path_buf(), "different --sample-bits"));
+                    return;
+                }
+                if !union.merge(sketch) {
+                    self.skipped
+                        .push((path.to_path_buf(), "different sketch size"));
+                    return;
+                }
+            }
+        }
+        self.lengths.merge(&summary.uniqueSizes);
+        self.totalSize += summary.totalSize;
+        self.merged.push(path.to_path_buf());
+    }
+
+    pub fn estimate(&self) -> Option<SketchEstimate> {
+        self.sketch
+            .as_ref()
+            .map(|sketch| estimateFrom(sketch, self.sampleBits, &self.lengths))
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Union of {} traces:", self.merged.len())?;
+        for (path, reason) in &self.skipped {
+            writeln!(out, "  skipped {}: {}", path.display(), reason)?;
+        }
+        if let Some(e) = self.estimate() {
+            writeln!(
+                out,
+                "  {} logical, an estimated {:.0} unique chunks, {} unique (±{:.1}%)",
+                fmtSize(self.totalSize as usize),
+                e.uniqueChunks,
+                fmtSize(e.uniqueSize as usize),
+                e.error * 100.0
+            )?;
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::{TraceHeader, TraceSummary};
+
+    fn createTrace(hasher: &str, ids: std::ops::Range<u64>) -> Trace {
+        let mut sketch = HyperLogLog::default();
+        let mut summary = TraceSummary::default();
+        for i in ids {
+            sketch.insert(blake3::hash(&i.to_le_bytes()).as_bytes());
+            summary.uniqueSizes.record(4096);
+            summary.totalSize += 4096;
+        }
+        summary.sketch = Some(sketch);
+        Trace {
+            header: TraceHeader {
+                hasher: hasher.to_string(),
+                ..Default::default()
+            },
+            summary: Some(summary),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testEstimateTrace() {
+        let mut trace = createTrace("BLAKE3", 0..10_000);
+        let e = estimateTrace(&trace).unwrap();
+        assert!((e.uniqueChunks - 10_000.0).abs() < 300.0);
+        assert_eq!(e.uniqueSize, e.uniqueChunks * 4096.0);
+
+        // A sampled trace stands for 2^bits times as many chunks.
+        trace.header.sampleBits = 2;
+        let sampled = estimateTrace(&trace).unwrap();
+        assert_eq!(sampled.uniqueChunks, e.uniqueChunks * 4.0);
+    }
+
+    #[test]
+    fn testUnion() {
+        let mut union = SketchUnion::new();
+        union.add(Path::new("a"), &createTrace("BLAKE3", 0..20_000));
+        union.add(Path::new("b"), &createTrace("BLAKE3", 10_000..30_000));
+        union.add(Path::new("c"), &createTrace("MD5", 0..100));
+        union.add(Path::new("d"), &Trace::default());
+        let mut salted = createTrace("BLAKE3", 0..100);
+        salted.header.saltVerifier = vec![1, 2, 3, 4];
+        union.add(Path::new("e"), &salted);
+        let mut otherChunker = createTrace("BLAKE3", 0..100);
+        otherChunker.header.chunker = "FastCDC-4K".to_string();
+        union.add(Path::new("f"), &otherChunker);
+
+        let e = union.estimate().unwrap();
+        assert!(
+            (e.uniqueChunks - 30_000.0).abs() < 900.0,
+            "{}",
+            e.uniqueChunks
+        );
+        assert_eq!(union.merged.len(), 2);
+        assert_eq!(union.skipped.len(), 4);
+
+        let mut out = Vec::new();
+        union.write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("skipped c: different hash function"));
+        assert!(text.contains("skipped e: different --salt"));
+        assert!(text.contains("skipped f: different chunker"));
+        assert!(text.contains("156.2 MiB logical"));
+    }
+}
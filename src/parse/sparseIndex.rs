@@ -0,0 +1,319 @@
+use crate::trace::sampling::isSampled;
+use crate::trace::tracer::fmtSize;
+use crate::util::traceFile::{ChunkRecord, Trace};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+/// A hook only points to this many of the most recent segments that contain it.
+const MAX_MANIFESTS_PER_HOOK: usize = 8;
+/// Bytes of a chunk location stored next to each fingerprint in an index.
+const LOCATION_SIZE: u64 = 8;
+/// Bytes of a manifest reference in the sparse index.
+const MANIFEST_ID_SIZE: u64 = 4;
+
+/// Outcome of replaying a trace through one index design.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexStats {
+    /// Entries the index keeps in RAM.
+    pub entries: u64,
+    pub ramSize: u64,
+    /// Bytes of the duplicates it detected.
+    pub dupSize: u64,
+}
+
+/// Exact dedup: every fingerprint is in the index.
+pub fn simulateExact(trace: &Trace) -> IndexStats {
+    let mut seen: HashSet<[u8; 32]> = HashSet::new();
+    let mut stats = IndexStats::default();
+    for c in &trace.chunks {
+        if !seen.insert(c.digest) {
+            stats.dupSize += c.length as u64;
+        }
+    }
+    stats.entries = seen.len() as u64;
+    stats.ramSize = stats.entries * (trace.header.digestLength as u64 + LOCATION_SIZE);
+    stats
+}
+
+/// Split the chunk stream into segments of about `segmentSize` logical bytes.
+fn segments(chunks: &[ChunkRecord], segmentSize: u64) -> Vec<&[ChunkRecord]> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (i, c) in chunks.iter().enumerate() {
+        size += c.length as u64;
+        if size >= segmentSize {
+            result.push(&chunks[start..=i]);
+            start = i + 1;
+            size = 0;
+        }
+    }
+    if start < chunks.len() {
+        result.push(&chunks[start..]);
+    }
+    result
+}
+
+/// Sparse indexing (Lillibridge et al., 2009). Only hooks, the fingerprints
+/// with `hookBits` leading zero bits, are kept in RAM, each pointing to the
+/// manifests of recent segments that contain it. An incoming segment picks up
+/// to `champions` manifests that share the most hooks with it and only dedups
+/// against their chunks.
+pub fn simulateSparse(
+    trace: &Trace,
+    hookBits: u8,
+    segmentSize: u64,
+    champions: usize,
+) -> IndexStats {
+    let mut sparseIndex: HashMap<[u8; 32], Vec<u32>> = HashMap::new();
+    let mut manifests: Vec<HashSet<[u8; 32]>> = Vec::new();
+    let mut stats = IndexStats::default();
+
+    for segment in segments(&trace.chunks, segmentSize) {
+        let hooks: HashSet<[u8; 32]> = segment
+            .iter()
+            .filter(|c| isSampled(&c.digest, hookBits))
+            .map(|c| c.digest)
+            .collect();
+
+        // Pick champions greedily by the hooks not yet covered by another.
+        let mut votes: HashMap<u32, HashSet<&[u8; 32]>> = HashMap::new();
+        for hook in &hooks {
+            for &manifest in sparseIndex.get(hook).into_iter().flatten() {
+                votes.entry(manifest).or_default().insert(hook);
+            }
+        }
+        let mut chosen: Vec<u32> = Vec::new();
+        while chosen.len() < champions {
+            let Some((&best, covered)) = votes
+                .iter()
+                .filter(|(_, hooks)| !hooks.is_empty())
+                .max_by_key(|&(&manifest, hooks)| (hooks.len(), manifest))
+            else {
+                break;
+            };
+            let covered = covered.clone();
+            chosen.push(best);
+            votes.remove(&best);
+            for hooks in votes.values_mut() {
+                hooks.retain(|h| !covered.contains(h));
+            }
+        }
+
+        let mut manifest: HashSet<[u8; 32]> = HashSet::new();
+        for c in segment {
+            let known = chosen
+                .iter()
+                .any(|&m| manifests[m as usize].contains(&c.digest));
+            if !manifest.insert(c.digest) || known {
+                stats.dupSize += c.length as u64;
+            }
+        }
+
+        let id = manifests.len() as u32;
+        for hook in hooks {
+            let entry = sparseIndex.entry(hook).or_default();
+            entry.push(id);
+            if entry.len() > MAX_MANIFESTS_PER_HOOK {
+                entry.remove(0);
+            }
+        }
+        manifests.push(manifest);
+    }
+
+    stats.entries = sparseIndex.len() as u64;
+    stats.ramSize = sparseIndex
+        .values()
+        .map(|m| trace.header.digestLength as u64 + m.len() as u64 * MANIFEST_ID_SIZE)
+        .sum();
+    stats
+}
+
+/// Extreme binning (Bhagwat et al., 2009). Each file is represented by its
+/// smallest fingerprint, the only one kept in RAM, and dedups against the bin
+/// of chunks of earlier files with the same representative.
+pub fn simulateExtremeBinning(trace: &Trace) -> IndexStats {
+    let mut bins: HashMap<[u8; 32], HashSet<[u8; 32]>> = HashMap::new();
+    let mut stats = IndexStats::default();
+
+    for file in trace.chunks.chunk_by(|a, b| a.fileIndex == b.fileIndex) {
+        let Some(representative) = file.iter().map(|c| c.digest).min() else {
+            continue;
+        };
+        let bin = bins.entry(representative).or_default();
+        for c in file {
+            if !bin.insert(c.digest) {
+                stats.dupSize += c.length as u64;
+            }
+        }
+    }
+
+    stats.entries = bins.len() as u64;
+    stats.ramSize = stats.entries * (trace.header.digestLength as u64 + LOCATION_SIZE);
+    stats
+}
+
+pub fn writeSparseIndex(
+    out: &mut dyn Write,
+    trace: &Trace,
+    hookBits: &[u8],
+    segmentSize: u64,
+    champions: usize,
+) -> io::Result<()> {
+    if trace.chunks.is_empty() {
+        return writeln!(
+            out,
+            "Index simulation needs chunk records, trace with --log-fingerprints.\n"
+        );
+    }
+    if trace.header.sampleBits > 0 {
+        return writeln!(
+            out,
+            "Index simulation needs all chunks, trace without --sample-bits.\n"
+        );
+    }
+
+    let exact = simulateExact(trace);
+    writeln!(
+        out,
+        "Approximate indexes ({} segments, {} champions):",
+        fmtSize(segmentSize as usize),
+        champions
+    )?;
+    writeln!(
+        out,
+        "  {:<18}  {:>10}  {:>10}  {:>10}  {:>8}",
+        "index", "entries", "RAM", "detected", "of exact"
+    )?;
+    let mut rows = vec![(
+        "exact".to_string(),
+        exact.entries,
+        exact.ramSize,
+        exact.dupSize,
+    )];
+    for &bits in hookBits {
+        let s = simulateSparse(trace, bits, segmentSize, champions);
+        rows.push((
+            format!("sparse, 1 in {}", 1u64 << bits),
+            s.entries,
+            s.ramSize,
+            s.dupSize,
+        ));
+    }
+    let s = simulateExtremeBinning(trace);
+    rows.push((
+        "extreme binning".to_string(),
+        s.entries,
+        s.ramSize,
+        s.dupSize,
+    ));
+
+    for (label, entries, ramSize, dupSize) in rows {
+        let fraction = if exact.dupSize == 0 {
+            100.0
+        } else {
+            dupSize as f64 * 100.0 / exact.dupSize as f64
+        };
+        writeln!(
+            out,
+            "  {:<18}  {:>10}  {:>10}  {:>10}  {:>7.1}%",
+            label,
+            entries,
+            fmtSize(ramSize as usize),
+            fmtSize(dupSize as usize),
+            fraction
+        )?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::traceFile::TraceHeader;
+
+    /// A digest for `id`; ids below 16 are hooks at any rate up to 1 in 2^28.
+    fn digest(id: u32) -> [u8; 32] {
+        let mut digest = [0xffu8; 32];
+        digest[..4].copy_from_slice(&if id < 16 { id } else { u32::MAX - id }.to_be_bytes());
+        digest[4..8].copy_from_slice(&id.to_le_bytes());
+        digest
+    }
+
+    /// Files of 1 KiB chunks with the given ids.
+    fn createTrace(files: &[&[u32]]) -> Trace {
+        let mut chunks = Vec::new();
+        for (fileIndex, ids) in files.iter().enumerate() {
+            for &id in *ids {
+                chunks.push(ChunkRecord {
+                    fileIndex: fileIndex as u32,
+                    length: 1024,
+                    digest: digest(id),
+                    ..Default::default()
+                });
+            }
+        }
+        Trace {
+            header: TraceHeader {
+                digestLength: 32,
+                ..Default::default()
+            },
+            chunks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn testSparseFindsChampions() {
+        // The second copy of each segment shares its hook with the first.
+        let trace = createTrace(&[
+            &[1, 100, 101, 102],
+            &[2, 200, 201, 202],
+            &[1, 100, 101, 102],
+        ]);
+        let exact = simulateExact(&trace);
+        assert_eq!(exact.dupSize, 4096);
+        assert_eq!(exact.entries, 8);
+
+        let sparse = simulateSparse(&trace, 28, 4096, 1);
+        assert_eq!(sparse.dupSize, 4096);
+        assert_eq!(sparse.entries, 2);
+        // Hook 1 points to the first and the third segment.
+        assert_eq!(sparse.ramSize, 2 * 32 + 3 * 4);
+    }
+
+    #[test]
+    fn testSparseMissesWithoutHooks() {
+        // The third segment mixes two earlier ones but only hooks into the
+        // first, so with one champion the chunks of the second are stored again.
+        let trace = createTrace(&[
+            &[1, 100, 101, 102],
+            &[2, 200, 201, 202],
+            &[1, 100, 201, 202],
+        ]);
+        assert_eq!(simulateExact(&trace).dupSize, 4096);
+        assert_eq!(simulateSparse(&trace, 28, 4096, 1).dupSize, 2048);
+        assert_eq!(simulateSparse(&trace, 28, 4096, 4).dupSize, 2048);
+    }
+
+    #[test]
+    fn testExtremeBinning() {
+        // The representative is the smallest digest: the hook ids come first.
+        let trace = createTrace(&[&[1, 100, 101], &[1, 100, 102], &[2, 100]]);
+        let stats = simulateExtremeBinning(&trace);
+        assert_eq!(stats.entries, 2);
+        // The third file lands in a new bin and misses chunk 100.
+        assert_eq!(stats.dupSize, 2048);
+        assert_eq!(simulateExact(&trace).dupSize, 3072);
+
+        let mut out = Vec::new();
+        writeSparseIndex(&mut out, &trace, &[2], 2048, 2).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("sparse, 1 in 4"));
+        assert!(text.contains("extreme binning"));
+        assert!(text.contains("66.7%"));
+    }
+}
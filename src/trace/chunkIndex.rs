@@ -0,0 +1,209 @@
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use memmap2::Mmap;
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// Where a chunk was first seen, so later matches can be compared byte by byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkLocation {
+    pub fileIndex: usize,
+    pub offset: usize,
+    pub length: usize,
+}
+
+pub enum Lookup {
+    Unique,
+    Duplicate(Option<ChunkLocation>),
+}
+
+/// The set of fingerprints seen so far. Plain tracing only needs the digests;
+/// verification additionally remembers the first occurrence of every chunk.
+/// With `--sketch-only` there is no exact index and every chunk looks unique.
+pub enum ChunkIndex {
+    Fingerprints(DashSet<[u8; 32]>),
+    Locations(DashMap<[u8; 32], ChunkLocation>),
+    Disabled,
+}
+
+impl ChunkIndex {
+    pub fn new(verifyDuplicates: bool, capacity: usize) -> Self {
+        if verifyDuplicates {
+            ChunkIndex::Locations(DashMap::with_capacity(capacity))
+        } else {
+            ChunkIndex::Fingerprints(DashSet::with_capacity(capacity))
+        }
+    }
+
+    pub fn isEnabled(&self) -> bool {
+        !matches!(self, ChunkIndex::Disabled)
+    }
+
+    pub fn insert(&self, hash: [u8; 32], location: ChunkLocation) -> Lookup {
+        match self {
+            ChunkIndex::Disabled => Lookup::Unique,
+            ChunkIndex::Fingerprints(set) => {
+                if set.insert(hash) {
+                    Lookup::Unique
+                } else {
+                    Lookup::Duplicate(None)
+                }
+            }
+            ChunkIndex::Locations(map) => match map.entry(hash) {
+                Entry::Occupied(e) => Lookup::Duplicate(Some(*e.get())),
+                Entry::Vacant(e) => {
+                    e.insert(location);
+                    Lookup::Unique
+                }
+            },
+        }
+    }
+}
+
+/// Two chunks with the same fingerprint but different content.
+pub struct Collision {
+    pub hash: [u8; 32],
+    pub first: ChunkLocation,
+    pub second: ChunkLocation,
+}
+
+/// Counters for `--verify-duplicates`. Besides collisions they record what
+/// verification costs: every verified duplicate is one extra read of the
+/// original chunk plus a byte comparison.
+#[derive(Default)]
+pub struct VerifyStats {
+    pub verifiedCount: AtomicUsize,
+    pub verifiedBytes: AtomicUsize,
+    pub verifyNanos: AtomicU64,
+    pub collisionCount: AtomicUsize,
+    pub collisions: Mutex<Vec<Collision>>,
+}
+
+impl VerifyStats {
+    /// Compare `chunk` against the bytes stored at `first` and record the outcome.
+    /// Returns `true` if the chunk really is a duplicate.
+    pub fn verify(
+        &self,
+        mmaps: &[Arc<Mmap>],
+        hash: [u8; 32],
+        first: ChunkLocation,
+        second: ChunkLocation,
+        chunk: &[u8],
+    ) -> bool {
+        let start = Instant::now();
+        let original = &mmaps[first.fileIndex][first.offset..first.offset + first.length];
+        let isDuplicate = original == chunk;
+        let elapsed = start.elapsed().as_nanos() as u64;
+
+        self.verifiedCount.fetch_add(1, Ordering::Relaxed);
+        self.verifiedBytes.fetch_add(chunk.len(), Ordering::Relaxed);
+        self.verifyNanos.fetch_add(elapsed, Ordering::Relaxed);
+
+        if !isDuplicate {
+            self.collisionCount.fetch_add(1, Ordering::Relaxed);
+            self.collisions.lock().unwrap().push(Collision {
+                hash,
+                first,
+                second,
+            });
+        }
+
+        isDuplicate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+    use tempfile::tempfile;
+
+    fn mapBytes(data: &[u8]) -> Arc<Mmap> {
+        let mut file = tempfile().unwrap();
+        file.write_all(data).unwrap();
+        Arc::new(unsafe { Mmap::map(&file).unwrap() })
+    }
+
+    fn location(fileIndex: usize, offset: usize, length: usize) -> ChunkLocation {
+        ChunkLocation {
+            fileIndex,
+            offset,
+            length,
+        }
+    }
+
+    #[test]
+    fn testFingerprintIndex() {
+        let index = ChunkIndex::new(false, 4);
+        assert!(matches!(
+            index.insert([1; 32], location(0, 0, 8)),
+            Lookup::Unique
+        ));
+        assert!(matches!(
+            index.insert([1; 32], location(0, 8, 8)),
+            Lookup::Duplicate(None)
+        ));
+        assert!(matches!(
+            index.insert([2; 32], location(0, 16, 8)),
+            Lookup::Unique
+        ));
+    }
+
+    #[test]
+    fn testLocationIndexKeepsFirstOccurrence() {
+        let index = ChunkIndex::new(true, 4);
+        assert!(matches!(
+            index.insert([1; 32], location(0, 0, 8)),
+            Lookup::Unique
+        ));
+        for offset in [8, 16] {
+            match index.insert([1; 32], location(1, offset, 8)) {
+                Lookup::Duplicate(Some(first)) => assert_eq!(first, location(0, 0, 8)),
+                _ => panic!("expected a duplicate with a recorded location"),
+            }
+        }
+    }
+
+    #[test]
+    fn testVerifyMatchingChunk() {
+        let mmaps = vec![mapBytes(b"abcdabcd")];
+        let stats = VerifyStats::default();
+
+        assert!(stats.verify(
+            &mmaps,
+            [0; 32],
+            location(0, 0, 4),
+            location(0, 4, 4),
+            b"abcd"
+        ));
+        assert_eq!(stats.verifiedCount.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.verifiedBytes.load(Ordering::Relaxed), 4);
+        assert_eq!(stats.collisionCount.load(Ordering::Relaxed), 0);
+        assert!(stats.collisions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn testVerifyReportsCollision() {
+        let mmaps = vec![mapBytes(b"abcd"), mapBytes(b"wxyz")];
+        let stats = VerifyStats::default();
+
+        assert!(!stats.verify(
+            &mmaps,
+            [7; 32],
+            location(0, 0, 4),
+            location(1, 0, 4),
+            b"wxyz"
+        ));
+        assert_eq!(stats.collisionCount.load(Ordering::Relaxed), 1);
+        let collisions = stats.collisions.lock().unwrap();
+        assert_eq!(collisions[0].hash, [7; 32]);
+        assert_eq!(collisions[0].first, location(0, 0, 4));
+        assert_eq!(collisions[0].second, location(1, 0, 4));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Compression applied to unique chunks to estimate dedup plus compression.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CompressionType {
+    #[default]
+    NONE,
+    LZ4,
+    ZSTD(i32),
+}
+
+impl FromStr for CompressionType {
+    type Err = String;
+
+    /// Accepts `none`, `lz4`, `zstd` and `zstd:<level>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), level) {
+            ("none", None) => Ok(CompressionType::NONE),
+            ("lz4", None) => Ok(CompressionType::LZ4),
+            ("zstd", None) => Ok(CompressionType::ZSTD(DEFAULT_ZSTD_LEVEL)),
+            ("zstd", Some(level)) => {
+                let level: i32 = level
+                    .parse()
+                    .map_err(|_| format!("Invalid zstd level {:?}", level))?;
+                if !zstd::compression_level_range().contains(&level) {
+                    return Err(format!(
+                        "zstd level {} is out of range {:?}",
+                        level,
+                        zstd::compression_level_range()
+                    ));
+                }
+                Ok(CompressionType::ZSTD(level))
+            }
+            _ => Err(format!(
+                "Unknown compression {:?}, expected none, lz4 or zstd[:level]",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionType::NONE => write!(f, "none"),
+            CompressionType::LZ4 => write!(f, "lz4"),
+            CompressionType::ZSTD(level) => write!(f, "zstd:{}", level),
+        }
+    }
+}
+
+/// Per-worker compressor that reuses its context and output buffer.
+pub struct Compressor {
+    zstd: Option<zstd::bulk::Compressor<'static>>,
+    compressionType: CompressionType,
+    buffer: Vec<u8>,
+}
+
+impl Compressor {
+    pub fn new(compressionType: CompressionType) -> Self {
+        let zstd = match compressionType {
+            CompressionType::ZSTD(level) => {
+                Some(zstd::bulk::Compressor::new(level).expect("valid zstd level"))
+            }
+            _ => None,
+        };
+        Self {
+            zstd,
+            compressionType,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Size the chunk would take on disk. Like a storage system, incompressible
+    /// chunks are kept as they are.
+    pub fn compressedSize(&mut self, chunk: &[u8]) -> usize {
+        let size = match self.compressionType {
+            CompressionType::NONE => chunk.len(),
+            CompressionType::LZ4 => {
+                self.buffer
+                    .resize(lz4_flex::block::get_maximum_output_size(chunk.len()), 0);
+                lz4_flex::block::compress_into(chunk, &mut self.buffer).unwrap_or(chunk.len())
+            }
+            CompressionType::ZSTD(_) => {
+                self.buffer.clear();
+                self.buffer
+                    .reserve(zstd::zstd_safe::compress_bound(chunk.len()));
+                self.zstd
+                    .as_mut()
+                    .unwrap()
+                    .compress_to_buffer(chunk, &mut self.buffer)
+                    .unwrap_or(chunk.len())
+            }
+        };
+        size.min(chunk.len())
+    }
+}
+
+/// Whether a unique chunk is part of the compression sample. The choice depends
+/// on the fingerprint only, so repeated runs compress the same chunks. It uses
+/// the last 8 bytes of the digest, which `--sample-bits` does not look at, so
+/// it stays one in `sampleRate` among the chunks sampled by fingerprint.
+pub fn isSampled(digest: &[u8], sampleRate: u64) -> bool {
+    let tail = &digest[digest.len() - 8..];
+    sampleRate <= 1 || u64::from_le_bytes(tail.try_into().unwrap()) % sampleRate == 0
+}
+
+/// Compression settings and the compressed unique chunks, shared by all workers.
+#[derive(Default)]
+pub struct CompressionStats {
+    pub compressionType: CompressionType,
+    /// One in this many unique chunks is compressed.
+    pub sampleRate: u64,
+    pub sampledCount: AtomicUsize,
+    pub sampledSize: AtomicUsize,
+    pub compressedSize: AtomicUsize,
+}
+
+impl CompressionStats {
+    pub fn new(compressionType: CompressionType, sampleRate: u64) -> Self {
+        Self {
+            compressionType,
+            sampleRate,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&self, sampledCount: usize, sampledSize: usize, compressedSize: usize) {
+        self.sampledCount.fetch_add(sampledCount, Ordering::Relaxed);
+        self.sampledSize.fetch_add(sampledSize, Ordering::Relaxed);
+        self.compressedSize
+            .fetch_add(compressedSize, Ordering::Relaxed);
+    }
+
+    /// Compressed size relative to the sampled size, 1.0 if nothing was sampled.
+    pub fn ratio(&self) -> f64 {
+        compressionRatio(
+            self.sampledSize.load(Ordering::Relaxed) as u64,
+            self.compressedSize.load(Ordering::Relaxed) as u64,
+        )
+    }
+}
+
+pub fn compressionRatio(sampledSize: u64, compressedSize: u64) -> f64 {
+    if sampledSize == 0 {
+        1.0
+    } else {
+        compressedSize as f64 / sampledSize as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::trace::sampling;
+
+    #[test]
+    fn testParseCompressionType() {
+        assert_eq!("none".parse(), Ok(CompressionType::NONE));
+        assert_eq!("LZ4".parse(), Ok(CompressionType::LZ4));
+        assert_eq!("zstd".parse(), Ok(CompressionType::ZSTD(3)));
+        assert_eq!("zstd:19".parse(), Ok(CompressionType::ZSTD(19)));
+        assert!("zstd:x".parse::<CompressionType>().is_err());
+        assert!("zstd:1000".parse::<CompressionType>().is_err());
+        assert!("lz4:1".parse::<CompressionType>().is_err());
+        assert!("gzip".parse::<CompressionType>().is_err());
+
+        for c in [
+            CompressionType::NONE,
+            CompressionType::LZ4,
+            CompressionType::ZSTD(7),
+        ] {
+            assert_eq!(c.to_string().parse(), Ok(c));
+        }
+    }
+
+    #[test]
+    fn testCompressedSize() {
+        let text = b"all work and no play makes jack a dull boy. ".repeat(100);
+        let mut random = vec![0u8; 4096];
+        let mut x: u64 = 0x9e3779b97f4a7c15;
+        for b in random.iter_mut() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = x as u8;
+        }
+
+        for c in [CompressionType::LZ4, CompressionType::ZSTD(3)] {
+            let mut compressor = Compressor::new(c);
+            assert!(compressor.compressedSize(&text) < text.len() / 10, "{c}");
+            // Incompressible data is stored as is.
+            assert_eq!(compressor.compressedSize(&random), random.len(), "{c}");
+            assert_eq!(compressor.compressedSize(&[]), 0, "{c}");
+        }
+        let mut none = Compressor::new(CompressionType::NONE);
+        assert_eq!(none.compressedSize(&text), text.len());
+    }
+
+    #[test]
+    fn testIsSampled() {
+        let mut hash = [0u8; 20];
+        assert!(isSampled(&hash, 1));
+        assert!(isSampled(&hash, 16));
+        hash[12] = 1;
+        assert!(!isSampled(&hash, 16));
+        assert!(isSampled(&hash, 1));
+
+        // Roughly one in N of a range of fingerprints.
+        let sampled = (0..1600u32)
+            .filter(|i| isSampled(blake3::hash(&i.to_le_bytes()).as_bytes(), 16))
+            .count();
+        assert!((50..150).contains(&sampled), "{sampled}");
+    }
+
+    #[test]
+    fn testIsSampledWithSampleBits() {
+        // Among the chunks kept by --sample-bits, still one in N is compressed,
+        // also for the shortest digest.
+        let kept: Vec<[u8; 32]> = (0..64_000u32)
+            .map(|i| {
+                let mut h = [0u8; 32];
+                h[..16].copy_from_slice(&blake3::hash(&i.to_le_bytes()).as_bytes()[..16]);
+                h
+            })
+            .filter(|h| sampling::isSampled(h, 3))
+            .collect();
+        let sampled = kept.iter().filter(|h| isSampled(&h[..16], 16)).count();
+        let expected = kept.len() / 16;
+        assert!(
+            sampled.abs_diff(expected) < expected / 4,
+            "{sampled} of {}",
+            kept.len()
+        );
+    }
+}
@@ -0,0 +1,62 @@
+/// Chunks longer than this are estimated from evenly spaced bytes.
+const MAX_SAMPLE: usize = 4096;
+
+/// Shannon entropy of the byte distribution in bits per byte, from 0 (a single
+/// byte value) to 8 (uniformly random). Encrypted and compressed data is close
+/// to 8.
+pub fn shannonEntropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let step = data.len().div_ceil(MAX_SAMPLE);
+    let mut counts = [0u32; 256];
+    let mut total = 0u32;
+    for &b in data.iter().step_by(step) {
+        counts[b as usize] += 1;
+        total += 1;
+    }
+
+    let total = total as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testShannonEntropy() {
+        assert_eq!(shannonEntropy(&[]), 0.0);
+        assert_eq!(shannonEntropy(&[7; 1000]), 0.0);
+        assert_eq!(shannonEntropy(&[0, 1, 0, 1]), 1.0);
+
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(shannonEntropy(&all), 8.0);
+
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(50);
+        let e = shannonEntropy(&text);
+        assert!((3.0..5.0).contains(&e), "{e}");
+    }
+
+    #[test]
+    fn testShannonEntropySampled() {
+        // Random data stays close to 8 bits when only every n-th byte is read.
+        let mut data = vec![0u8; 1 << 20];
+        let mut x: u64 = 0x9e3779b97f4a7c15;
+        for b in data.iter_mut() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = (x >> 32) as u8;
+        }
+        let e = shannonEntropy(&data);
+        assert!(e > 7.9, "{e}");
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+/// Number of leading bytes needed to recognize every signature below.
+pub const SNIFF_LENGTH: usize = 512;
+
+/// Signatures at fixed offsets, checked in order.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "elf"),
+    (0, b"MZ", "pe"),
+    (0, b"PK\x03\x04", "zip"),
+    (0, b"PK\x05\x06", "zip"),
+    (0, b"\x1f\x8b", "gzip"),
+    (0, b"BZh", "bzip2"),
+    (0, b"\xfd7zXZ\x00", "xz"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7z"),
+    (257, b"ustar", "tar"),
+    (0, b"\xff\xd8\xff", "jpeg"),
+    (0, b"\x89PNG\r\n\x1a\n", "png"),
+    (0, b"GIF8", "gif"),
+    (0, b"%PDF-", "pdf"),
+    (0, b"SQLite format 3\x00", "sqlite"),
+    (0, b"QFI\xfb", "qcow2"),
+    (0, b"KDMV", "vmdk"),
+    (0, b"conectix", "vhd"),
+    (0, b"vhdxfile", "vhdx"),
+    (64, b"<<< Oracle VM VirtualBox Disk Image >>>", "vdi"),
+    (4, b"ftyp", "mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "matroska"),
+    (0, b"ID3", "mp3"),
+    (0, b"OggS", "ogg"),
+    (0, b"fLaC", "flac"),
+    (0, b"RIFF", "riff"),
+];
+
+/// Fallback for files without a known signature, by lowercase extension.
+const EXTENSIONS: &[(&[&str], &str)] = &[
+    (&["txt", "md", "rst", "log", "csv", "tsv"], "text"),
+    (
+        &["json", "xml", "yaml", "yml", "toml", "ini", "conf"],
+        "config",
+    ),
+    (&["html", "htm", "css", "js", "ts"], "web"),
+    (
+        &[
+            "c", "h", "cc", "cpp", "hpp", "rs", "go", "py", "java", "rb", "sh", "pl",
+        ],
+        "source",
+    ),
+    (&["o", "a", "so", "dll", "lib", "class", "pyc"], "object"),
+    (&["iso", "img", "raw"], "disk-image"),
+    (&["qcow2"], "qcow2"),
+    (&["vmdk"], "vmdk"),
+    (&["vdi"], "vdi"),
+    (&["vhd"], "vhd"),
+    (&["vhdx"], "vhdx"),
+    (&["db", "sqlite", "sqlite3"], "sqlite"),
+    (&["mp3"], "mp3"),
+    (&["mp4", "m4v", "mov"], "mp4"),
+    (&["mkv", "webm"], "matroska"),
+    (&["jpg", "jpeg"], "jpeg"),
+    (&["png"], "png"),
+    (&["pdf"], "pdf"),
+    (&["zip", "jar", "docx", "xlsx", "pptx", "odt"], "zip"),
+    (&["gz", "tgz"], "gzip"),
+    (&["tar"], "tar"),
+];
+
+/// Classify a file by its leading bytes, falling back to its extension.
+pub fn classify(path: &Path, head: &[u8]) -> &'static str {
+    for (offset, magic, label) in SIGNATURES {
+        if head.len() >= offset + magic.len() && &head[*offset..offset + magic.len()] == *magic {
+            return label;
+        }
+    }
+
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    if let Some(extension) = extension {
+        for (extensions, label) in EXTENSIONS {
+            if extensions.contains(&extension.as_str()) {
+                return label;
+            }
+        }
+    }
+
+    if head.is_empty() {
+        "empty"
+    } else {
+        "unknown"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testClassifyByMagic() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x7fELF\x02\x01\x01", "elf"),
+            (b"PK\x03\x04\x14\x00", "zip"),
+            (b"\x1f\x8b\x08\x00", "gzip"),
+            (b"\xff\xd8\xff\xe0\x00\x10JFIF", "jpeg"),
+            (b"SQLite format 3\x00\x10\x00", "sqlite"),
+            (b"QFI\xfb\x00\x00\x00\x03", "qcow2"),
+            (b"\x00\x00\x00\x18ftypmp42", "mp4"),
+        ];
+        for (head, expected) in cases {
+            // The extension must not override the signature.
+            assert_eq!(classify(Path::new("file.txt"), head), *expected);
+        }
+    }
+
+    #[test]
+    fn testClassifyTar() {
+        let mut head = vec![0u8; SNIFF_LENGTH];
+        head[257..262].copy_from_slice(b"ustar");
+        assert_eq!(classify(Path::new("backup"), &head), "tar");
+        // Too short to contain the signature.
+        assert_eq!(classify(Path::new("backup"), &head[..200]), "unknown");
+    }
+
+    #[test]
+    fn testClassifyByExtension() {
+        assert_eq!(classify(Path::new("/a/b/notes.TXT"), b"hello"), "text");
+        assert_eq!(classify(Path::new("disk.vmdk"), b"\x00\x00"), "vmdk");
+        assert_eq!(classify(Path::new("main.rs"), b"fn main"), "source");
+        assert_eq!(classify(Path::new("blob"), b"\x00\x01"), "unknown");
+        assert_eq!(classify(Path::new("blob"), b""), "empty");
+    }
+}
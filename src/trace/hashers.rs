@@ -3,22 +3,49 @@ use digest::Digest;
 use md5::Md5;
 use sha1::Sha1;
 use sha2::Sha256;
+use std::sync::Arc;
+
+impl HashType {
+    /// Number of significant bytes in the 32 byte digests returned by [`Hasher::hash`].
+    pub fn digestLength(&self) -> usize {
+        match self {
+            HashType::BLAKE3 | HashType::SHA256 => 32,
+            HashType::SHA1 => 20,
+            HashType::MD5 => 16,
+        }
+    }
+}
 
 pub trait Hasher: Send {
     fn hash(&self, chunk: &[u8]) -> [u8; 32];
 }
 
-struct Blake3Hasher;
+/// Fed to the hash function before every chunk, so the same content gets
+/// different fingerprints under different salts. Empty without `--salt`.
+type Salt = Arc<[u8]>;
+
+struct Blake3Hasher {
+    salt: Salt,
+}
 impl Hasher for Blake3Hasher {
     fn hash(&self, chunk: &[u8]) -> [u8; 32] {
-        *blake3::hash(chunk).as_bytes()
+        if self.salt.is_empty() {
+            return *blake3::hash(chunk).as_bytes();
+        }
+        let mut h = blake3::Hasher::new();
+        h.update(&self.salt);
+        h.update(chunk);
+        *h.finalize().as_bytes()
     }
 }
 
-struct Sha1Hasher;
+struct Sha1Hasher {
+    salt: Salt,
+}
 impl Hasher for Sha1Hasher {
     fn hash(&self, chunk: &[u8]) -> [u8; 32] {
         let mut h = Sha1::new();
+        h.update(&self.salt);
         h.update(chunk);
         let mut out = [0u8; 32];
         out[..20].copy_from_slice(&h.finalize());
@@ -26,10 +53,13 @@ impl Hasher for Sha1Hasher {
     }
 }
 
-struct Sha256Hasher;
+struct Sha256Hasher {
+    salt: Salt,
+}
 impl Hasher for Sha256Hasher {
     fn hash(&self, chunk: &[u8]) -> [u8; 32] {
         let mut h = Sha256::new();
+        h.update(&self.salt);
         h.update(chunk);
         let mut out = [0u8; 32];
         out.copy_from_slice(&h.finalize());
@@ -37,10 +67,13 @@ impl Hasher for Sha256Hasher {
     }
 }
 
-struct Md5Hasher;
+struct Md5Hasher {
+    salt: Salt,
+}
 impl Hasher for Md5Hasher {
     fn hash(&self, chunk: &[u8]) -> [u8; 32] {
         let mut h = Md5::new();
+        h.update(&self.salt);
         h.update(chunk);
         let mut out = [0u8; 32];
         out[..16].copy_from_slice(&h.finalize());
@@ -48,22 +81,50 @@ impl Hasher for Md5Hasher {
     }
 }
 
+/// Hashed with the salt to tell traces with the same salt apart from others
+/// without storing the salt itself.
+const SALT_VERIFIER_INPUT: &[u8] = b"chunkIQ salt verifier";
+/// Bytes of the verifier kept in the trace header.
+const SALT_VERIFIER_LENGTH: usize = 8;
+
 pub struct HasherFactory {
     t: HashType,
+    salt: Salt,
 }
 
 impl HasherFactory {
     pub fn new(hashType: HashType) -> Self {
-        Self { t: hashType }
+        Self {
+            t: hashType,
+            salt: Arc::from(&[][..]),
+        }
+    }
+
+    pub fn withSalt(mut self, salt: Option<&str>) -> Self {
+        self.salt = Arc::from(salt.unwrap_or_default().as_bytes());
+        self
+    }
+
+    pub fn digestLength(&self) -> usize {
+        self.t.digestLength()
     }
 
     pub fn createHasher(&self) -> Box<dyn Hasher> {
+        let salt = Arc::clone(&self.salt);
         match self.t {
-            HashType::BLAKE3 => Box::new(Blake3Hasher),
-            HashType::SHA1 => Box::new(Sha1Hasher),
-            HashType::SHA256 => Box::new(Sha256Hasher),
-            HashType::MD5 => Box::new(Md5Hasher),
+            HashType::BLAKE3 => Box::new(Blake3Hasher { salt }),
+            HashType::SHA1 => Box::new(Sha1Hasher { salt }),
+            HashType::SHA256 => Box::new(Sha256Hasher { salt }),
+            HashType::MD5 => Box::new(Md5Hasher { salt }),
+        }
+    }
+
+    /// Identifies the salt in a trace header; empty without a salt.
+    pub fn saltVerifier(&self) -> Vec<u8> {
+        if self.salt.is_empty() {
+            return Vec::new();
         }
+        self.createHasher().hash(SALT_VERIFIER_INPUT)[..SALT_VERIFIER_LENGTH].to_vec()
     }
 }
 
@@ -203,4 +264,31 @@ mod test {
             );
         }
     }
+
+    // ── Salt tests ────────────────────────────────────────────────
+
+    #[test]
+    fn testSalt() {
+        let data = createTestData();
+        for &hashType in ALL_HASH_TYPES {
+            let plain = HasherFactory::new(hashType);
+            let salted = HasherFactory::new(hashType).withSalt(Some("pepper"));
+            // A salt is the same as a prefix of the input.
+            assert_eq!(
+                salted.createHasher().hash(data.as_bytes()),
+                plain
+                    .createHasher()
+                    .hash(format!("pepper{}", data).as_bytes()),
+                "salt not applied for {hashType:?}"
+            );
+            assert!(plain.saltVerifier().is_empty());
+            assert_eq!(salted.saltVerifier().len(), 8);
+            assert_ne!(
+                salted.saltVerifier(),
+                HasherFactory::new(hashType)
+                    .withSalt(Some("salt"))
+                    .saltVerifier()
+            );
+        }
+    }
 }
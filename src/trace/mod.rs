@@ -1,2 +1,9 @@
+pub mod chunkIndex;
+pub mod compression;
+pub mod entropy;
+pub mod fileType;
 pub mod hashers;
+pub mod pattern;
+pub mod resemblance;
+pub mod sampling;
 pub mod tracer;
@@ -0,0 +1,122 @@
+/// Content classes of chunks that dedup trivially, or that a storage system would
+/// rather not store at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChunkPattern {
+    ORDINARY,
+    /// Only zero bytes.
+    ZERO,
+    /// One non-zero byte value repeated over the whole chunk.
+    REPEATED,
+}
+
+/// Classify a chunk by its content. Empty chunks are ordinary.
+pub fn classify(chunk: &[u8]) -> ChunkPattern {
+    let Some(&first) = chunk.first() else {
+        return ChunkPattern::ORDINARY;
+    };
+    // Compare word by word; this runs on every chunk of the input.
+    let word = u64::from_ne_bytes([first; 8]);
+    let (words, tail) = chunk.as_chunks::<8>();
+    if !words.iter().all(|w| u64::from_ne_bytes(*w) == word) || !tail.iter().all(|&b| b == first) {
+        return ChunkPattern::ORDINARY;
+    }
+    if first == 0 {
+        ChunkPattern::ZERO
+    } else {
+        ChunkPattern::REPEATED
+    }
+}
+
+/// Counters for the chunks of one pattern class.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PatternCounts {
+    pub chunkCount: u64,
+    pub size: u64,
+    pub dupCount: u64,
+    pub dupSize: u64,
+}
+
+impl PatternCounts {
+    pub fn record(&mut self, length: usize, isDuplicate: bool) {
+        self.chunkCount += 1;
+        self.size += length as u64;
+        if isDuplicate {
+            self.dupCount += 1;
+            self.dupSize += length as u64;
+        }
+    }
+
+    pub fn merge(&mut self, other: &PatternCounts) {
+        self.chunkCount += other.chunkCount;
+        self.size += other.size;
+        self.dupCount += other.dupCount;
+        self.dupSize += other.dupSize;
+    }
+}
+
+/// Zero-filled and single-byte chunks, counted apart from ordinary data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PatternStats {
+    pub zero: PatternCounts,
+    pub repeated: PatternCounts,
+}
+
+impl PatternStats {
+    pub fn record(&mut self, pattern: ChunkPattern, length: usize, isDuplicate: bool) {
+        match pattern {
+            ChunkPattern::ORDINARY => {}
+            ChunkPattern::ZERO => self.zero.record(length, isDuplicate),
+            ChunkPattern::REPEATED => self.repeated.record(length, isDuplicate),
+        }
+    }
+
+    pub fn merge(&mut self, other: &PatternStats) {
+        self.zero.merge(&other.zero);
+        self.repeated.merge(&other.repeated);
+    }
+
+    /// Both classes together.
+    pub fn trivial(&self) -> PatternCounts {
+        let mut total = self.zero;
+        total.merge(&self.repeated);
+        total
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testClassify() {
+        assert_eq!(classify(&[]), ChunkPattern::ORDINARY);
+        assert_eq!(classify(&[0; 4096]), ChunkPattern::ZERO);
+        assert_eq!(classify(&[0; 13]), ChunkPattern::ZERO);
+        assert_eq!(classify(&[0xff; 4099]), ChunkPattern::REPEATED);
+        assert_eq!(classify(b"a"), ChunkPattern::REPEATED);
+
+        // A single different byte anywhere, including the unaligned tail.
+        for position in [0, 7, 8, 4095, 4098] {
+            let mut chunk = vec![0u8; 4099];
+            chunk[position] = 1;
+            assert_eq!(classify(&chunk), ChunkPattern::ORDINARY, "at {position}");
+        }
+    }
+
+    #[test]
+    fn testStats() {
+        let mut stats = PatternStats::default();
+        stats.record(ChunkPattern::ZERO, 4096, false);
+        stats.record(ChunkPattern::ZERO, 4096, true);
+        stats.record(ChunkPattern::REPEATED, 100, true);
+        stats.record(ChunkPattern::ORDINARY, 100, true);
+
+        assert_eq!(stats.zero.chunkCount, 2);
+        assert_eq!(stats.zero.dupSize, 4096);
+        let trivial = stats.trivial();
+        assert_eq!(trivial.chunkCount, 3);
+        assert_eq!(trivial.size, 8292);
+        assert_eq!(trivial.dupCount, 2);
+        assert_eq!(trivial.dupSize, 4196);
+    }
+}
@@ -0,0 +1,119 @@
+/// Features per chunk, grouped into `SUPER_FEATURE_COUNT` super-features.
+pub const FEATURE_COUNT: usize = 12;
+pub const SUPER_FEATURE_COUNT: usize = 3;
+const FEATURES_PER_SUPER_FEATURE: usize = FEATURE_COUNT / SUPER_FEATURE_COUNT;
+
+/// Bytes covered by the rolling hash; a 64-bit gear hash forgets older bytes.
+const WINDOW: usize = 64;
+/// Only windows whose hash starts with this many zero bits are considered,
+/// chosen by content so similar chunks consider the same windows.
+const WINDOW_SAMPLE_BITS: u32 = 3;
+
+pub type Features = [u32; FEATURE_COUNT];
+
+pub const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+const fn table<const N: usize>(seed: u64, odd: bool) -> [u64; N] {
+    let mut result = [0u64; N];
+    let mut i = 0;
+    while i < N {
+        result[i] = splitmix64(seed.wrapping_add((i as u64).wrapping_mul(0x632be59bd9b4e019)));
+        if odd {
+            result[i] |= 1;
+        }
+        i += 1;
+    }
+    result
+}
+
+const GEAR: [u64; 256] = table(0x5eed_0001, false);
+const MULTIPLIERS: [u64; FEATURE_COUNT] = table(0x5eed_0002, true);
+const ADDENDS: [u64; FEATURE_COUNT] = table(0x5eed_0003, false);
+
+/// Resemblance features of a chunk in the style of the N-transform (Broder,
+/// Shilane et al.): each feature is the maximum of one linear transform over
+/// the rolling hashes of the chunk's windows. Two chunks share a feature with
+/// a probability close to the share of windows they have in common. Chunks
+/// shorter than a window have all-zero features.
+pub fn features(chunk: &[u8]) -> Features {
+    let mut result = [0u32; FEATURE_COUNT];
+    let mut hash: u64 = 0;
+    for (i, &byte) in chunk.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if i + 1 < WINDOW || hash >> (64 - WINDOW_SAMPLE_BITS) != 0 {
+            continue;
+        }
+        for (feature, (m, a)) in result.iter_mut().zip(MULTIPLIERS.iter().zip(&ADDENDS)) {
+            let value = (m.wrapping_mul(hash).wrapping_add(*a) >> 32) as u32;
+            *feature = (*feature).max(value);
+        }
+    }
+    result
+}
+
+/// Super-features combine consecutive features, so two chunks only share one
+/// if they share all of its features. A shared super-feature marks a likely
+/// base for delta compression.
+pub fn superFeatures(features: &Features) -> [u64; SUPER_FEATURE_COUNT] {
+    let mut result = [0u64; SUPER_FEATURE_COUNT];
+    for (sf, group) in result
+        .iter_mut()
+        .zip(features.chunks(FEATURES_PER_SUPER_FEATURE))
+    {
+        *sf = group.iter().fold(0, |acc, &f| splitmix64(acc ^ f as u64));
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn randomData(seed: u64, length: usize) -> Vec<u8> {
+        (0..length as u64)
+            .map(|i| splitmix64(seed ^ i.wrapping_mul(0x9e3779b97f4a7c15)) as u8)
+            .collect()
+    }
+
+    fn sharedFeatures(a: &Features, b: &Features) -> usize {
+        a.iter().zip(b).filter(|(x, y)| x == y).count()
+    }
+
+    #[test]
+    fn testSimilarChunksShareFeatures() {
+        let base = randomData(1, 8192);
+        let mut edited = base.clone();
+        edited[4000..4010].fill(0);
+        let other = randomData(2, 8192);
+
+        let f = features(&base);
+        assert_eq!(f, features(&base));
+        assert!(sharedFeatures(&f, &features(&edited)) >= 10);
+        assert_eq!(sharedFeatures(&f, &features(&other)), 0);
+
+        let sf = superFeatures(&f);
+        assert!(sf
+            .iter()
+            .zip(superFeatures(&features(&edited)))
+            .any(|(a, b)| *a == b));
+    }
+
+    #[test]
+    fn testShiftedContent() {
+        // Inserting bytes at the front only moves the windows.
+        let base = randomData(3, 8192);
+        let mut shifted = vec![7u8; 100];
+        shifted.extend_from_slice(&base[..8092]);
+        assert!(sharedFeatures(&features(&base), &features(&shifted)) >= 10);
+    }
+
+    #[test]
+    fn testShortChunk() {
+        assert_eq!(features(&[1; WINDOW - 1]), [0; FEATURE_COUNT]);
+    }
+}
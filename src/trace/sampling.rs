@@ -0,0 +1,40 @@
+/// Upper limit for `--sample-bits`; beyond this hardly any chunk is kept.
+pub const MAX_SAMPLE_BITS: u8 = 24;
+
+/// Whether a chunk is kept when sampling by fingerprint: its first `sampleBits`
+/// bits must be zero. The decision only depends on the content, so a chunk is
+/// either kept in every file and trace or in none, and one in 2^`sampleBits`
+/// distinct chunks is kept.
+pub fn isSampled(hash: &[u8; 32], sampleBits: u8) -> bool {
+    sampleBits == 0
+        || u32::from_be_bytes(hash[..4].try_into().unwrap()).leading_zeros() >= sampleBits as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testIsSampled() {
+        let mut hash = [0xffu8; 32];
+        assert!(isSampled(&hash, 0));
+        assert!(!isSampled(&hash, 1));
+
+        hash[0] = 0x0f;
+        assert!(isSampled(&hash, 4));
+        assert!(!isSampled(&hash, 5));
+
+        hash[..3].fill(0);
+        hash[3] = 0x80;
+        assert!(isSampled(&hash, MAX_SAMPLE_BITS));
+    }
+
+    #[test]
+    fn testSampleRate() {
+        let kept = (0..64_000u32)
+            .filter(|i| isSampled(blake3::hash(&i.to_le_bytes()).as_bytes(), 6))
+            .count();
+        // 1000 expected, the bounds are about six standard deviations.
+        assert!((800..1200).contains(&kept), "{kept}");
+    }
+}
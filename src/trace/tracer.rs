@@ -1,42 +1,72 @@
 use crate::chunker::chunker::ChunkFactory;
+use crate::trace::chunkIndex::{ChunkIndex, ChunkLocation, Lookup, VerifyStats};
+use crate::trace::compression::{self, CompressionStats, CompressionType, Compressor};
+use crate::trace::entropy;
+use crate::trace::fileType::{self, SNIFF_LENGTH};
 use crate::trace::hashers::HasherFactory;
-use crate::tui::tui::{FileStatus, TraceUiState};
+use crate::trace::pattern::{self, ChunkPattern, PatternStats};
+use crate::trace::resemblance;
+use crate::trace::sampling;
+use crate::tui::tui::{FileStats, FileStatus, TraceUiState};
 
-use crossbeam_channel::{bounded, Receiver};
+use crossbeam_channel::{bounded, Receiver, Sender};
 use dashmap::{DashMap, DashSet};
 use memmap2::{Advice, Mmap};
 
 use crate::util::arguments::TraceArgs;
+use crate::util::histogram::SizeHistogram;
+use crate::util::hyperLogLog::HyperLogLog;
+use crate::util::traceFile::{
+    ChunkRecord, FileRecord, TraceHeader, TraceSummary, TraceWriter, CHUNK_COMPRESSED,
+    CHUNK_DUPLICATE, CHUNK_FEATURES, CHUNK_REPEATED, CHUNK_ZERO,
+};
 
 use std::{
     cmp::min,
     fs::File,
-    io::Result,
+    io::{BufWriter, Error, Result},
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-const WORK_UNIT_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+pub const WORK_UNIT_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
 
 pub struct ChunkingTask {
     mmap: Arc<Mmap>,
     offset: usize,
     length: usize,
-    fileName: String,
+    fileIndex: usize,
+    /// Fingerprints seen in this file so far, shared by all of its tasks and
+    /// freed once the last one is dropped.
+    fileFingerprints: Arc<DashSet<[u8; 32]>>,
 }
 
 fn spawnWorkers(
     numWorkers: usize,
     receiver: Receiver<ChunkingTask>,
-    hashSet: Arc<DashSet<[u8; 32]>>,
-    fileStats: Arc<DashMap<String, FileStatus>>,
+    index: Arc<ChunkIndex>,
+    mmaps: Arc<Vec<Arc<Mmap>>>,
+    verifyStats: Option<Arc<VerifyStats>>,
+    compressionStats: Option<Arc<CompressionStats>>,
+    traceSender: Option<Sender<Vec<ChunkRecord>>>,
+    measureEntropy: bool,
+    superFeatures: bool,
+    hashFiles: bool,
+    sampleBits: u8,
+    fileStats: Arc<DashMap<usize, FileStats>>,
     completedTasks: Arc<AtomicUsize>,
     globalChunkCount: Arc<AtomicUsize>,
     globalDupCount: Arc<AtomicUsize>,
     globalDupSize: Arc<AtomicUsize>,
+    globalUniqueSizes: Arc<Mutex<SizeHistogram>>,
+    globalDupSizes: Arc<Mutex<SizeHistogram>>,
+    globalPatternStats: Arc<Mutex<PatternStats>>,
+    globalSketch: Option<Arc<Mutex<HyperLogLog>>>,
     hasherFactory: Arc<HasherFactory>,
     chunkFactory: Arc<ChunkFactory>,
 ) -> Vec<thread::JoinHandle<()>> {
@@ -44,14 +74,26 @@ fn spawnWorkers(
 
     for _ in 0..numWorkers {
         let receiver = receiver.clone();
-        let hashSet = Arc::clone(&hashSet);
+        let index = Arc::clone(&index);
+        let mmaps = Arc::clone(&mmaps);
+        let verifyStats = verifyStats.clone();
+        let compressionStats = compressionStats.clone();
+        let mut compressor = compressionStats
+            .as_ref()
+            .map(|stats| Compressor::new(stats.compressionType));
+        let traceSender = traceSender.clone();
         let fileStats = Arc::clone(&fileStats);
         let completedTasks = Arc::clone(&completedTasks);
         let globalChunkCount = Arc::clone(&globalChunkCount);
         let globalDupCount = Arc::clone(&globalDupCount);
         let globalDupSize = Arc::clone(&globalDupSize);
+        let globalUniqueSizes = Arc::clone(&globalUniqueSizes);
+        let globalDupSizes = Arc::clone(&globalDupSizes);
+        let globalPatternStats = Arc::clone(&globalPatternStats);
+        let globalSketch = globalSketch.clone();
         let chunker = chunkFactory.createChunker();
         let hasher = hasherFactory.createHasher();
+        let digestLength = hasherFactory.digestLength();
 
         let handle = thread::spawn(move || {
             while let Ok(task) = receiver.recv() {
@@ -63,10 +105,10 @@ fn spawnWorkers(
                 }
 
                 // Mark file as in-progress on first task picked up for it
-                if let Some(mut s) = fileStats.get_mut(&task.fileName) {
-                    if matches!(*s, FileStatus::Queued) {
-                        *s = FileStatus::Processing;
-                    }
+                if let Some(mut s) = fileStats.get_mut(&task.fileIndex)
+                    && matches!(s.status, FileStatus::Queued)
+                {
+                    s.status = FileStatus::Processing;
                 }
 
                 let fileContent = &task.mmap[task.offset..task.offset + task.length];
@@ -75,25 +117,163 @@ fn spawnWorkers(
                 let mut localChunkCount: usize = 0;
                 let mut localDupCount: usize = 0;
                 let mut localDupSize: usize = 0;
+                let mut localInternalDupSize: usize = 0;
+                let mut localEntropySum: f64 = 0.0;
+                let mut localUniqueSizes = SizeHistogram::new();
+                let mut localDupSizes = SizeHistogram::new();
+                let mut localPatternStats = PatternStats::default();
+                let mut localSketch = globalSketch.as_ref().map(|_| HyperLogLog::default());
+                let mut localCompressedCount: usize = 0;
+                let mut localCompressInputSize: usize = 0;
+                let mut localCompressOutputSize: usize = 0;
+                let mut records: Vec<ChunkRecord> = Vec::new();
+                let mut fileDigest: Option<[u8; 32]> = None;
+
+                let mut chunkOffset = task.offset;
 
                 for chunk in chunks {
-                    localChunkCount += 1;
+                    // Entropy describes the file, so it is measured on every chunk.
+                    if measureEntropy {
+                        localEntropySum += entropy::shannonEntropy(chunk) * chunk.len() as f64;
+                    }
+
                     let hash = hasher.hash(chunk);
-                    if !hashSet.insert(hash) {
+                    if hashFiles && chunk.len() == task.mmap.len() {
+                        fileDigest = Some(hash);
+                    }
+                    let location = ChunkLocation {
+                        fileIndex: task.fileIndex,
+                        offset: chunkOffset,
+                        length: chunk.len(),
+                    };
+                    chunkOffset += chunk.len();
+
+                    // Chunks outside of the sample are neither indexed nor counted.
+                    if !sampling::isSampled(&hash, sampleBits) {
+                        continue;
+                    }
+                    localChunkCount += 1;
+                    if let Some(ref mut sketch) = localSketch {
+                        sketch.insert(&hash);
+                    }
+
+                    let isDuplicate = match index.insert(hash, location) {
+                        Lookup::Unique => false,
+                        Lookup::Duplicate(None) => true,
+                        Lookup::Duplicate(Some(first)) => match verifyStats {
+                            Some(ref stats) => stats.verify(&mmaps, hash, first, location, chunk),
+                            None => true,
+                        },
+                    };
+                    // Remember every fingerprint of this file, so a duplicate can be
+                    // attributed to the file itself or to another one.
+                    let seenInFile = index.isEnabled() && !task.fileFingerprints.insert(hash);
+                    if isDuplicate {
                         localDupCount += 1;
                         localDupSize += chunk.len();
+                        localDupSizes.record(chunk.len() as u64);
+                        if seenInFile {
+                            localInternalDupSize += chunk.len();
+                        }
+                    } else {
+                        localUniqueSizes.record(chunk.len() as u64);
+                    }
+
+                    let chunkPattern = pattern::classify(chunk);
+                    localPatternStats.record(chunkPattern, chunk.len(), isDuplicate);
+
+                    // Only unique chunks are stored, so only they are compressed.
+                    let mut compressedSize = None;
+                    if let (Some(stats), Some(compressor)) = (&compressionStats, &mut compressor)
+                        && !isDuplicate
+                        && compression::isSampled(&hash[..digestLength], stats.sampleRate)
+                    {
+                        let size = compressor.compressedSize(chunk);
+                        localCompressedCount += 1;
+                        localCompressInputSize += chunk.len();
+                        localCompressOutputSize += size;
+                        compressedSize = Some(size as u32);
                     }
+
+                    if traceSender.is_some() {
+                        let mut flags = if isDuplicate { CHUNK_DUPLICATE } else { 0 };
+                        if compressedSize.is_some() {
+                            flags |= CHUNK_COMPRESSED;
+                        }
+                        flags |= match chunkPattern {
+                            ChunkPattern::ORDINARY => 0,
+                            ChunkPattern::ZERO => CHUNK_ZERO,
+                            ChunkPattern::REPEATED => CHUNK_REPEATED,
+                        };
+                        // Duplicates resemble their first occurrence, so only unique
+                        // chunks need features.
+                        let features = (superFeatures && !isDuplicate)
+                            .then(|| Box::new(resemblance::features(chunk)));
+                        if features.is_some() {
+                            flags |= CHUNK_FEATURES;
+                        }
+                        records.push(ChunkRecord {
+                            fileIndex: task.fileIndex as u32,
+                            offset: location.offset as u64,
+                            length: chunk.len() as u32,
+                            flags,
+                            digest: hash,
+                            compressedSize: compressedSize.unwrap_or(0),
+                            features,
+                        });
+                    }
+                }
+
+                if let Some(ref sender) = traceSender
+                    && !records.is_empty()
+                {
+                    // A failed send means the trace writer gave up; its error is
+                    // reported once all workers have finished.
+                    let _ = sender.send(records);
                 }
 
                 // Flush per task (once per 64 MiB) so TUI stats stay live
                 globalChunkCount.fetch_add(localChunkCount, Ordering::Relaxed);
                 globalDupCount.fetch_add(localDupCount, Ordering::Relaxed);
                 globalDupSize.fetch_add(localDupSize, Ordering::Relaxed);
+                globalUniqueSizes.lock().unwrap().merge(&localUniqueSizes);
+                globalDupSizes.lock().unwrap().merge(&localDupSizes);
+                globalPatternStats
+                    .lock()
+                    .unwrap()
+                    .merge(&localPatternStats);
+                if let (Some(global), Some(local)) = (&globalSketch, &localSketch) {
+                    global.lock().unwrap().merge(local);
+                }
+                if let Some(ref stats) = compressionStats {
+                    stats.add(
+                        localCompressedCount,
+                        localCompressInputSize,
+                        localCompressOutputSize,
+                    );
+                }
+
+                // Per-file counts are compared with the file size, so they are
+                // scaled up from the sample to the whole file.
+                let mut isLastTask = false;
+                if let Some(mut s) = fileStats.get_mut(&task.fileIndex) {
+                    s.chunkCount += localChunkCount << sampleBits;
+                    s.dupCount += localDupCount << sampleBits;
+                    s.dupSize += localDupSize << sampleBits;
+                    s.internalDupSize += localInternalDupSize << sampleBits;
+                    s.entropySum += localEntropySum;
+                    s.pendingTasks -= 1;
+                    isLastTask = s.pendingTasks == 0;
+                }
 
-                // Mark file done when its last work unit completes
-                if task.mmap.len() == task.offset + task.length {
-                    if let Some(mut s) = fileStats.get_mut(&task.fileName) {
-                        *s = FileStatus::Done;
+                // Mark file done when its last work unit completes, after taking
+                // the whole-file digest. A file that is a single chunk has it already.
+                if isLastTask {
+                    let digest = hashFiles
+                        .then(|| fileDigest.unwrap_or_else(|| hasher.hash(&task.mmap[..])));
+                    if let Some(mut s) = fileStats.get_mut(&task.fileIndex) {
+                        s.digest = digest;
+                        s.status = FileStatus::Done;
                     }
                 }
 
@@ -108,21 +288,27 @@ fn spawnWorkers(
 
 pub fn run(args: &TraceArgs) -> Result<()> {
     let mut tasks: Vec<ChunkingTask> = Vec::new();
-    let hasherFactory = Arc::new(HasherFactory::new(args.hashType));
+    let hasherFactory =
+        Arc::new(HasherFactory::new(args.hashType).withSalt(args.hashSalt.as_deref()));
     let chunkFactory = Arc::new(ChunkFactory::new(args.chunkerType));
-    let fileStats: Arc<DashMap<String, FileStatus>> = Arc::new(DashMap::new());
+    // Keyed by file index, since the same path may be given twice.
+    let fileStats: Arc<DashMap<usize, FileStats>> = Arc::new(DashMap::new());
+    let mut mmaps: Vec<Arc<Mmap>> = Vec::with_capacity(args.fileNames.len());
     let mut totalBytes: usize = 0;
 
-    for filename in &args.fileNames {
+    for (fileIndex, filename) in args.fileNames.iter().enumerate() {
         let file = File::open(filename)?;
         let mmap = unsafe { Mmap::map(&file)? };
         let _ = mmap.advise(Advice::Sequential);
         let mmap = Arc::new(mmap);
         let fileLength = mmap.len();
         let fname: String = filename.to_string_lossy().into_owned();
+        let fileType = fileType::classify(filename, &mmap[..min(fileLength, SNIFF_LENGTH)]);
 
         totalBytes += fileLength;
 
+        let fileFingerprints = Arc::new(DashSet::new());
+        let mut pendingTasks = 0;
         let mut offset = 0;
         while offset < fileLength {
             let length = min(WORK_UNIT_SIZE, fileLength - offset);
@@ -130,12 +316,25 @@ pub fn run(args: &TraceArgs) -> Result<()> {
                 mmap: Arc::clone(&mmap),
                 offset,
                 length,
-                fileName: fname.clone(),
+                fileIndex,
+                fileFingerprints: Arc::clone(&fileFingerprints),
             });
+            pendingTasks += 1;
             offset += length;
         }
 
-        fileStats.insert(fname, FileStatus::Queued);
+        fileStats.insert(
+            fileIndex,
+            FileStats {
+                name: fname,
+                status: FileStatus::Queued,
+                size: fileLength,
+                fileType,
+                pendingTasks,
+                ..Default::default()
+            },
+        );
+        mmaps.push(mmap);
     }
 
     let numTasks = tasks.len();
@@ -145,20 +344,68 @@ pub fn run(args: &TraceArgs) -> Result<()> {
     // Estimate unique chunk count to pre-size the hash set and avoid rehashing.
     // For FILE chunking getSize() returns 0, so fall back to one slot per file.
     let avgChunkSize = args.chunkerType.getSize();
-    let estimatedChunks = if avgChunkSize == 0 {
-        numFiles
-    } else {
-        (totalBytes / avgChunkSize).max(1)
-    };
+    let estimatedChunks = totalBytes
+        .checked_div(avgChunkSize)
+        .map_or(numFiles, |n| (n >> args.sampleBits).max(1));
 
     let (sender, receiver) = bounded(numWorkers * 4);
-    let hashSet: Arc<DashSet<[u8; 32]>> = Arc::new(DashSet::with_capacity(estimatedChunks));
+    // Only unique chunks are compressed and carry features, so those still need
+    // the duplicate test when only sketching.
+    let needsIndex =
+        !args.sketchOnly || args.compression != CompressionType::NONE || args.superFeatures;
+    let index = Arc::new(if !needsIndex {
+        ChunkIndex::Disabled
+    } else {
+        ChunkIndex::new(args.verifyDuplicates, estimatedChunks)
+    });
+    let mmaps = Arc::new(mmaps);
+    let verifyStats = args
+        .verifyDuplicates
+        .then(|| Arc::new(VerifyStats::default()));
+    let compressionStats = (args.compression != CompressionType::NONE)
+        .then(|| Arc::new(CompressionStats::new(args.compression, args.compressSampleRate)));
     let completedTasks = Arc::new(AtomicUsize::new(0));
     let chunkCount = Arc::new(AtomicUsize::new(0));
     let dupCount = Arc::new(AtomicUsize::new(0));
     let dupSize = Arc::new(AtomicUsize::new(0));
+    let uniqueSizes = Arc::new(Mutex::new(SizeHistogram::new()));
+    let dupSizes = Arc::new(Mutex::new(SizeHistogram::new()));
+    let patternStats = Arc::new(Mutex::new(PatternStats::default()));
+    let sketch = args
+        .sketch
+        .then(|| Arc::new(Mutex::new(HyperLogLog::default())));
     let isDone = Arc::new(AtomicBool::new(false));
 
+    let header = TraceHeader {
+        toolVersion: env!("CARGO_PKG_VERSION").to_string(),
+        chunker: format!("{:?}", args.chunkerType),
+        hasher: format!("{:?}", args.hashType),
+        digestLength: args.hashType.digestLength(),
+        startTime: unixTime(),
+        compression: args.compression.to_string(),
+        compressSampleRate: args.compressSampleRate,
+        sampleBits: args.sampleBits,
+        sketchOnly: !index.isEnabled(),
+        saltVerifier: hasherFactory.saltVerifier(),
+    };
+    let traceWriter = match args.outputFile {
+        Some(ref path) => Some(TraceWriter::create(path, header)?),
+        None => None,
+    };
+
+    // A dedicated thread writes the trace so workers never block on the output
+    // file. Chunk records are only sent on request; they dominate the trace size.
+    let (traceSender, traceReceiver) = bounded::<Vec<ChunkRecord>>(numWorkers * 4);
+    let traceHandle = traceWriter.map(|mut writer| {
+        thread::spawn(move || -> Result<TraceWriter<BufWriter<File>>> {
+            for records in traceReceiver {
+                writer.writeChunks(records[0].fileIndex, &records)?;
+            }
+            Ok(writer)
+        })
+    });
+    let traceSender = (traceHandle.is_some() && args.logFingerprints).then_some(traceSender);
+
     let uiState = Arc::new(TraceUiState {
         totalTasks: numTasks,
         totalFiles: numFiles,
@@ -166,11 +413,19 @@ pub fn run(args: &TraceArgs) -> Result<()> {
         chunkCount: Arc::clone(&chunkCount),
         dupCount: Arc::clone(&dupCount),
         dupSize: Arc::clone(&dupSize),
+        uniqueSizes: Arc::clone(&uniqueSizes),
+        dupSizes: Arc::clone(&dupSizes),
+        patternStats: Arc::clone(&patternStats),
+        sketch: sketch.clone(),
+        sampleBits: args.sampleBits,
+        chunkerBounds: args.chunkerType.getBounds(),
         fileStats: Arc::clone(&fileStats),
         isDone: Arc::clone(&isDone),
         chunkerLabel: format!("{:?}", args.chunkerType),
         hasherLabel: format!("{:?}", args.hashType),
         numWorkers,
+        verifyStats: verifyStats.clone(),
+        compressionStats: compressionStats.clone(),
     });
 
     let tuiHandle = {
@@ -181,12 +436,24 @@ pub fn run(args: &TraceArgs) -> Result<()> {
     let workers = spawnWorkers(
         numWorkers,
         receiver,
-        Arc::clone(&hashSet),
+        Arc::clone(&index),
+        Arc::clone(&mmaps),
+        verifyStats.clone(),
+        compressionStats.clone(),
+        traceSender,
+        args.entropy,
+        args.superFeatures,
+        args.fileDigests,
+        args.sampleBits,
         Arc::clone(&fileStats),
         Arc::clone(&completedTasks),
         Arc::clone(&chunkCount),
         Arc::clone(&dupCount),
         Arc::clone(&dupSize),
+        Arc::clone(&uniqueSizes),
+        Arc::clone(&dupSizes),
+        Arc::clone(&patternStats),
+        sketch.clone(),
         Arc::clone(&hasherFactory),
         Arc::clone(&chunkFactory),
     );
@@ -196,9 +463,11 @@ pub fn run(args: &TraceArgs) -> Result<()> {
     }
     drop(sender);
 
+    let mut panicked = 0;
     for (i, worker) in workers.into_iter().enumerate() {
         if let Err(e) = worker.join() {
             eprintln!("Error joining worker thread {}: {:?}", i, e);
+            panicked += 1;
         }
     }
 
@@ -206,13 +475,28 @@ pub fn run(args: &TraceArgs) -> Result<()> {
     // This handles edge cases like empty files that produced no tasks and never
     // transitioned out of Queued.
     for mut entry in fileStats.iter_mut() {
-        *entry.value_mut() = FileStatus::Done;
+        entry.value_mut().status = FileStatus::Done;
     }
 
     // Signal TUI after all workers finish so the final draw reflects accurate state
     isDone.store(true, Ordering::Relaxed);
     tuiHandle.join().unwrap();
 
+    // The counts miss the work units of the panicked workers. Without a summary
+    // record the trace would look cut off, so it is removed altogether.
+    if panicked > 0 {
+        if let Some(handle) = traceHandle {
+            let _ = handle.join();
+            if let Some(ref path) = args.outputFile {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        return Err(Error::other(format!(
+            "{} worker threads panicked, the results are incomplete",
+            panicked
+        )));
+    }
+
     println!(
         "Found {} duplicate chunks out of {} total ({} saved).",
         dupCount.load(Ordering::Relaxed),
@@ -220,10 +504,148 @@ pub fn run(args: &TraceArgs) -> Result<()> {
         fmtSize(dupSize.load(Ordering::Relaxed)),
     );
 
+    // Only the sampled bytes are counted; scale their dedup rate to all data.
+    let sampledUniqueSize = uniqueSizes.lock().unwrap().sum;
+    let sampledSize = sampledUniqueSize + dupSizes.lock().unwrap().sum;
+    let uniqueSize = if sampledSize == 0 {
+        totalBytes
+    } else {
+        (totalBytes as f64 * sampledUniqueSize as f64 / sampledSize as f64) as usize
+    };
+    if args.sampleBits > 0 {
+        println!(
+            "Sampled one in {} chunks by fingerprint ({} of {}), an estimated {} saved.",
+            1u64 << args.sampleBits,
+            fmtSize(sampledSize as usize),
+            fmtSize(totalBytes),
+            fmtSize(totalBytes - uniqueSize),
+        );
+    }
+
+    if let Some(ref sketch) = sketch {
+        let sketch = sketch.lock().unwrap();
+        let uniqueChunks = sketch.estimate() * (1u64 << args.sampleBits) as f64;
+        println!(
+            "The sketch estimates {:.0} unique chunks ({}, ±{:.1}%).",
+            uniqueChunks,
+            fmtSize((uniqueChunks * uniqueSizes.lock().unwrap().mean()) as usize),
+            sketch.standardError() * 100.0,
+        );
+    }
+
+    let patterns = *patternStats.lock().unwrap();
+    let trivial = patterns.trivial();
+    if trivial.chunkCount > 0 {
+        println!(
+            "{} zero-filled and {} single-byte chunks ({}); without them {} duplicate chunks out of {} ({} saved).",
+            patterns.zero.chunkCount,
+            patterns.repeated.chunkCount,
+            fmtSize(trivial.size as usize),
+            dupCount.load(Ordering::Relaxed) - trivial.dupCount as usize,
+            chunkCount.load(Ordering::Relaxed) - trivial.chunkCount as usize,
+            fmtSize(dupSize.load(Ordering::Relaxed) - trivial.dupSize as usize),
+        );
+    }
+
+    if let Some(ref stats) = compressionStats {
+        println!(
+            "{} compresses the sampled unique chunks to {:.1}%, {} after dedup and compression.",
+            stats.compressionType,
+            stats.ratio() * 100.0,
+            fmtSize((uniqueSize as f64 * stats.ratio()) as usize),
+        );
+    }
+
+    if let Some(stats) = verifyStats {
+        printVerifyReport(&stats, &args.fileNames);
+    }
+
+    if let Some(handle) = traceHandle {
+        let mut writer = handle.join().unwrap()?;
+        for (fileIndex, path) in args.fileNames.iter().enumerate() {
+            let stats = fileStats.get(&fileIndex).unwrap();
+            writer.writeFile(&FileRecord {
+                fileIndex: fileIndex as u32,
+                path: path.as_os_str().as_encoded_bytes().to_vec(),
+                size: stats.size as u64,
+                chunkCount: stats.chunkCount as u64,
+                dupCount: stats.dupCount as u64,
+                dupSize: stats.dupSize as u64,
+                internalDupSize: stats.internalDupSize as u64,
+                fileType: stats.fileType.to_string(),
+                entropy: (args.entropy && stats.size > 0)
+                    .then(|| (stats.entropySum / stats.size as f64) as f32),
+                digest: stats.digest,
+            })?;
+        }
+        writer.finish(&TraceSummary {
+            fileCount: numFiles as u64,
+            totalSize: totalBytes as u64,
+            chunkCount: chunkCount.load(Ordering::Relaxed) as u64,
+            dupCount: dupCount.load(Ordering::Relaxed) as u64,
+            dupSize: dupSize.load(Ordering::Relaxed) as u64,
+            endTime: unixTime(),
+            uniqueSizes: uniqueSizes.lock().unwrap().clone(),
+            dupSizes: dupSizes.lock().unwrap().clone(),
+            patterns,
+            compressedCount: compressionStats
+                .as_ref()
+                .map_or(0, |s| s.sampledCount.load(Ordering::Relaxed) as u64),
+            compressInputSize: compressionStats
+                .as_ref()
+                .map_or(0, |s| s.sampledSize.load(Ordering::Relaxed) as u64),
+            compressOutputSize: compressionStats
+                .as_ref()
+                .map_or(0, |s| s.compressedSize.load(Ordering::Relaxed) as u64),
+            sketch: sketch.map(|s| s.lock().unwrap().clone()),
+        })?;
+    }
+
     Ok(())
 }
 
-fn fmtSize(bytes: usize) -> String {
+fn unixTime() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn printVerifyReport(stats: &VerifyStats, fileNames: &[PathBuf]) {
+    let verifiedCount = stats.verifiedCount.load(Ordering::Relaxed);
+    let verifiedBytes = stats.verifiedBytes.load(Ordering::Relaxed);
+    let verifyTime = Duration::from_nanos(stats.verifyNanos.load(Ordering::Relaxed));
+    let collisions = stats.collisions.lock().unwrap();
+
+    println!(
+        "Verified {} duplicate chunks: {} re-read and compared in {:.3}s.",
+        verifiedCount,
+        fmtSize(verifiedBytes),
+        verifyTime.as_secs_f64(),
+    );
+
+    if collisions.is_empty() {
+        println!("No fingerprint collisions found.");
+        return;
+    }
+
+    println!("Found {} fingerprint collisions:", collisions.len());
+    for c in collisions.iter() {
+        let digest: String = c.hash.iter().map(|b| format!("{:02x}", b)).collect();
+        println!(
+            "  {}: {}@{}+{} vs {}@{}+{}",
+            digest,
+            fileNames[c.first.fileIndex].display(),
+            c.first.offset,
+            c.first.length,
+            fileNames[c.second.fileIndex].display(),
+            c.second.offset,
+            c.second.length,
+        );
+    }
+}
+
+pub fn fmtSize(bytes: usize) -> String {
     const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
     const MIB: f64 = 1024.0 * 1024.0;
     const KIB: f64 = 1024.0;
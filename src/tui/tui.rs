@@ -1,15 +1,21 @@
 use std::{
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread,
     time::Duration,
 };
 
+use crate::trace::chunkIndex::VerifyStats;
+use crate::trace::compression::CompressionStats;
+use crate::trace::pattern::PatternStats;
+use crate::util::histogram::SizeHistogram;
+use crate::util::hyperLogLog::HyperLogLog;
+
 use dashmap::DashMap;
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
@@ -17,22 +23,53 @@ use ratatui::{
 };
 
 const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Rows taken by the chunk size panel: two histograms plus the block borders.
+const SIZE_PANEL_HEIGHT: u16 = 4;
 
 /// Below this terminal width the file list is omitted to avoid layout artifacts.
 const MIN_LIST_WIDTH: u16 = 30;
 
+/// Width of the per-file redundancy column, and the list width it needs to be shown.
+const FILE_STATS_WIDTH: usize = 15;
+const MIN_FILE_STATS_WIDTH: u16 = 50;
+
 /// Minimum terminal dimensions to render any TUI. Below this the TUI is
 /// suppressed entirely (chunking continues unaffected). The TUI is re-enabled
 /// automatically if the terminal is resized back above these thresholds.
 const MIN_TUI_WIDTH: u16 = 20;
 const MIN_TUI_HEIGHT: u16 = 4;
 
+#[derive(Default)]
 pub enum FileStatus {
+    #[default]
     Queued,
     Processing,
     Done,
 }
 
+/// Progress and redundancy of a single input file.
+#[derive(Default)]
+pub struct FileStats {
+    /// Path of the file as shown in the file list.
+    pub name: String,
+    pub status: FileStatus,
+    pub size: usize,
+    pub fileType: &'static str,
+    /// Work units of this file that have not been processed yet.
+    pub pendingTasks: usize,
+    pub chunkCount: usize,
+    pub dupCount: usize,
+    pub dupSize: usize,
+    /// Duplicate bytes whose first occurrence is in the same file.
+    pub internalDupSize: usize,
+    /// Sum of chunk entropy times chunk length, if entropy is measured.
+    pub entropySum: f64,
+    /// Digest of the whole file, taken when a trace is written.
+    pub digest: Option<[u8; 32]>,
+}
+
 pub struct TraceUiState {
     pub totalTasks: usize,
     pub totalFiles: usize,
@@ -40,11 +77,22 @@ pub struct TraceUiState {
     pub chunkCount: Arc<AtomicUsize>,
     pub dupCount: Arc<AtomicUsize>,
     pub dupSize: Arc<AtomicUsize>,
-    pub fileStats: Arc<DashMap<String, FileStatus>>,
+    pub uniqueSizes: Arc<Mutex<SizeHistogram>>,
+    pub dupSizes: Arc<Mutex<SizeHistogram>>,
+    /// Zero-filled and single-byte chunks, also included in the counters above.
+    pub patternStats: Arc<Mutex<PatternStats>>,
+    /// Estimate of the unique chunks, scaled by 2^`sampleBits`.
+    pub sketch: Option<Arc<Mutex<HyperLogLog>>>,
+    pub sampleBits: u8,
+    /// Configured (min, max) chunk size, to highlight chunks outside of it.
+    pub chunkerBounds: (usize, usize),
+    pub fileStats: Arc<DashMap<usize, FileStats>>,
     pub isDone: Arc<AtomicBool>,
     pub chunkerLabel: String,
     pub hasherLabel: String,
     pub numWorkers: usize,
+    pub verifyStats: Option<Arc<VerifyStats>>,
+    pub compressionStats: Option<Arc<CompressionStats>>,
 }
 
 fn terminalBigEnough() -> bool {
@@ -57,10 +105,18 @@ fn tryInit(numFiles: usize) -> Option<DefaultTerminal> {
     if !terminalBigEnough() {
         return None;
     }
-    let height = (numFiles as u16 + 5).min(20);
-    Some(ratatui::init_with_options(TerminalOptions {
+    let height = (numFiles as u16 + 5 + SIZE_PANEL_HEIGHT).min(20 + SIZE_PANEL_HEIGHT);
+    // Fall back to running without a TUI if the terminal cannot be set up,
+    // e.g. when stdout is not an interactive terminal.
+    match ratatui::try_init_with_options(TerminalOptions {
         viewport: Viewport::Inline(height),
-    }))
+    }) {
+        Ok(terminal) => Some(terminal),
+        Err(_) => {
+            let _ = crossterm::terminal::disable_raw_mode();
+            None
+        }
+    }
 }
 
 pub fn run(state: Arc<TraceUiState>) {
@@ -123,31 +179,84 @@ fn fmtSize(bytes: usize) -> String {
     }
 }
 
+/// One row of the chunk size panel: a log-scale bar per power of two between
+/// `lowest` and `highest`, followed by summary statistics.
+fn sizeLine(
+    label: &'static str,
+    h: &SizeHistogram,
+    lowest: u64,
+    highest: u64,
+    bounds: (usize, usize),
+) -> Line<'static> {
+    let mut spans = vec![Span::raw(" "), Span::raw(label), Span::raw("  ")];
+    if h.isEmpty() {
+        spans.push(Span::styled("–", Style::default().fg(Color::DarkGray)));
+        return Line::from(spans);
+    }
+
+    let buckets = h.log2Buckets();
+    let peak = buckets.iter().map(|b| b.1).max().unwrap_or(1).max(1);
+    let mut bars = String::new();
+    let mut power = lowest.max(1);
+    while power <= highest.max(1) {
+        let count = buckets.iter().find(|b| b.0 == power).map_or(0, |b| b.1);
+        bars.push(if count == 0 {
+            ' '
+        } else {
+            BARS[((count * (BARS.len() as u64 - 1)) / peak) as usize]
+        });
+        power <<= 1;
+    }
+    spans.push(Span::styled(bars, Style::default().fg(Color::Cyan)));
+
+    // Only the last chunk of a work unit may fall below the lower bound, so only
+    // exceeding the upper bound is flagged.
+    let maxStyle = if h.max as usize > bounds.1 {
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    spans.extend([
+        Span::styled("  min ", Style::default().fg(Color::DarkGray)),
+        Span::raw(fmtSize(h.min as usize)),
+        Span::styled("  mean ", Style::default().fg(Color::DarkGray)),
+        Span::raw(fmtSize(h.mean() as usize)),
+        Span::styled("  p50 ", Style::default().fg(Color::DarkGray)),
+        Span::raw(fmtSize(h.median() as usize)),
+        Span::styled("  p99 ", Style::default().fg(Color::DarkGray)),
+        Span::raw(fmtSize(h.percentile(99.0) as usize)),
+        Span::styled("  max ", Style::default().fg(Color::DarkGray)),
+        Span::styled(fmtSize(h.max as usize), maxStyle),
+    ]);
+    Line::from(spans)
+}
+
 fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
     let area = frame.area();
     let showFileList = area.width >= MIN_LIST_WIDTH;
 
     // Build a layout with or without the file list depending on terminal width.
-    // Returns (headerArea, fileListArea, statsArea, gaugeArea).
-    let (headerArea, fileListArea, statsArea, gaugeArea): (Rect, Option<Rect>, Rect, Rect) =
-        if showFileList {
-            let l = Layout::vertical([
-                Constraint::Length(1), // header
-                Constraint::Min(1),    // file list
-                Constraint::Length(1), // stats
-                Constraint::Length(1), // gauge
-            ])
-            .split(area);
-            (l[0], Some(l[1]), l[2], l[3])
-        } else {
-            let l = Layout::vertical([
-                Constraint::Length(1), // header
-                Constraint::Length(1), // stats
-                Constraint::Length(1), // gauge
-            ])
-            .split(area);
-            (l[0], None, l[1], l[2])
-        };
+    // The chunk size panel shares the wide layout with the file list.
+    // Returns (headerArea, fileListArea, sizesArea, statsArea, gaugeArea).
+    let (headerArea, fileListArea, sizesArea, statsArea, gaugeArea) = if showFileList {
+        let l = Layout::vertical([
+            Constraint::Length(1),                 // header
+            Constraint::Min(1),                    // file list
+            Constraint::Length(SIZE_PANEL_HEIGHT), // chunk sizes
+            Constraint::Length(1),                 // stats
+            Constraint::Length(1),                 // gauge
+        ])
+        .split(area);
+        (l[0], Some(l[1]), Some(l[2]), l[3], l[4])
+    } else {
+        let l = Layout::vertical([
+            Constraint::Length(1), // header
+            Constraint::Length(1), // stats
+            Constraint::Length(1), // gauge
+        ])
+        .split(area);
+        (l[0], None, None, l[1], l[2])
+    };
 
     // ── Header ───────────────────────────────────────────────────
     frame.render_widget(
@@ -173,21 +282,26 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
 
     // ── File List (auto-scrolling) ────────────────────────────────
     if let Some(listArea) = fileListArea {
+        // Only show per-file redundancy if there is room left for the path.
+        let showFileStats = listArea.width >= MIN_FILE_STATS_WIDTH;
+        let statsWidth = if showFileStats { FILE_STATS_WIDTH } else { 0 };
         // Inner width: subtract 2 borders + 1 left pad + 1 icon + 2 spacing
-        let innerWidth = listArea.width.saturating_sub(6) as usize;
+        let innerWidth = (listArea.width.saturating_sub(6) as usize).saturating_sub(statsWidth);
         // Rows available inside the block borders
         let maxVisible = listArea.height.saturating_sub(2) as usize;
 
-        let mut entries: Vec<(String, u8)> = state
+        // (path, status code, duplicate bytes, file size)
+        let mut entries: Vec<(String, u8, usize, usize)> = state
             .fileStats
             .iter()
             .map(|e| {
-                let code = match *e.value() {
+                let stats = e.value();
+                let code = match stats.status {
                     FileStatus::Done => 2,
                     FileStatus::Processing => 1,
                     FileStatus::Queued => 0,
                 };
-                (e.key().clone(), code)
+                (stats.name.clone(), code, stats.dupSize, stats.size)
             })
             .collect();
         // Done → Processing → Queued; alphabetical within each group
@@ -195,7 +309,7 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
 
         // Auto-scroll: anchor view so the first active file is visible,
         // with up to 2 completed files shown above it as context.
-        let firstActive = entries.iter().position(|(_, c)| *c < 2).unwrap_or(0);
+        let firstActive = entries.iter().position(|e| e.1 < 2).unwrap_or(0);
         let scrollStart = firstActive.saturating_sub(2);
 
         // Reserve rows for overflow indicators as needed, then compute the window.
@@ -218,7 +332,7 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
             ))));
         }
 
-        for (path, code) in &entries[scrollStart..endIdx] {
+        for (path, code, dupSize, size) in &entries[scrollStart..endIdx] {
             let (icon, iconStyle, nameStyle) = match code {
                 2 => (
                     "✓",
@@ -247,12 +361,27 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
                 path.clone()
             };
 
-            items.push(ListItem::new(Line::from(vec![
+            let mut spans = vec![
                 Span::raw(" "),
                 Span::styled(icon, iconStyle),
                 Span::raw("  "),
-                Span::styled(display, nameStyle),
-            ])));
+                Span::styled(format!("{:<innerWidth$}", display), nameStyle),
+            ];
+
+            // Duplicate bytes found in this file so far and their share of its size.
+            if showFileStats && *code > 0 {
+                let dupPct = (dupSize * 100).checked_div(*size).unwrap_or(0);
+                spans.push(Span::styled(
+                    format!("{:>9} {:>4}%", fmtSize(*dupSize), dupPct),
+                    Style::default().fg(if *dupSize > 0 {
+                        Color::Red
+                    } else {
+                        Color::DarkGray
+                    }),
+                ));
+            }
+
+            items.push(ListItem::new(Line::from(spans)));
         }
 
         if belowCount > 0 {
@@ -273,36 +402,108 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
         frame.render_widget(List::new(items).block(block), listArea);
     }
 
+    // ── Chunk Sizes ────────────────────────────────────────────────
+    if let Some(sizesArea) = sizesArea {
+        let unique = state.uniqueSizes.lock().unwrap().clone();
+        let dup = state.dupSizes.lock().unwrap().clone();
+
+        // Both rows share one range of powers of two, so their bars line up.
+        let powers: Vec<u64> = unique
+            .log2Buckets()
+            .into_iter()
+            .chain(dup.log2Buckets())
+            .map(|(power, _)| power)
+            .collect();
+        let lowest = powers.iter().copied().min().unwrap_or(0);
+        let highest = powers.iter().copied().max().unwrap_or(0);
+
+        let rows: Vec<ListItem> = [("unique", &unique), ("dups  ", &dup)]
+            .into_iter()
+            .map(|(label, h)| ListItem::new(sizeLine(label, h, lowest, highest, state.chunkerBounds)))
+            .collect();
+
+        let block = Block::default()
+            .title(Span::styled(
+                " Chunk sizes ",
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray));
+
+        frame.render_widget(List::new(rows).block(block), sizesArea);
+    }
+
     // ── Stats ──────────────────────────────────────────────────────
     {
         let chunkCount = state.chunkCount.load(Ordering::Relaxed);
         let dupCount = state.dupCount.load(Ordering::Relaxed);
         let dupSize = state.dupSize.load(Ordering::Relaxed);
-        let dupPct = if chunkCount > 0 {
-            dupCount * 100 / chunkCount
-        } else {
-            0
-        };
+        let dupPct = (dupCount * 100).checked_div(chunkCount).unwrap_or(0);
 
-        frame.render_widget(
-            Paragraph::new(Line::from(vec![
-                Span::raw("  chunks "),
-                Span::styled(
-                    chunkCount.to_string(),
-                    Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
-                ),
-                Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
-                Span::raw("duplicates "),
-                Span::styled(
-                    format!("{} ({}%)", dupCount, dupPct),
-                    Style::default().fg(Color::Red),
-                ),
-                Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
-                Span::raw("saved "),
-                Span::styled(fmtSize(dupSize), Style::default().fg(Color::Green)),
-            ])),
-            statsArea,
-        );
+        let mut spans = vec![
+            Span::raw("  chunks "),
+            Span::styled(
+                chunkCount.to_string(),
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
+            Span::raw("duplicates "),
+            Span::styled(
+                format!("{} ({}%)", dupCount, dupPct),
+                Style::default().fg(Color::Red),
+            ),
+            Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
+            Span::raw("saved "),
+            Span::styled(fmtSize(dupSize), Style::default().fg(Color::Green)),
+        ];
+
+        let patterns = *state.patternStats.lock().unwrap();
+        for (label, counts) in [("zero ", patterns.zero), ("single-byte ", patterns.repeated)] {
+            if counts.chunkCount == 0 {
+                continue;
+            }
+            spans.push(Span::styled("  │  ", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::raw(label));
+            spans.push(Span::styled(
+                format!("{} ({})", counts.chunkCount, fmtSize(counts.size as usize)),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+
+        if let Some(ref sketch) = state.sketch {
+            let estimate = sketch.lock().unwrap().estimate() * (1u64 << state.sampleBits) as f64;
+            spans.push(Span::styled("  │  ", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::raw("unique ~"));
+            spans.push(Span::styled(
+                format!("{:.0}", estimate),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+
+        if let Some(ref compression) = state.compressionStats {
+            spans.push(Span::styled("  │  ", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::raw(format!("{} ", compression.compressionType)));
+            spans.push(Span::styled(
+                format!("{:.1}%", compression.ratio() * 100.0),
+                Style::default().fg(Color::Green),
+            ));
+        }
+
+        if let Some(ref verify) = state.verifyStats {
+            let collisions = verify.collisionCount.load(Ordering::Relaxed);
+            spans.push(Span::styled("  │  ", Style::default().fg(Color::DarkGray)));
+            spans.push(Span::raw("collisions "));
+            spans.push(Span::styled(
+                collisions.to_string(),
+                if collisions > 0 {
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::Green)
+                },
+            ));
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), statsArea);
     }
 
     // ── Progress Gauge ─────────────────────────────────────────────
@@ -317,7 +518,7 @@ fn draw(frame: &mut Frame, state: &TraceUiState, tick: usize) {
         let doneFiles = state
             .fileStats
             .iter()
-            .filter(|e| matches!(*e.value(), FileStatus::Done))
+            .filter(|e| matches!(e.value().status, FileStatus::Done))
             .count();
 
         frame.render_widget(
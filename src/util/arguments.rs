@@ -1,6 +1,11 @@
 use crate::chunker::chunker::ChunkerType;
+use crate::parse::bloomFilter::BloomConfig;
+use crate::parse::containers::RewriteStrategy;
+use crate::parse::retention::RetentionPolicy;
+use crate::trace::compression::CompressionType;
+use crate::trace::sampling::MAX_SAMPLE_BITS;
 use clap::{Parser, Subcommand, ValueEnum};
-use std::{path::PathBuf, usize};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(
@@ -25,6 +30,31 @@ pub enum Commands {
         long_about = "The parse mode processes the trace file generated by the trace command. It reads the chunk-level metadata and produces a report on file system redundancy, chunk frequency, and potential compression opportunities. This mode can also be used to visualize temporal patterns, detect duplicates, and help assess the effectiveness of the chunking strategy."
     )]
     Parse(ParseArgs),
+    #[command(
+        about = "Compare two traces of the same file system",
+        long_about = "The diff mode compares an older and a newer trace. It reports the bytes of unchanged, renamed, moved, modified, added and deleted files and, for traces written with --log-fingerprints, the new unique chunks, the chunks no longer referenced and the net change of the unique data footprint."
+    )]
+    Diff(DiffArgs),
+    #[command(
+        about = "Combine several traces into one",
+        long_about = "The merge mode combines the file and chunk records of traces written in parallel, e.g. of different shares or hosts, into one trace. The traces must use the same chunker, hash function, salt and sampling. With chunk records, duplicates across the traces are counted in the merged trace."
+    )]
+    Merge(MergeArgs),
+    #[command(
+        about = "Show the header and contents of a trace",
+        long_about = "The inspect mode prints the header of a trace: the format and tool version, chunker, hash function, salt verifier, sampling, compression and sketch, the time range of the trace and the number of file and chunk records next to the totals of the summary."
+    )]
+    Inspect(InspectArgs),
+    #[command(
+        about = "Check traces for corruption and truncation",
+        long_about = "The verify mode reads every record of the given traces and checks their checksums, that they decode, that the header comes first and the summary last, and that the file records agree with the summary. A trace without a summary record was cut off before the tracer finished. Exits with an error if any trace fails."
+    )]
+    Verify(VerifyArgs),
+    #[command(
+        about = "Convert a trace to JSON Lines or CSV tables, or JSON Lines back to a trace",
+        long_about = "The convert mode writes the header, file and chunk records of a trace as three tables, trace, files and chunks, to a directory, as JSON Lines or CSV for tools like pandas or DuckDB. Digests are hex, paths are UTF-8 with other bytes escaped as \\xNN. With --to trace, it reads such a directory of JSON Lines tables and writes a trace that parse accepts."
+    )]
+    Convert(ConvertArgs),
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
@@ -35,6 +65,38 @@ pub enum HashType {
     MD5,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum DirectorySort {
+    LOGICAL,
+    UNIQUE,
+    RATIO,
+    SHARED,
+    NAME,
+}
+
+/// Replacement policy of a simulated fingerprint cache.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum CachePolicy {
+    LRU,
+    ARC,
+    LFU,
+}
+
+/// Output format of reports that can be read by other tools.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    TEXT,
+    JSON,
+}
+
+/// Formats the convert mode writes.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ConvertFormat {
+    JSONL,
+    CSV,
+    TRACE,
+}
+
 #[derive(Parser, Debug)]
 pub struct TraceArgs {
     #[arg(
@@ -81,11 +143,17 @@ pub struct TraceArgs {
     )]
     pub logFingerprints: bool,
 
+    #[arg(
+        long = "file-digests",
+        help = "Include a digest of every whole file in the log, for finding identical files"
+    )]
+    pub fileDigests: bool,
+
     #[arg(
         short = 'o',
         long = "output",
         name = "FILE",
-        help = "Redirect output to a file"
+        help = "Write the trace to a file"
     )]
     pub outputFile: Option<PathBuf>,
 
@@ -127,6 +195,60 @@ pub struct TraceArgs {
     )]
     pub hashSalt: Option<Box<str>>,
 
+    #[arg(
+        long = "verify-duplicates",
+        help = "Byte-compare duplicate chunks with their first occurrence to detect fingerprint collisions"
+    )]
+    pub verifyDuplicates: bool,
+
+    #[arg(
+        long = "compress",
+        name = "COMPRESSION",
+        help = "Compress unique chunks to estimate dedup plus compression (none, lz4, zstd[:level])",
+        default_value = "none"
+    )]
+    pub compression: CompressionType,
+
+    #[arg(
+        long = "compress-sample",
+        name = "N",
+        help = "Only compress one in <N> unique chunks, chosen by fingerprint",
+        default_value_t = 1
+    )]
+    pub compressSampleRate: u64,
+
+    #[arg(
+        long = "entropy",
+        help = "Estimate the entropy of every chunk to flag encrypted or compressed files"
+    )]
+    pub entropy: bool,
+
+    #[arg(
+        long = "super-features",
+        help = "Store resemblance features of unique chunks to estimate delta compression (needs --log-fingerprints)"
+    )]
+    pub superFeatures: bool,
+
+    #[arg(
+        long = "sample-bits",
+        name = "BITS",
+        help = "Only keep chunks whose fingerprint starts with <BITS> zero bits (one in 2^BITS)",
+        default_value_t = 0
+    )]
+    pub sampleBits: u8,
+
+    #[arg(
+        long = "sketch",
+        help = "Also estimate the unique chunks with a HyperLogLog sketch"
+    )]
+    pub sketch: bool,
+
+    #[arg(
+        long = "sketch-only",
+        help = "Estimate unique chunks with a sketch, without an exact index unless --compress or --super-features needs one"
+    )]
+    pub sketchOnly: bool,
+
     #[arg(
         short = 's',
         long = "silent",
@@ -151,10 +273,32 @@ impl TraceArgs {
         }
         self.jobs
             .get_or_insert(std::thread::available_parallelism().unwrap().get());
-        if let Some(ref file) = self.progressFile {
-            if !file.exists() {
-                return Err(format!("Progress File {:?} does not exist", file));
-            }
+        if let Some(ref file) = self.progressFile
+            && !file.exists()
+        {
+            return Err(format!("Progress File {:?} does not exist", file));
+        }
+        if self.sampleBits > MAX_SAMPLE_BITS {
+            return Err(format!(
+                "--sample-bits must be at most {}",
+                MAX_SAMPLE_BITS
+            ));
+        }
+        if self.sketchOnly && self.verifyDuplicates {
+            return Err("--verify-duplicates needs the exact index, not --sketch-only".to_string());
+        }
+        self.sketch |= self.sketchOnly;
+        if self.compressSampleRate == 0 {
+            return Err("--compress-sample must be at least 1".to_string());
+        }
+        if self.superFeatures && !self.logFingerprints {
+            return Err("--super-features requires --log-fingerprints".to_string());
+        }
+        if self.fileDigests && self.outputFile.is_none() {
+            return Err("--file-digests requires an output file (-o)".to_string());
+        }
+        if self.logFingerprints && self.outputFile.is_none() {
+            return Err("--log-fingerprints requires an output file (-o)".to_string());
         }
         let files = &self.fileNames;
         for file in files {
@@ -175,7 +319,7 @@ pub struct ParseArgs {
         name = "FILE",
         help = "Redirect output to a file"
     )]
-    output: Option<PathBuf>,
+    pub output: Option<PathBuf>,
 
     #[arg(
         short = 'I',
@@ -184,16 +328,400 @@ pub struct ParseArgs {
         help = "Print a status report every <INTERVAL> seconds",
         default_value = "60"
     )]
-    reportInterval: Option<u32>,
+    pub reportInterval: Option<u32>,
+
+    #[arg(
+        short = 't',
+        long = "top",
+        name = "N",
+        help = "Number of entries to show in ranked reports",
+        default_value_t = 10
+    )]
+    pub top: usize,
+
+    #[arg(
+        long = "directories",
+        help = "Roll redundancy up the directory tree (needs chunk records)"
+    )]
+    pub directories: bool,
+
+    #[arg(
+        long = "depth",
+        help = "How many directory levels to show below the common root",
+        default_value_t = 3
+    )]
+    pub depth: usize,
+
+    #[arg(
+        long = "sort",
+        help = "Order of sibling directories",
+        value_enum,
+        default_value_t = DirectorySort::LOGICAL
+    )]
+    pub sort: DirectorySort,
+
+    #[arg(long = "types", help = "Break redundancy down by file type")]
+    pub types: bool,
+
+    #[arg(
+        long = "chunk-sizes",
+        help = "Show the distribution of unique and duplicate chunk sizes"
+    )]
+    pub chunkSizes: bool,
+
+    #[arg(
+        long = "popularity",
+        help = "Show how often chunks are referenced (needs chunk records)"
+    )]
+    pub popularity: bool,
+
+    #[arg(
+        long = "entropy",
+        help = "Group files by entropy (needs a trace written with --entropy)"
+    )]
+    pub entropy: bool,
+
+    #[arg(
+        long = "resemblance",
+        help = "Estimate delta compression of similar unique chunks (needs a trace written with --super-features)"
+    )]
+    pub resemblance: bool,
+
+    #[arg(
+        long = "duplicate-files",
+        name = "FORMAT",
+        help = "List groups of identical files by wasted bytes, as text or with =json instead of all other reports",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text"
+    )]
+    pub duplicateFiles: Option<ReportFormat>,
+
+    #[arg(
+        long = "similar-files",
+        help = "Cluster near-duplicate files by the MinHash of their chunks (needs chunk records)"
+    )]
+    pub similarFiles: bool,
+
+    #[arg(
+        long = "similarity",
+        name = "THRESHOLD",
+        help = "Jaccard similarity at which --similar-files links two files",
+        default_value_t = 0.8
+    )]
+    pub similarity: f64,
+
+    #[arg(
+        long = "churn",
+        help = "Compare the files of each trace with the trace before: renamed, moved, modified, added and deleted"
+    )]
+    pub churn: bool,
+
+    #[arg(
+        long = "union",
+        help = "Estimate the unique data of all traces together from their sketches"
+    )]
+    pub union: bool,
+
+    #[arg(
+        long = "overlap",
+        help = "Compare the unique chunks of all traces with each other (needs chunk records)"
+    )]
+    pub overlap: bool,
+
+    #[arg(
+        long = "overlap-csv",
+        name = "CSV",
+        help = "Also write the overlap of all traces to a CSV file"
+    )]
+    pub overlapCsv: Option<PathBuf>,
+
+    #[arg(
+        long = "simulate-cache",
+        name = "POLICY",
+        help = "Replay the chunks through an in-memory fingerprint cache (needs chunk records)",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub simulateCache: Vec<CachePolicy>,
+
+    #[arg(
+        long = "cache-size",
+        name = "ENTRIES",
+        help = "Number of fingerprints the simulated cache holds",
+        default_value_t = 1 << 20
+    )]
+    pub cacheSize: usize,
+
+    #[arg(
+        long = "simulate-containers",
+        help = "Pack unique chunks into containers and measure restore fragmentation (needs chunk records)"
+    )]
+    pub simulateContainers: bool,
+
+    #[arg(
+        long = "container-size",
+        name = "SIZE",
+        help = "Size of a simulated container, e.g. 4M",
+        value_parser = parseSize,
+        default_value = "4M"
+    )]
+    pub containerSize: u64,
+
+    #[arg(
+        long = "rewrite",
+        name = "STRATEGY",
+        help = "Rewriting strategies to compare (none, capping[:containers], threshold[:percent])",
+        value_delimiter = ','
+    )]
+    pub rewrite: Vec<RewriteStrategy>,
+
+    #[arg(
+        long = "sparse-index",
+        name = "BITS",
+        help = "Compare sparse indexes that keep one in 2^<BITS> fingerprints as hooks with exact dedup (needs chunk records)",
+        value_delimiter = ','
+    )]
+    pub sparseIndex: Vec<u8>,
+
+    #[arg(
+        long = "segment-size",
+        name = "SEGMENT",
+        help = "Logical size of a segment in the sparse index simulation, e.g. 8M",
+        value_parser = parseSize,
+        default_value = "8M"
+    )]
+    pub segmentSize: u64,
+
+    #[arg(
+        long = "champions",
+        name = "CHAMPIONS",
+        help = "Number of earlier segments a segment dedups against",
+        default_value_t = 4
+    )]
+    pub champions: usize,
+
+    #[arg(
+        long = "simulate-bloom",
+        name = "FILTER",
+        help = "Put a Bloom filter in front of the index, e.g. bits-per-key=10 or size=1M,hashes=4 (needs chunk records, repeatable)"
+    )]
+    pub simulateBloom: Vec<BloomConfig>,
+
+    #[arg(
+        long = "retention",
+        name = "RETENTION",
+        help = "Expire the traces as backup generations, e.g. 7d,4w,12m, and track the store size (needs chunk records)"
+    )]
+    pub retention: Option<RetentionPolicy>,
+
+    #[arg(
+        long = "daily",
+        help = "Take the traces as consecutive daily generations instead of using their start times"
+    )]
+    pub daily: bool,
 
     #[arg(required = true, help = "Trace files to process")]
-    fileNames: Vec<PathBuf>,
+    pub fileNames: Vec<PathBuf>,
 }
 
 impl ParseArgs {
     pub fn validate(&mut self) -> Result<(), String> {
         self.reportInterval.get_or_insert(60);
+        self.overlap |= self.overlapCsv.is_some();
+        self.simulateContainers |= !self.rewrite.is_empty();
+        if self.rewrite.is_empty() {
+            self.rewrite.push(RewriteStrategy::NONE);
+        }
+        if self.containerSize == 0 {
+            return Err("--container-size must be at least 1".to_string());
+        }
+        if self.sparseIndex.iter().any(|&bits| bits > MAX_SAMPLE_BITS) {
+            return Err(format!(
+                "--sparse-index must be at most {} bits",
+                MAX_SAMPLE_BITS
+            ));
+        }
+        if self.segmentSize == 0 || self.champions == 0 {
+            return Err("--segment-size and --champions must be at least 1".to_string());
+        }
+        if self.cacheSize == 0 {
+            return Err("--cache-size must be at least 1".to_string());
+        }
+        if !(self.similarity > 0.0 && self.similarity <= 1.0) {
+            return Err("--similarity must be above 0 and at most 1".to_string());
+        }
+
+        for file in &self.fileNames {
+            if !file.exists() {
+                return Err(format!("Trace file {:?} does not exist", file));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    #[arg(
+        short = 'o',
+        long = "output",
+        name = "FILE",
+        help = "Redirect output to a file"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        long = "format",
+        name = "FORMAT",
+        help = "Print the report as text or JSON",
+        value_enum,
+        default_value = "text"
+    )]
+    pub format: ReportFormat,
+
+    #[arg(help = "Trace of the older generation")]
+    pub oldTrace: PathBuf,
+
+    #[arg(help = "Trace of the newer generation")]
+    pub newTrace: PathBuf,
+}
+
+impl DiffArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        for file in [&self.oldTrace, &self.newTrace] {
+            if !file.exists() {
+                return Err(format!("Trace file {:?} does not exist", file));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct MergeArgs {
+    #[arg(
+        short = 'o',
+        long = "output",
+        name = "FILE",
+        help = "Write the merged trace to this file"
+    )]
+    pub output: PathBuf,
+
+    #[arg(
+        short = 'p',
+        long = "prefix-paths",
+        help = "Prefix the paths of every trace with its file name, as in <name>:<path>"
+    )]
+    pub prefixPaths: bool,
+
+    #[arg(required = true, help = "Trace files to merge")]
+    pub fileNames: Vec<PathBuf>,
+}
 
+impl MergeArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        for file in &self.fileNames {
+            if !file.exists() {
+                return Err(format!("Trace file {:?} does not exist", file));
+            }
+            if *file == self.output {
+                return Err(format!("Trace file {:?} is also the output", file));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct InspectArgs {
+    #[arg(help = "Trace file to inspect")]
+    pub traceFile: PathBuf,
+}
+
+impl InspectArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.traceFile.exists() {
+            return Err(format!("Trace file {:?} does not exist", self.traceFile));
+        }
         Ok(())
     }
 }
+
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    #[arg(required = true, help = "Trace files to verify")]
+    pub fileNames: Vec<PathBuf>,
+}
+
+impl VerifyArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        for file in &self.fileNames {
+            if !file.exists() {
+                return Err(format!("Trace file {:?} does not exist", file));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ConvertArgs {
+    #[arg(
+        long = "to",
+        name = "FORMAT",
+        help = "Convert a trace to jsonl or csv tables, or jsonl tables to a trace",
+        value_enum
+    )]
+    pub to: ConvertFormat,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        name = "FILE",
+        help = "Directory for the tables, or the trace file with --to trace"
+    )]
+    pub output: PathBuf,
+
+    #[arg(help = "Trace file, or directory of jsonl tables with --to trace")]
+    pub input: PathBuf,
+}
+
+impl ConvertArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.input.exists() {
+            return Err(format!("Input {:?} does not exist", self.input));
+        }
+        match self.to {
+            ConvertFormat::TRACE if !self.input.is_dir() => Err(format!(
+                "--to trace reads a directory of jsonl tables, {:?} is not one",
+                self.input
+            )),
+            ConvertFormat::JSONL | ConvertFormat::CSV if self.output.is_file() => Err(format!(
+                "The tables are written to a directory, {:?} is a file",
+                self.output
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse a size in bytes with an optional binary suffix: K, M or G.
+pub fn parseSize(s: &str) -> Result<u64, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (number, shift) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 10),
+        Some('M') => (&digits[..digits.len() - 1], 20),
+        Some('G') => (&digits[..digits.len() - 1], 30),
+        _ => (digits, 0),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("Invalid size {:?}, expected e.g. 4096, 512K or 4M", s))
+}
@@ -0,0 +1,8 @@
+/// Quote a CSV field if it contains a separator, quote or line break.
+pub fn csvField(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,235 @@
+/// Each power of two is split into this many linear sub-buckets, which bounds the
+/// relative error of percentiles to 1 / SUB_BUCKETS.
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const NUM_BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+fn bucketIndex(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let sub = (value >> (exp - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+/// Smallest value that falls into bucket `index`.
+fn bucketLowerBound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let exp = (index / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+    let sub = (index % SUB_BUCKETS) as u64;
+    (1u64 << exp) + (sub << (exp - SUB_BITS))
+}
+
+/// Log-scale histogram of chunk lengths with exact count, sum, min and max.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizeHistogram {
+    counts: Vec<u64>,
+    pub count: u64,
+    pub sum: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Default for SizeHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; NUM_BUCKETS],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+}
+
+impl SizeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucketIndex(value)] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &SizeHistogram) {
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Approximate value below which `p` percent of the recorded values fall.
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((p / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        if rank >= self.count {
+            return self.max;
+        }
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                // Report the middle of the bucket, but never leave the observed range.
+                let lower = bucketLowerBound(index);
+                let upper = if index + 1 < NUM_BUCKETS {
+                    bucketLowerBound(index + 1) - 1
+                } else {
+                    u64::MAX
+                };
+                return (lower + (upper - lower) / 2).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn median(&self) -> u64 {
+        self.percentile(50.0)
+    }
+
+    /// Counts per power of two as `(lower bound, count)`, from the smallest to the
+    /// largest non-empty range.
+    pub fn log2Buckets(&self) -> Vec<(u64, u64)> {
+        let mut buckets: Vec<(u64, u64)> = Vec::new();
+        for (index, &count) in self.counts.iter().enumerate() {
+            let lower = bucketLowerBound(index);
+            let power = if lower == 0 {
+                0
+            } else {
+                1u64 << (63 - lower.leading_zeros())
+            };
+            match buckets.last_mut() {
+                Some(last) if last.0 == power => last.1 += count,
+                _ => buckets.push((power, count)),
+            }
+        }
+        let first = buckets.iter().position(|b| b.1 > 0).unwrap_or(0);
+        let last = buckets.iter().rposition(|b| b.1 > 0).map_or(0, |i| i + 1);
+        buckets[first..last].to_vec()
+    }
+
+    /// Non-empty buckets as `(index, count)`, used to store the histogram.
+    pub fn sparseCounts(&self) -> Vec<(u16, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .map(|(i, c)| (i as u16, *c))
+            .collect()
+    }
+
+    /// Rebuild a histogram from stored parts. Returns `None` if a bucket index
+    /// is out of range.
+    pub fn fromParts(sum: u64, min: u64, max: u64, sparse: &[(u16, u64)]) -> Option<Self> {
+        let mut h = Self::new();
+        for &(index, count) in sparse {
+            *h.counts.get_mut(index as usize)? += count;
+            h.count += count;
+        }
+        h.sum = sum;
+        if h.count > 0 {
+            h.min = min;
+            h.max = max;
+        }
+        Some(h)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testBucketBounds() {
+        for value in (0..100_000u64).chain([u64::MAX / 2, u64::MAX]) {
+            let index = bucketIndex(value);
+            assert!(bucketLowerBound(index) <= value, "value {value}");
+            if index + 1 < NUM_BUCKETS {
+                assert!(bucketLowerBound(index + 1) > value, "value {value}");
+            }
+        }
+    }
+
+    #[test]
+    fn testStatistics() {
+        let mut h = SizeHistogram::new();
+        for v in 1..=1000 {
+            h.record(v);
+        }
+        assert_eq!(h.count, 1000);
+        assert_eq!(h.min, 1);
+        assert_eq!(h.max, 1000);
+        assert_eq!(h.mean(), 500.5);
+
+        // Percentiles are exact to within one sub-bucket.
+        for (p, exact) in [(10.0, 100.0), (50.0, 500.0), (90.0, 900.0), (99.0, 990.0)] {
+            let estimate = h.percentile(p) as f64;
+            assert!(
+                (estimate - exact).abs() / exact <= 1.0 / SUB_BUCKETS as f64,
+                "p{p}: {estimate} vs {exact}"
+            );
+        }
+        assert_eq!(h.percentile(100.0), 1000);
+    }
+
+    #[test]
+    fn testEmpty() {
+        let h = SizeHistogram::new();
+        assert!(h.isEmpty());
+        assert_eq!(h.median(), 0);
+        assert_eq!(h.mean(), 0.0);
+        assert!(h.log2Buckets().is_empty());
+    }
+
+    #[test]
+    fn testMergeAndLog2Buckets() {
+        let mut a = SizeHistogram::new();
+        let mut b = SizeHistogram::new();
+        a.record(1000);
+        a.record(1500);
+        b.record(4096);
+        a.merge(&b);
+
+        assert_eq!(a.count, 3);
+        assert_eq!(a.min, 1000);
+        assert_eq!(a.max, 4096);
+        assert_eq!(
+            a.log2Buckets(),
+            vec![(512, 1), (1024, 1), (2048, 0), (4096, 1)]
+        );
+    }
+
+    #[test]
+    fn testPartsRoundTrip() {
+        let mut h = SizeHistogram::new();
+        for v in [3, 700, 8192, 8192, 65536] {
+            h.record(v);
+        }
+        let restored = SizeHistogram::fromParts(h.sum, h.min, h.max, &h.sparseCounts()).unwrap();
+        assert_eq!(restored, h);
+        assert!(SizeHistogram::fromParts(0, 0, 0, &[(u16::MAX, 1)]).is_none());
+    }
+}
@@ -0,0 +1,161 @@
+/// Default number of index bits: 2^14 one-byte registers (16 KiB) and a
+/// standard error of about 0.8%.
+pub const DEFAULT_PRECISION: u8 = 14;
+const MIN_PRECISION: u8 = 4;
+const MAX_PRECISION: u8 = 18;
+
+/// HyperLogLog sketch of chunk fingerprints. It estimates the number of distinct
+/// fingerprints in constant memory, and two sketches of the same precision merge
+/// into the sketch of the union.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    precision: u8,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        assert!((MIN_PRECISION..=MAX_PRECISION).contains(&precision));
+        Self {
+            registers: vec![0; 1 << precision],
+            precision,
+        }
+    }
+
+    /// Restore a sketch from its registers. Returns `None` unless their number
+    /// is a supported power of two.
+    pub fn fromRegisters(registers: Vec<u8>) -> Option<Self> {
+        let precision = registers.len().trailing_zeros() as u8;
+        if !registers.len().is_power_of_two()
+            || !(MIN_PRECISION..=MAX_PRECISION).contains(&precision)
+        {
+            return None;
+        }
+        Some(Self {
+            registers,
+            precision,
+        })
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Add a fingerprint. Fingerprints are uniformly distributed already, so no
+    /// further hashing is needed. Bytes 8..16 are used because sampling by
+    /// fingerprint (`--sample-bits`) skews the leading bytes.
+    pub fn insert(&mut self, hash: &[u8; 32]) {
+        let word = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        let index = (word >> (64 - self.precision)) as usize;
+        let rest = word << self.precision;
+        let rank = (rest.leading_zeros() + 1).min(64 - self.precision as u32 + 1) as u8;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Merge another sketch into this one. Returns false, leaving this sketch
+    /// unchanged, if the precisions differ.
+    pub fn merge(&mut self, other: &HyperLogLog) -> bool {
+        if self.precision != other.precision {
+            return false;
+        }
+        for (a, &b) in self.registers.iter_mut().zip(&other.registers) {
+            *a = (*a).max(b);
+        }
+        true
+    }
+
+    /// Estimated number of distinct fingerprints.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 0.5f64.powi(r as i32)).sum();
+        let raw = alpha * m * m / sum;
+
+        // Small cardinalities are counted more exactly from the empty registers.
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Relative standard error of `estimate`.
+    pub fn standardError(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fingerprint(i: u64) -> [u8; 32] {
+        *blake3::hash(&i.to_le_bytes()).as_bytes()
+    }
+
+    fn assertClose(h: &HyperLogLog, exact: u64) {
+        let error = (h.estimate() - exact as f64).abs() / exact as f64;
+        assert!(
+            error < 3.0 * h.standardError(),
+            "{} vs {exact}",
+            h.estimate()
+        );
+    }
+
+    #[test]
+    fn testEstimate() {
+        let mut h = HyperLogLog::default();
+        assert_eq!(h.estimate(), 0.0);
+
+        for i in 0..100_000u64 {
+            h.insert(&fingerprint(i));
+            if i == 999 {
+                assertClose(&h, 1000);
+            }
+        }
+        assertClose(&h, 100_000);
+
+        // Duplicates do not change the sketch.
+        let before = h.clone();
+        for i in 0..1000u64 {
+            h.insert(&fingerprint(i));
+        }
+        assert_eq!(h, before);
+    }
+
+    #[test]
+    fn testMerge() {
+        let mut a = HyperLogLog::new(12);
+        let mut b = HyperLogLog::new(12);
+        for i in 0..30_000u64 {
+            a.insert(&fingerprint(i));
+        }
+        for i in 20_000..50_000u64 {
+            b.insert(&fingerprint(i));
+        }
+        assert!(a.merge(&b));
+        assertClose(&a, 50_000);
+
+        assert!(!a.merge(&HyperLogLog::new(13)));
+    }
+
+    #[test]
+    fn testFromRegisters() {
+        let mut h = HyperLogLog::new(10);
+        h.insert(&fingerprint(1));
+        let restored = HyperLogLog::fromRegisters(h.registers().to_vec()).unwrap();
+        assert_eq!(restored, h);
+        assert!(HyperLogLog::fromRegisters(vec![0; 1000]).is_none());
+        assert!(HyperLogLog::fromRegisters(vec![0; 4]).is_none());
+        assert!(HyperLogLog::fromRegisters(Vec::new()).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+/// Quote and escape a string for JSON output.
+pub fn jsonString(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// A value of a flat JSON object. Numbers keep their text, so that 64-bit
+/// integers are not rounded through `f64`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    NULL,
+    BOOL(bool),
+    NUMBER(String),
+    STRING(String),
+    ARRAY(Vec<JsonValue>),
+}
+
+impl JsonValue {
+    pub fn toJson(&self) -> String {
+        match self {
+            JsonValue::NULL => "null".to_string(),
+            JsonValue::BOOL(b) => b.to_string(),
+            JsonValue::NUMBER(n) => n.clone(),
+            JsonValue::STRING(s) => jsonString(s),
+            JsonValue::ARRAY(values) => {
+                let values: Vec<String> = values.iter().map(JsonValue::toJson).collect();
+                format!("[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skipWhitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skipWhitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at column {}", c, self.pos + 1))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(format!("invalid value at column {}", self.pos + 1))
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or("truncated \\u escape")?;
+        self.pos += 4;
+        u32::from_str_radix(digits, 16).map_err(|_| format!("invalid \\u escape {}", digits))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            let c = self.peek().ok_or("unterminated string")?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let e = self.peek().ok_or("unterminated string")?;
+                    self.pos += 1;
+                    result.push(match e {
+                        '"' | '\\' | '/' => e,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = self.hex4()?;
+                            // A surrogate pair encodes a character outside the BMP.
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            char::from_u32(code).ok_or("invalid \\u escape")?
+                        }
+                        _ => return Err(format!("invalid escape \\{}", e)),
+                    });
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skipWhitespace();
+        match self.peek() {
+            Some('"') => Ok(JsonValue::STRING(self.string()?)),
+            Some('n') => self.literal("null", JsonValue::NULL),
+            Some('t') => self.literal("true", JsonValue::BOOL(true)),
+            Some('f') => self.literal("false", JsonValue::BOOL(false)),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.skipWhitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::ARRAY(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skipWhitespace();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::ARRAY(values));
+                        }
+                        _ => return Err(format!("expected ',' or ']' at column {}", self.pos + 1)),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                let rest = &self.text[start..];
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(JsonValue::NUMBER(rest[..len].to_string()))
+            }
+            _ => Err(format!("invalid value at column {}", self.pos + 1)),
+        }
+    }
+}
+
+/// Parse one JSON object whose values are scalars or arrays, as written on a
+/// line of JSON Lines. Nested objects are not supported.
+pub fn parseObject(text: &str) -> Result<HashMap<String, JsonValue>, String> {
+    let mut p = JsonParser { text, pos: 0 };
+    let mut object = HashMap::new();
+    p.expect('{')?;
+    p.skipWhitespace();
+    if p.peek() == Some('}') {
+        p.pos += 1;
+    } else {
+        loop {
+            p.skipWhitespace();
+            let key = p.string()?;
+            p.expect(':')?;
+            object.insert(key, p.value()?);
+            p.skipWhitespace();
+            match p.peek() {
+                Some(',') => p.pos += 1,
+                Some('}') => {
+                    p.pos += 1;
+                    break;
+                }
+                _ => return Err(format!("expected ',' or '}}' at column {}", p.pos + 1)),
+            }
+        }
+    }
+    p.skipWhitespace();
+    if p.pos != text.len() {
+        return Err(format!("unexpected text at column {}", p.pos + 1));
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn testJsonString() {
+        assert_eq!(jsonString("a\"b\\c\n\u{1}é"), "\"a\\\"b\\\\c\\n\\u0001é\"");
+    }
+
+    #[test]
+    fn testParseObject() {
+        let object = parseObject(
+            r#" {"a": 18446744073709551615, "b": "x\"é😀", "c": [1, 2.5], "d": null, "e": true} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            object["a"],
+            JsonValue::NUMBER("18446744073709551615".to_string())
+        );
+        assert_eq!(object["b"], JsonValue::STRING("x\"é😀".to_string()));
+        assert_eq!(
+            object["c"],
+            JsonValue::ARRAY(vec![
+                JsonValue::NUMBER("1".to_string()),
+                JsonValue::NUMBER("2.5".to_string())
+            ])
+        );
+        assert_eq!(object["d"], JsonValue::NULL);
+        assert_eq!(object["e"], JsonValue::BOOL(true));
+        assert!(parseObject("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn testParseObjectRejectsInvalid() {
+        assert!(parseObject("{\"a\": 1").is_err());
+        assert!(parseObject("{\"a\": 1} x").is_err());
+        assert!(parseObject("{\"a\": nope}").is_err());
+        assert!(parseObject("[1]").is_err());
+    }
+}
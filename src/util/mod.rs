@@ -1,2 +1,7 @@
 pub mod arguments;
+pub mod csv;
 pub mod fileIO;
+pub mod histogram;
+pub mod hyperLogLog;
+pub mod json;
+pub mod traceFile;
@@ -0,0 +1,1164 @@
+//! On-disk trace format.
+//!
+//! A trace starts with [`MAGIC`] and a format version, followed by a sequence of
+//! records. Every record is a one byte kind, a little-endian `u32` payload length,
+//! the payload itself and, since version 2, a checksum of the three. Readers skip
+//! record kinds they do not know, and fields added to a record later are appended
+//! to its payload, so older traces (with shorter payloads) still decode with those
+//! fields set to their defaults.
+//!
+//! Appending a field to a record is the only compatible change; the `get…Or`
+//! methods of the decoder read such fields. Any other change to the layout,
+//! like data inside a chunk entry that only a new flag announces, bumps
+//! [`VERSION`], so that older readers refuse the trace instead of misreading
+//! it. Readers accept every version up to their own:
+//!
+//! 1. Header, file, chunk and summary records.
+//! 2. Chunk entries carry the compressed size and resemblance features of the
+//!    chunks flagged with them, and every record ends with a checksum. Version
+//!    1 chunk entries with these flags are rejected.
+//!
+//! The tracer writes one header record, chunk batches as workers finish their work
+//! units (only with `--log-fingerprints`), one file record per input file and a
+//! summary record last.
+
+use crate::trace::pattern::{PatternCounts, PatternStats};
+use crate::trace::resemblance::Features;
+use crate::trace::sampling::MAX_SAMPLE_BITS;
+use crate::util::histogram::SizeHistogram;
+use crate::util::hyperLogLog::HyperLogLog;
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    path::Path,
+};
+
+pub const MAGIC: &[u8; 8] = b"CHUNKIQ\0";
+pub const VERSION: u16 = 2;
+/// The first format version whose records carry a checksum.
+const CHECKSUM_VERSION: u16 = 2;
+/// The first format version whose chunk entries carry the flagged fields.
+const CHUNK_FIELDS_VERSION: u16 = 2;
+const CHECKSUM_LENGTH: usize = 4;
+
+const RECORD_HEADER: u8 = 1;
+const RECORD_FILE: u8 = 2;
+const RECORD_CHUNKS: u8 = 3;
+const RECORD_SUMMARY: u8 = 4;
+
+/// The chunk was already in the index when the tracer saw it.
+pub const CHUNK_DUPLICATE: u8 = 1 << 0;
+/// The chunk only contains zero bytes.
+pub const CHUNK_ZERO: u8 = 1 << 1;
+/// The chunk repeats a single non-zero byte.
+pub const CHUNK_REPEATED: u8 = 1 << 2;
+/// The chunk was compressed; its compressed size follows the digest.
+pub const CHUNK_COMPRESSED: u8 = 1 << 3;
+/// The chunk carries resemblance features, after the compressed size.
+pub const CHUNK_FEATURES: u8 = 1 << 4;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceHeader {
+    pub toolVersion: String,
+    pub chunker: String,
+    pub hasher: String,
+    pub digestLength: usize,
+    pub startTime: u64,
+    /// Compression applied to unique chunks, e.g. `zstd:3`, or `none`.
+    pub compression: String,
+    /// One in this many unique chunks was compressed.
+    pub compressSampleRate: u64,
+    /// Only chunks whose fingerprint starts with this many zero bits were kept.
+    pub sampleBits: u8,
+    /// Duplicates were not indexed, only counted through the sketch.
+    pub sketchOnly: bool,
+    /// Identifies the `--salt` of the hash function without revealing it.
+    /// Empty if the chunks were hashed without a salt.
+    pub saltVerifier: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileRecord {
+    pub fileIndex: u32,
+    /// The path exactly as it was passed to the tracer, without any re-encoding.
+    pub path: Vec<u8>,
+    pub size: u64,
+    /// In a sampled trace, the chunk and duplicate counts of a file are scaled
+    /// up by 2^`sampleBits`, unlike those of the summary.
+    pub chunkCount: u64,
+    pub dupCount: u64,
+    pub dupSize: u64,
+    /// Duplicate bytes of chunks that already occurred earlier in the same
+    /// file, even if their first occurrence is in another file.
+    pub internalDupSize: u64,
+    /// Detected by signature or extension, see `trace::fileType`.
+    pub fileType: String,
+    /// Average entropy of the file's chunks in bits per byte, weighted by chunk
+    /// length. Only measured with `--entropy`.
+    pub entropy: Option<f32>,
+    /// Digest of the whole file by the trace's hash function, padded like
+    /// chunk digests. Missing in traces written before it was recorded.
+    pub digest: Option<[u8; 32]>,
+}
+
+impl FileRecord {
+    pub fn displayPath(&self) -> String {
+        String::from_utf8_lossy(&self.path).into_owned()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkRecord {
+    pub fileIndex: u32,
+    pub offset: u64,
+    pub length: u32,
+    pub flags: u8,
+    pub digest: [u8; 32],
+    /// Only set with the `CHUNK_COMPRESSED` flag.
+    pub compressedSize: u32,
+    /// Only set with the `CHUNK_FEATURES` flag.
+    pub features: Option<Box<Features>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceSummary {
+    pub fileCount: u64,
+    pub totalSize: u64,
+    pub chunkCount: u64,
+    pub dupCount: u64,
+    pub dupSize: u64,
+    pub endTime: u64,
+    /// Lengths of the chunks seen for the first time.
+    pub uniqueSizes: SizeHistogram,
+    /// Lengths of the duplicate chunks.
+    pub dupSizes: SizeHistogram,
+    /// Zero-filled and single-byte chunks, included in the totals above.
+    pub patterns: PatternStats,
+    /// Unique chunks that were compressed, and their size before and after.
+    pub compressedCount: u64,
+    pub compressInputSize: u64,
+    pub compressOutputSize: u64,
+    /// Sketch of the sampled fingerprints, with `--sketch`.
+    pub sketch: Option<HyperLogLog>,
+}
+
+/// A fully decoded trace. Chunks are sorted by file and offset, i.e. in the
+/// order a sequential scan of the input would have produced them.
+#[derive(Debug, Default)]
+pub struct Trace {
+    /// Format version the trace was written with.
+    pub version: u16,
+    pub header: TraceHeader,
+    pub files: Vec<FileRecord>,
+    pub chunks: Vec<ChunkRecord>,
+    pub summary: Option<TraceSummary>,
+}
+
+impl Trace {
+    pub fn read(path: &Path) -> io::Result<Trace> {
+        let file = File::open(path)?;
+        readTrace(BufReader::new(file))
+    }
+
+    /// The chunk records of one file, in offset order.
+    pub fn chunksOf(&self, fileIndex: u32) -> &[ChunkRecord] {
+        let start = self.chunks.partition_point(|c| c.fileIndex < fileIndex);
+        let end = self.chunks.partition_point(|c| c.fileIndex <= fileIndex);
+        &self.chunks[start..end]
+    }
+}
+
+// ── Encoding ─────────────────────────────────────────────────────
+
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    fn putU8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn putU32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn putU64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn putBytes(&mut self, v: &[u8]) {
+        self.putU32(v.len() as u32);
+        self.buf.extend_from_slice(v);
+    }
+
+    fn putPatternCounts(&mut self, c: &PatternCounts) {
+        self.putU64(c.chunkCount);
+        self.putU64(c.size);
+        self.putU64(c.dupCount);
+        self.putU64(c.dupSize);
+    }
+
+    fn putHistogram(&mut self, h: &SizeHistogram) {
+        let sparse = h.sparseCounts();
+        self.putU64(h.sum);
+        self.putU64(h.min);
+        self.putU64(h.max);
+        self.putU32(sparse.len() as u32);
+        for (index, count) in sparse {
+            self.buf.extend_from_slice(&index.to_le_bytes());
+            self.putU64(count);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn isEmpty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(Error::new(ErrorKind::InvalidData, "Truncated trace record"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn getU8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn getU32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn getU64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn getBytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.getU32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn getString(&mut self) -> io::Result<String> {
+        String::from_utf8(self.getBytes()?)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid UTF-8 in trace record"))
+    }
+
+    fn getHistogram(&mut self) -> io::Result<SizeHistogram> {
+        let sum = self.getU64()?;
+        let min = self.getU64()?;
+        let max = self.getU64()?;
+        let len = self.getU32()? as usize;
+        let mut sparse = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            let index = u16::from_le_bytes(self.take(2)?.try_into().unwrap());
+            sparse.push((index, self.getU64()?));
+        }
+        SizeHistogram::fromParts(sum, min, max, &sparse)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid histogram bucket"))
+    }
+
+    fn getU8Or(&mut self, default: u8) -> io::Result<u8> {
+        if self.isEmpty() {
+            Ok(default)
+        } else {
+            self.getU8()
+        }
+    }
+
+    fn getU32Or(&mut self, default: u32) -> io::Result<u32> {
+        if self.isEmpty() {
+            Ok(default)
+        } else {
+            self.getU32()
+        }
+    }
+
+    fn getU64Or(&mut self, default: u64) -> io::Result<u64> {
+        if self.isEmpty() {
+            Ok(default)
+        } else {
+            self.getU64()
+        }
+    }
+
+    fn getHistogramOr(&mut self) -> io::Result<SizeHistogram> {
+        if self.isEmpty() {
+            Ok(SizeHistogram::new())
+        } else {
+            self.getHistogram()
+        }
+    }
+
+    fn getPatternCountsOr(&mut self) -> io::Result<PatternCounts> {
+        if self.isEmpty() {
+            return Ok(PatternCounts::default());
+        }
+        Ok(PatternCounts {
+            chunkCount: self.getU64()?,
+            size: self.getU64()?,
+            dupCount: self.getU64()?,
+            dupSize: self.getU64()?,
+        })
+    }
+
+    fn getBytesOr(&mut self) -> io::Result<Vec<u8>> {
+        if self.isEmpty() {
+            Ok(Vec::new())
+        } else {
+            self.getBytes()
+        }
+    }
+
+    fn getDigestOr(&mut self, digestLength: usize) -> io::Result<Option<[u8; 32]>> {
+        if self.isEmpty() {
+            return Ok(None);
+        }
+        let bytes = self.getBytes()?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        if bytes.len() != digestLength {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid file digest length"));
+        }
+        let mut digest = [0u8; 32];
+        digest[..digestLength].copy_from_slice(&bytes);
+        Ok(Some(digest))
+    }
+
+    fn getSketchOr(&mut self) -> io::Result<Option<HyperLogLog>> {
+        if self.isEmpty() {
+            return Ok(None);
+        }
+        let registers = self.getBytes()?;
+        if registers.is_empty() {
+            return Ok(None);
+        }
+        HyperLogLog::fromRegisters(registers)
+            .map(Some)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid sketch size"))
+    }
+
+    fn getStringOr(&mut self, default: &str) -> io::Result<String> {
+        if self.isEmpty() {
+            Ok(default.to_string())
+        } else {
+            self.getString()
+        }
+    }
+}
+
+fn encodeHeader(h: &TraceHeader) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.putBytes(h.toolVersion.as_bytes());
+    e.putBytes(h.chunker.as_bytes());
+    e.putBytes(h.hasher.as_bytes());
+    e.putU8(h.digestLength as u8);
+    e.putU64(h.startTime);
+    e.putBytes(h.compression.as_bytes());
+    e.putU64(h.compressSampleRate);
+    e.putU8(h.sampleBits);
+    e.putU8(h.sketchOnly as u8);
+    e.putBytes(&h.saltVerifier);
+    e.buf
+}
+
+fn decodeHeader(d: &mut Decoder) -> io::Result<TraceHeader> {
+    let h = TraceHeader {
+        toolVersion: d.getString()?,
+        chunker: d.getString()?,
+        hasher: d.getString()?,
+        digestLength: d.getU8()? as usize,
+        startTime: d.getU64()?,
+        compression: d.getStringOr("none")?,
+        compressSampleRate: d.getU64Or(1)?,
+        sampleBits: d.getU8Or(0)?,
+        sketchOnly: d.getU8Or(0)? != 0,
+        saltVerifier: d.getBytesOr()?,
+    };
+    if h.digestLength > 32 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported digest length {}", h.digestLength),
+        ));
+    }
+    // Reports shift by the sample bits and divide by the sample rate.
+    if h.sampleBits > MAX_SAMPLE_BITS {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported sample bits {}", h.sampleBits),
+        ));
+    }
+    if h.compressSampleRate == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid compression sample rate 0",
+        ));
+    }
+    Ok(h)
+}
+
+fn encodeFile(header: &TraceHeader, f: &FileRecord) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.putU32(f.fileIndex);
+    e.putBytes(&f.path);
+    e.putU64(f.size);
+    e.putU64(f.chunkCount);
+    e.putU64(f.dupCount);
+    e.putU64(f.dupSize);
+    e.putU64(f.internalDupSize);
+    e.putBytes(f.fileType.as_bytes());
+    // NaN marks a file whose entropy was not measured.
+    e.putU32(f.entropy.unwrap_or(f32::NAN).to_bits());
+    // An empty digest means it was not computed.
+    e.putBytes(f.digest.as_ref().map_or(&[][..], |d| &d[..header.digestLength]));
+    e.buf
+}
+
+fn decodeFile(header: &TraceHeader, d: &mut Decoder) -> io::Result<FileRecord> {
+    Ok(FileRecord {
+        fileIndex: d.getU32()?,
+        path: d.getBytes()?,
+        size: d.getU64()?,
+        chunkCount: d.getU64()?,
+        dupCount: d.getU64()?,
+        dupSize: d.getU64()?,
+        internalDupSize: d.getU64()?,
+        fileType: d.getStringOr("unknown")?,
+        entropy: Some(f32::from_bits(d.getU32Or(f32::NAN.to_bits())?)).filter(|e| !e.is_nan()),
+        digest: d.getDigestOr(header.digestLength)?,
+    })
+}
+
+fn encodeChunks(header: &TraceHeader, fileIndex: u32, chunks: &[ChunkRecord]) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.putU32(fileIndex);
+    e.putU32(chunks.len() as u32);
+    for c in chunks {
+        debug_assert_eq!(c.fileIndex, fileIndex);
+        debug_assert_eq!(c.features.is_some(), c.flags & CHUNK_FEATURES != 0);
+        e.putU64(c.offset);
+        e.putU32(c.length);
+        e.putU8(c.flags);
+        e.buf.extend_from_slice(&c.digest[..header.digestLength]);
+        if c.flags & CHUNK_COMPRESSED != 0 {
+            e.putU32(c.compressedSize);
+        }
+        if let Some(ref features) = c.features {
+            features.iter().for_each(|&f| e.putU32(f));
+        }
+    }
+    e.buf
+}
+
+fn decodeChunks(
+    header: &TraceHeader,
+    version: u16,
+    d: &mut Decoder,
+    out: &mut Vec<ChunkRecord>,
+) -> io::Result<()> {
+    let fileIndex = d.getU32()?;
+    let count = d.getU32()? as usize;
+    out.reserve(count);
+    for _ in 0..count {
+        let mut c = ChunkRecord {
+            fileIndex,
+            offset: d.getU64()?,
+            length: d.getU32()?,
+            flags: d.getU8()?,
+            digest: [0u8; 32],
+            compressedSize: 0,
+            features: None,
+        };
+        c.digest[..header.digestLength].copy_from_slice(d.take(header.digestLength)?);
+        if version < CHUNK_FIELDS_VERSION && c.flags & (CHUNK_COMPRESSED | CHUNK_FEATURES) != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Chunk entry with flags {:#x} in a version {} trace",
+                    c.flags, version
+                ),
+            ));
+        }
+        if c.flags & CHUNK_COMPRESSED != 0 {
+            c.compressedSize = d.getU32()?;
+        }
+        if c.flags & CHUNK_FEATURES != 0 {
+            let mut features = Box::<Features>::default();
+            for f in features.iter_mut() {
+                *f = d.getU32()?;
+            }
+            c.features = Some(features);
+        }
+        out.push(c);
+    }
+    Ok(())
+}
+
+fn encodeSummary(s: &TraceSummary) -> Vec<u8> {
+    let mut e = Encoder::new();
+    e.putU64(s.fileCount);
+    e.putU64(s.totalSize);
+    e.putU64(s.chunkCount);
+    e.putU64(s.dupCount);
+    e.putU64(s.dupSize);
+    e.putU64(s.endTime);
+    e.putHistogram(&s.uniqueSizes);
+    e.putHistogram(&s.dupSizes);
+    e.putPatternCounts(&s.patterns.zero);
+    e.putPatternCounts(&s.patterns.repeated);
+    e.putU64(s.compressedCount);
+    e.putU64(s.compressInputSize);
+    e.putU64(s.compressOutputSize);
+    // An empty register array means there is no sketch.
+    e.putBytes(s.sketch.as_ref().map_or(&[][..], |h| h.registers()));
+    e.buf
+}
+
+fn decodeSummary(d: &mut Decoder) -> io::Result<TraceSummary> {
+    Ok(TraceSummary {
+        fileCount: d.getU64()?,
+        totalSize: d.getU64()?,
+        chunkCount: d.getU64()?,
+        dupCount: d.getU64()?,
+        dupSize: d.getU64()?,
+        endTime: d.getU64()?,
+        uniqueSizes: d.getHistogramOr()?,
+        dupSizes: d.getHistogramOr()?,
+        patterns: PatternStats {
+            zero: d.getPatternCountsOr()?,
+            repeated: d.getPatternCountsOr()?,
+        },
+        compressedCount: d.getU64Or(0)?,
+        compressInputSize: d.getU64Or(0)?,
+        compressOutputSize: d.getU64Or(0)?,
+        sketch: d.getSketchOr()?,
+    })
+}
+
+// ── Writing ──────────────────────────────────────────────────────
+
+pub struct TraceWriter<W: Write> {
+    out: W,
+    header: TraceHeader,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(path: &Path, header: TraceHeader) -> io::Result<Self> {
+        TraceWriter::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, header: TraceHeader) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        let mut writer = Self { out, header };
+        let payload = encodeHeader(&writer.header);
+        writer.writeRecord(RECORD_HEADER, &payload)?;
+        Ok(writer)
+    }
+
+    fn writeRecord(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+        self.out.write_all(&[kind])?;
+        self.out.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.out.write_all(payload)?;
+        self.out.write_all(&checksum(kind, payload))
+    }
+
+    pub fn writeFile(&mut self, file: &FileRecord) -> io::Result<()> {
+        self.writeRecord(RECORD_FILE, &encodeFile(&self.header, file))
+    }
+
+    /// Write the chunks of one work unit. All chunks must belong to `fileIndex`.
+    pub fn writeChunks(&mut self, fileIndex: u32, chunks: &[ChunkRecord]) -> io::Result<()> {
+        let payload = encodeChunks(&self.header, fileIndex, chunks);
+        self.writeRecord(RECORD_CHUNKS, &payload)
+    }
+
+    /// Write the summary record and flush. Nothing may be written afterwards.
+    pub fn finish(mut self, summary: &TraceSummary) -> io::Result<W> {
+        self.writeRecord(RECORD_SUMMARY, &encodeSummary(summary))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// ── Reading ──────────────────────────────────────────────────────
+
+/// Truncated BLAKE3 hash of a record's kind, length and payload.
+fn checksum(kind: u8, payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[kind]);
+    hasher.update(&(payload.len() as u32).to_le_bytes());
+    hasher.update(payload);
+    hasher.finalize().as_bytes()[..CHECKSUM_LENGTH]
+        .try_into()
+        .unwrap()
+}
+
+/// The trace ends inside a record, e.g. because the tracer crashed.
+fn truncated(e: Error) -> Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => Error::new(ErrorKind::UnexpectedEof, "Truncated trace record"),
+        _ => e,
+    }
+}
+
+/// Reads the raw records of a trace and checks their checksums.
+struct RecordReader<R: Read> {
+    input: R,
+    version: u16,
+    /// Byte offset of the next record.
+    offset: u64,
+}
+
+impl<R: Read> RecordReader<R> {
+    fn new(mut input: R) -> io::Result<Self> {
+        let notATrace = || Error::new(ErrorKind::InvalidData, "Not a chunkIQ trace");
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic).map_err(|_| notATrace())?;
+        if &magic != MAGIC {
+            return Err(notATrace());
+        }
+        let mut version = [0u8; 2];
+        input.read_exact(&mut version).map_err(|_| notATrace())?;
+        let version = u16::from_le_bytes(version);
+        if version == 0 || version > VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported trace version {}", version),
+            ));
+        }
+        Ok(Self {
+            input,
+            version,
+            offset: (MAGIC.len() + 2) as u64,
+        })
+    }
+
+    /// Read the next record into `payload` and return its kind, or `None` at
+    /// the end of the trace.
+    fn next(&mut self, payload: &mut Vec<u8>) -> io::Result<Option<u8>> {
+        let mut kind = [0u8; 1];
+        match self.input.read_exact(&mut kind) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut len = [0u8; 4];
+        self.input.read_exact(&mut len).map_err(truncated)?;
+        // The length is not trusted until the payload is read, so the buffer
+        // only grows with the data actually there.
+        let len = u32::from_le_bytes(len) as u64;
+        payload.clear();
+        (&mut self.input).take(len).read_to_end(payload)?;
+        if (payload.len() as u64) < len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Trace record of {} bytes is cut off", len),
+            ));
+        }
+        self.offset += 1 + 4 + len;
+
+        if self.version >= CHECKSUM_VERSION {
+            let mut stored = [0u8; CHECKSUM_LENGTH];
+            self.input.read_exact(&mut stored).map_err(truncated)?;
+            self.offset += CHECKSUM_LENGTH as u64;
+            if stored != checksum(kind[0], payload) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Checksum mismatch in trace record",
+                ));
+            }
+        }
+        Ok(Some(kind[0]))
+    }
+}
+
+pub fn readTrace<R: Read>(input: R) -> io::Result<Trace> {
+    let mut records = RecordReader::new(input)?;
+    let mut trace = Trace {
+        version: records.version,
+        ..Default::default()
+    };
+    let mut seenHeader = false;
+    let mut payload: Vec<u8> = Vec::new();
+
+    while let Some(kind) = records.next(&mut payload)? {
+        let mut d = Decoder::new(&payload);
+        match kind {
+            RECORD_HEADER => {
+                trace.header = decodeHeader(&mut d)?;
+                seenHeader = true;
+            }
+            _ if !seenHeader => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Trace record before the header",
+                ));
+            }
+            RECORD_FILE => trace.files.push(decodeFile(&trace.header, &mut d)?),
+            RECORD_CHUNKS => decodeChunks(&trace.header, trace.version, &mut d, &mut trace.chunks)?,
+            RECORD_SUMMARY => trace.summary = Some(decodeSummary(&mut d)?),
+            _ => {}
+        }
+    }
+
+    if !seenHeader {
+        return Err(Error::new(ErrorKind::InvalidData, "Trace has no header"));
+    }
+
+    trace.files.sort_by_key(|f| f.fileIndex);
+    trace.chunks.sort_by_key(|c| (c.fileIndex, c.offset));
+
+    Ok(trace)
+}
+
+// ── Verification ─────────────────────────────────────────────────
+
+/// Outcome of checking a trace record by record.
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub version: u16,
+    /// Records that were read in full.
+    pub records: u64,
+    /// Bytes up to the end of the last record read in full.
+    pub validBytes: u64,
+    /// The records carry checksums, i.e. the trace has version 2 or later.
+    pub checksummed: bool,
+    /// The summary record, which the tracer writes last, is present.
+    pub complete: bool,
+    pub problems: Vec<String>,
+}
+
+impl Verification {
+    pub fn isValid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check the structure of a trace: every record is complete, matches its
+/// checksum and decodes, the header comes first, the summary last, and the
+/// records agree with each other. Unlike [`readTrace`], it does not stop at
+/// the first problem as long as the record boundaries can be trusted.
+pub fn verifyTrace<R: Read>(input: R) -> Verification {
+    let mut v = Verification::default();
+    let mut records = match RecordReader::new(input) {
+        Ok(records) => records,
+        Err(e) => {
+            v.problems.push(e.to_string());
+            return v;
+        }
+    };
+    v.version = records.version;
+    v.checksummed = records.version >= CHECKSUM_VERSION;
+    v.validBytes = records.offset;
+
+    let mut header: Option<TraceHeader> = None;
+    let mut summary: Option<TraceSummary> = None;
+    let mut files: Vec<FileRecord> = Vec::new();
+    let mut chunks: Vec<ChunkRecord> = Vec::new();
+    let mut payload: Vec<u8> = Vec::new();
+
+    loop {
+        let start = records.offset;
+        let kind = match records.next(&mut payload) {
+            Ok(Some(kind)) => kind,
+            Ok(None) => break,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                v.problems.push(format!(
+                    "truncated record at byte {}, the trace was cut off",
+                    start
+                ));
+                break;
+            }
+            Err(e) => {
+                v.problems
+                    .push(format!("record {} at byte {}: {}", v.records, start, e));
+                v.records += 1;
+                v.validBytes = records.offset;
+                continue;
+            }
+        };
+        let index = v.records;
+        v.records += 1;
+        v.validBytes = records.offset;
+
+        if summary.is_some() {
+            v.problems.push(format!(
+                "record {} at byte {} follows the summary",
+                index, start
+            ));
+        }
+        let mut d = Decoder::new(&payload);
+        let decoded = match (kind, &header) {
+            (RECORD_HEADER, None) => decodeHeader(&mut d).map(|h| header = Some(h)),
+            (RECORD_HEADER, Some(_)) => {
+                v.problems.push(format!(
+                    "record {} at byte {} is a second header",
+                    index, start
+                ));
+                Ok(())
+            }
+            (_, None) => {
+                v.problems.push(format!(
+                    "record {} at byte {} comes before the header",
+                    index, start
+                ));
+                break;
+            }
+            (RECORD_FILE, Some(h)) => decodeFile(h, &mut d).map(|f| files.push(f)),
+            (RECORD_CHUNKS, Some(h)) => decodeChunks(h, records.version, &mut d, &mut chunks),
+            (RECORD_SUMMARY, Some(_)) => decodeSummary(&mut d).map(|s| summary = Some(s)),
+            _ => Ok(()),
+        };
+        if let Err(e) = decoded {
+            v.problems
+                .push(format!("record {} at byte {}: {}", index, start, e));
+        }
+    }
+
+    if header.is_none() && v.records == 0 {
+        v.problems.push("the trace has no header".to_string());
+    }
+    v.complete = summary.is_some();
+    if !v.complete {
+        v.problems
+            .push("no summary record, the tracer did not finish".to_string());
+    }
+    v.problems
+        .extend(crossCheck(&files, &chunks, summary.as_ref()));
+    v
+}
+
+/// Problems between records that decoded fine on their own.
+fn crossCheck(
+    files: &[FileRecord],
+    chunks: &[ChunkRecord],
+    summary: Option<&TraceSummary>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut indexes: Vec<u32> = files.iter().map(|f| f.fileIndex).collect();
+    indexes.sort_unstable();
+    if let Some(w) = indexes.windows(2).find(|w| w[0] == w[1]) {
+        problems.push(format!("file index {} is used twice", w[0]));
+    }
+    let orphans = chunks
+        .iter()
+        .filter(|c| indexes.binary_search(&c.fileIndex).is_err())
+        .count();
+    if orphans > 0 {
+        problems.push(format!("{} chunk records belong to no file", orphans));
+    }
+
+    if let Some(s) = summary {
+        if s.fileCount != files.len() as u64 {
+            problems.push(format!(
+                "the summary counts {} files, but there are {} file records",
+                s.fileCount,
+                files.len()
+            ));
+        }
+        let totalSize: u64 = files.iter().map(|f| f.size).sum();
+        if s.totalSize != totalSize {
+            problems.push(format!(
+                "the summary counts {} bytes, but the file records {}",
+                s.totalSize, totalSize
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn createHeader() -> TraceHeader {
+        TraceHeader {
+            toolVersion: "0.1.3".to_string(),
+            chunker: "CDC8K".to_string(),
+            hasher: "MD5".to_string(),
+            digestLength: 16,
+            startTime: 1_700_000_000,
+            compression: "zstd:3".to_string(),
+            compressSampleRate: 4,
+            sampleBits: 2,
+            sketchOnly: true,
+            saltVerifier: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        }
+    }
+
+    fn createChunk(fileIndex: u32, offset: u64, fill: u8) -> ChunkRecord {
+        let mut digest = [0u8; 32];
+        digest[..16].fill(fill);
+        ChunkRecord {
+            fileIndex,
+            offset,
+            length: 4096,
+            flags: 0,
+            digest,
+            compressedSize: 0,
+            features: None,
+        }
+    }
+
+    #[test]
+    fn testRoundTrip() {
+        let header = createHeader();
+        let file = FileRecord {
+            fileIndex: 0,
+            path: b"/data/\xffodd name".to_vec(),
+            size: 8192,
+            chunkCount: 2,
+            dupCount: 1,
+            dupSize: 4096,
+            internalDupSize: 4096,
+            fileType: "text".to_string(),
+            entropy: Some(4.5),
+            // MD5 digests are padded to 32 bytes.
+            digest: Some(std::array::from_fn(|i| if i < 16 { 9 } else { 0 })),
+        };
+        let mut summary = TraceSummary {
+            fileCount: 1,
+            totalSize: 8192,
+            chunkCount: 2,
+            dupCount: 1,
+            dupSize: 4096,
+            endTime: 1_700_000_060,
+            ..Default::default()
+        };
+        summary.uniqueSizes.record(4096);
+        summary.dupSizes.record(4096);
+        summary.patterns.zero.record(4096, true);
+        let mut sketch = HyperLogLog::new(8);
+        sketch.insert(&[3; 32]);
+        summary.sketch = Some(sketch);
+        let mut unique = createChunk(0, 0, 7);
+        unique.flags = CHUNK_COMPRESSED | CHUNK_FEATURES;
+        unique.compressedSize = 1234;
+        unique.features = Some(Box::new(std::array::from_fn(|i| i as u32 * 1000)));
+        let mut dup = createChunk(0, 4096, 7);
+        dup.flags = CHUNK_DUPLICATE | CHUNK_ZERO;
+
+        let mut writer = TraceWriter::new(Vec::new(), header.clone()).unwrap();
+        // Work units may finish out of order; the reader restores file order.
+        writer.writeChunks(0, &[dup.clone()]).unwrap();
+        writer.writeChunks(0, &[unique.clone()]).unwrap();
+        writer.writeFile(&file).unwrap();
+        let bytes = writer.finish(&summary).unwrap();
+
+        let trace = readTrace(bytes.as_slice()).unwrap();
+        assert_eq!(trace.header, header);
+        assert_eq!(trace.files, vec![file]);
+        assert_eq!(trace.chunks, vec![unique, dup]);
+        assert_eq!(trace.summary, Some(summary));
+    }
+
+    #[test]
+    fn testChunksOf() {
+        let mut writer = TraceWriter::new(Vec::new(), createHeader()).unwrap();
+        writer.writeChunks(2, &[createChunk(2, 0, 1)]).unwrap();
+        writer
+            .writeChunks(0, &[createChunk(0, 0, 2), createChunk(0, 4096, 3)])
+            .unwrap();
+        let bytes = writer.finish(&TraceSummary::default()).unwrap();
+
+        let trace = readTrace(bytes.as_slice()).unwrap();
+        assert_eq!(trace.chunksOf(0).len(), 2);
+        assert!(trace.chunksOf(1).is_empty());
+        assert_eq!(trace.chunksOf(2), &[createChunk(2, 0, 1)]);
+    }
+
+    #[test]
+    fn testDecodesOlderFileRecord() {
+        let file = FileRecord {
+            fileIndex: 3,
+            path: b"/data/file".to_vec(),
+            fileType: "text".to_string(),
+            ..Default::default()
+        };
+        let header = createHeader();
+        let payload = encodeFile(&header, &file);
+        let decoded = decodeFile(&header, &mut Decoder::new(&payload)).unwrap();
+        assert_eq!(decoded.entropy, None);
+        assert_eq!(decoded.digest, None);
+
+        // Drop the digest, entropy and file type, which were appended to the
+        // record later.
+        let withoutDigest = &payload[..payload.len() - 4];
+        let withoutEntropy = &withoutDigest[..withoutDigest.len() - 4];
+        let decoded = decodeFile(&header, &mut Decoder::new(withoutEntropy)).unwrap();
+        assert_eq!(decoded.fileType, "text");
+        assert_eq!(decoded.entropy, None);
+
+        let withoutType = &withoutEntropy[..withoutEntropy.len() - 4 - file.fileType.len()];
+        let decoded = decodeFile(&header, &mut Decoder::new(withoutType)).unwrap();
+        assert_eq!(decoded.path, file.path);
+        assert_eq!(decoded.fileType, "unknown");
+    }
+
+    #[test]
+    fn testDecodesOlderHeader() {
+        let header = createHeader();
+        // Drop the compression, sampling, sketch and salt fields, which were
+        // appended to the record later.
+        let mut payload = encodeHeader(&header);
+        payload.truncate(payload.len() - 4 - header.saltVerifier.len());
+        let decoded = decodeHeader(&mut Decoder::new(&payload)).unwrap();
+        assert!(decoded.sketchOnly);
+        assert!(decoded.saltVerifier.is_empty());
+
+        payload.truncate(payload.len() - 1 - 1 - 8 - 4 - header.compression.len());
+
+        let decoded = decodeHeader(&mut Decoder::new(&payload)).unwrap();
+        assert_eq!(decoded.startTime, header.startTime);
+        assert_eq!(decoded.compression, "none");
+        assert_eq!(decoded.compressSampleRate, 1);
+        assert_eq!(decoded.sampleBits, 0);
+        assert!(!decoded.sketchOnly);
+
+        let payload = encodeHeader(&TraceHeader {
+            sampleBits: 70,
+            ..createHeader()
+        });
+        assert!(decodeHeader(&mut Decoder::new(&payload)).is_err());
+        let payload = encodeHeader(&TraceHeader {
+            compressSampleRate: 0,
+            ..createHeader()
+        });
+        assert!(decodeHeader(&mut Decoder::new(&payload)).is_err());
+    }
+
+    #[test]
+    fn testRejectsForeignFile() {
+        let err = readTrace(b"not a trace at all".as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn testRejectsTruncatedRecord() {
+        let writer = TraceWriter::new(Vec::new(), createHeader()).unwrap();
+        let bytes = writer.finish(&TraceSummary::default()).unwrap();
+        assert!(readTrace(&bytes[..bytes.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn testSkipsUnknownRecords() {
+        let mut writer = TraceWriter::new(Vec::new(), createHeader()).unwrap();
+        writer.writeRecord(0x7f, b"from the future").unwrap();
+        let bytes = writer.finish(&TraceSummary::default()).unwrap();
+        let trace = readTrace(bytes.as_slice()).unwrap();
+        assert!(trace.summary.is_some());
+    }
+
+    #[test]
+    fn testDetectsCorruption() {
+        let mut writer = TraceWriter::new(Vec::new(), createHeader()).unwrap();
+        let chunksAt = writer.out.len();
+        writer.writeChunks(0, &[createChunk(0, 0, 1)]).unwrap();
+        let summary = TraceSummary::default();
+        let bytes = writer.finish(&summary).unwrap();
+        let verification = verifyTrace(bytes.as_slice());
+        assert!(verification.checksummed);
+        assert!(verification.complete);
+        // The chunk belongs to a file without a record.
+        assert_eq!(
+            verification.problems,
+            vec!["1 chunk records belong to no file".to_string()]
+        );
+
+        // Flip a bit in the payload of the chunk record.
+        let mut corrupt = bytes.clone();
+        corrupt[chunksAt + 5 + 12] ^= 1;
+        assert!(readTrace(corrupt.as_slice()).is_err());
+        let verification = verifyTrace(corrupt.as_slice());
+        assert!(verification.problems[0].contains("Checksum mismatch"));
+        assert!(verification.complete);
+    }
+
+    #[test]
+    fn testDetectsTruncation() {
+        let mut writer = TraceWriter::new(Vec::new(), createHeader()).unwrap();
+        writer
+            .writeFile(&FileRecord {
+                size: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        let bytes = writer.finish(&TraceSummary::default()).unwrap();
+        let verification = verifyTrace(bytes.as_slice());
+        assert_eq!(verification.records, 3);
+        assert_eq!(verification.validBytes, bytes.len() as u64);
+        assert_eq!(verification.problems.len(), 2);
+
+        // Cut off inside the summary, as by a crash of the tracer.
+        let verification = verifyTrace(&bytes[..bytes.len() - 10]);
+        assert_eq!(verification.records, 2);
+        assert!(!verification.complete);
+        assert!(verification.problems[0].starts_with("truncated record at byte"));
+        assert_eq!(
+            verification.problems[1],
+            "no summary record, the tracer did not finish"
+        );
+
+        // A corrupt length must not make the reader allocate it.
+        let mut corrupt = bytes.clone();
+        let start = MAGIC.len() + 2 + 1;
+        corrupt[start..start + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let verification = verifyTrace(corrupt.as_slice());
+        assert_eq!(verification.records, 0);
+        assert!(verification.problems[0].starts_with("truncated record at byte 10"));
+        assert!(readTrace(corrupt.as_slice()).is_err());
+    }
+
+    #[test]
+    fn testReadsVersion1() {
+        // Version 1 records have no checksum.
+        let payload = encodeHeader(&createHeader());
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.push(RECORD_HEADER);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        let trace = readTrace(bytes.as_slice()).unwrap();
+        assert_eq!(trace.version, 1);
+        assert_eq!(trace.header, createHeader());
+        assert!(!verifyTrace(bytes.as_slice()).checksummed);
+
+        // Version 1 chunk entries have no compressed size or features.
+        let chunk = ChunkRecord {
+            flags: CHUNK_COMPRESSED,
+            compressedSize: 100,
+            ..Default::default()
+        };
+        let payload = encodeChunks(&createHeader(), 0, &[chunk]);
+        bytes.push(RECORD_CHUNKS);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        let err = readTrace(bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Chunk entry with flags 0x8 in a version 1 trace"
+        );
+        assert!(!verifyTrace(bytes.as_slice()).isValid());
+    }
+}